@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+use tracker::{Error, Result};
+
+/// Opens `text` in `$VISUAL` or `$EDITOR`, `vi` when neither is set, and
+/// returns the saved text. The editor may hold arguments, e.g. `code --wait`.
+pub fn edit_text(text: &str, name: &str) -> Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let path = env::temp_dir().join(format!("tracker-{}-{}.txt", name, std::process::id()));
+    fs::write(&path, text)?;
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    let edited = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path).map_err(Error::from),
+        Ok(status) => Err(Error::Io(format!("{} exited with {}", editor, status))),
+        Err(error) => Err(Error::from(error)),
+    };
+    fs::remove_file(&path)?;
+    edited
+}
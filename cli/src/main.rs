@@ -1,12 +1,436 @@
+mod editor;
+mod output;
+mod render;
+mod table;
+
+use crate::editor::edit_text;
+use crate::output::{
+    write_context, write_invoice, write_projects, write_report, write_track, write_tracks,
+    write_workspaces, Output,
+};
+use crate::render::{
+    render_projects, render_report, render_status, render_tracks, render_workspaces, Column,
+    ListOptions, STATUS_FORMAT,
+};
+use chrono::{Local, Utc};
 use clap::{Arg, ArgMatches};
 use clap_nested::{Command, Commander};
-use tracker;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal};
+use std::{env, process};
+use tracker::directory::{self, DirectoryDefaults};
+use tracker::export::ExportFormat;
+use tracker::import::{ImportDefaults, ImportFormat};
+use tracker::invoice::{InvoiceFormat, InvoiceRequest, LineItems};
+use tracker::migrations;
+use tracker::model::{Project, Rate, Track, Workspace};
+use tracker::parser::{parse_duration, parse_period, parse_rate, parse_tax, parse_time};
+use tracker::report::GroupBy;
+use tracker::repository::{Order, TrackQuery};
+use tracker::rounding::{Rounding, RoundingMode, RoundingScope};
+use tracker::service::{
+    Billing, InvoiceService, Overlap, ProjectService, TrackChanges, TrackEnd, TrackService,
+};
+use tracker::{Config, Error};
+
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::Storage(_) => 3,
+        Error::NotFound(_) => 4,
+        Error::NoActiveTrack => 6,
+        Error::InvalidTimestamp(_) => 7,
+        Error::Config(_) => 8,
+        Error::UnsupportedSchema(_, _) => 9,
+        Error::AmbiguousId(_) => 10,
+        Error::InvalidInterval(_, _) => 11,
+        Error::Overlap(_) => 12,
+        Error::InvalidDuration(_) => 13,
+        Error::AlreadyPaused(_) => 14,
+        Error::Io(_) => 15,
+        Error::Import(_) => 16,
+        Error::UnknownWorkspace(_) => 17,
+        Error::UnknownProject(_, _) => 18,
+        Error::AlreadyExists(_) => 19,
+        Error::Archived(_) => 20,
+        Error::NoContext(_) => 21,
+        Error::InvalidRate(_) => 22,
+        Error::MissingRate(_) => 23,
+        Error::Template(_) => 24,
+        Error::NothingToInvoice(_) => 25,
+        Error::InvalidTax(_) => 26,
+        Error::NotPaused(_) => 27,
+    }
+}
+
+fn message(error: &Error) -> String {
+    match error {
+        Error::Storage(message) => format!("could not access the tracks database: {}", message),
+        Error::NotFound(id) => format!("no track found with id {}", id),
+        Error::NoActiveTrack => String::from("no track is running"),
+        Error::InvalidTimestamp(value) => format!("\"{}\" is not a valid timestamp", value),
+        Error::Config(message) => format!("invalid configuration: {}", message),
+        Error::UnsupportedSchema(found, supported) => format!(
+            "the database uses schema version {} but this tracker only supports up to {}, please upgrade",
+            found, supported
+        ),
+        Error::AmbiguousId(prefix) => format!(
+            "several tracks start with \"{}\", use a longer id",
+            prefix
+        ),
+        Error::InvalidInterval(start, end) => format!(
+            "the end {} must be after the start {}",
+            end.with_timezone(&Local),
+            start.with_timezone(&Local)
+        ),
+        Error::Overlap(tracks) => {
+            let options = ListOptions {
+                columns: vec![
+                    Column::Id,
+                    Column::Name,
+                    Column::Project,
+                    Column::Start,
+                    Column::End,
+                ],
+                width: None,
+                color: false,
+            };
+            let mut message = String::from("the new track overlaps:\n");
+            message.push_str(&render_tracks(tracks, &options, Utc::now()));
+            message.push_str("use --overlap trim to shorten them or --overlap allow to keep both");
+            message
+        }
+        Error::InvalidDuration(value) => format!("\"{}\" is not a valid duration", value),
+        Error::AlreadyPaused(name) => format!("\"{}\" is already paused", name),
+        Error::Io(message) => format!("could not read or write: {}", message),
+        Error::Import(message) => format!("could not import: {}", message),
+        Error::UnknownWorkspace(name) => format!(
+            "there is no workspace \"{}\", add it with `tracker workspace add`",
+            name
+        ),
+        Error::UnknownProject(name, workspace) => format!(
+            "there is no project \"{}\" in workspace \"{}\", add it with `tracker project add`",
+            name, workspace
+        ),
+        Error::AlreadyExists(name) => format!("{} already exists", name),
+        Error::Archived(name) => format!("{} is archived", name),
+        Error::NoContext(what) => format!(
+            "no {} given and none in use, pass -{} or run `tracker use`",
+            what,
+            &what[..1]
+        ),
+        Error::InvalidRate(value) => format!(
+            "\"{}\" is not a valid rate, write an amount and a currency like \"95.50 EUR\"",
+            value
+        ),
+        Error::MissingRate(what) => format!(
+            "there is no hourly rate for {}, set one with `tracker project rate` or `tracker workspace rate`",
+            what
+        ),
+        Error::Template(message) => format!("invalid invoice template: {}", message),
+        Error::NothingToInvoice(client) => format!(
+            "no billable tracks of \"{}\" left to invoice in this period",
+            client
+        ),
+        Error::InvalidTax(value) => format!(
+            "\"{}\" is not a valid tax, write a percentage like \"20%\" or \"VAT 20%\"",
+            value
+        ),
+        Error::NotPaused(name) => format!("\"{}\" is not paused", name),
+    }
+}
+
+fn exit_on_error<T>(result: tracker::Result<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("error: {}", message(&error));
+            process::exit(exit_code(&error));
+        }
+    }
+}
+
+fn config(matches: &ArgMatches<'_>) -> Config {
+    let mut builder = Config::builder();
+    if let Some(path) = matches.value_of("db") {
+        builder = builder.database_path(path);
+    }
+    exit_on_error(builder.build())
+}
+
+fn init(matches: &ArgMatches<'_>) -> TrackService {
+    exit_on_error(tracker::init(&config(matches)))
+}
+
+fn init_projects(matches: &ArgMatches<'_>) -> ProjectService {
+    exit_on_error(tracker::init_projects(&config(matches)))
+}
+
+fn init_invoices(matches: &ArgMatches<'_>) -> InvoiceService {
+    exit_on_error(tracker::init_invoices(&config(matches)))
+}
+
+fn rounding_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("round")
+            .long("round")
+            .takes_value(true)
+            .value_name("DURATION")
+            .help("Round durations to this increment, e.g. 15m"),
+        Arg::with_name("round-mode")
+            .long("round-mode")
+            .takes_value(true)
+            .possible_values(&["up", "down", "nearest"])
+            .help("Which way to round, nearest by default"),
+        Arg::with_name("round-per")
+            .long("round-per")
+            .takes_value(true)
+            .possible_values(&["entry", "day"])
+            .help("Round each track, or the time of each project in a day"),
+        Arg::with_name("min-billable")
+            .long("min-billable")
+            .takes_value(true)
+            .value_name("DURATION")
+            .help("Bill at least this much for each rounded duration"),
+        Arg::with_name("no-rounding")
+            .long("no-rounding")
+            .conflicts_with_all(&["round", "round-mode", "round-per", "min-billable"])
+            .help("Ignore the rounding of the configuration file"),
+    ]
+}
+
+/// The rounding of the configuration file, changed by the rounding flags.
+fn rounding(matches: &ArgMatches<'_>) -> Option<Rounding> {
+    if matches.is_present("no-rounding") {
+        return None;
+    }
+    let mut rounding = config(matches).rounding.unwrap_or_default();
+    if let Some(increment) = matches.value_of("round") {
+        rounding.increment = exit_on_error(parse_duration(increment));
+    }
+    match matches.value_of("round-mode") {
+        Some("up") => rounding.mode = RoundingMode::Up,
+        Some("down") => rounding.mode = RoundingMode::Down,
+        Some(_) => rounding.mode = RoundingMode::Nearest,
+        None => {}
+    }
+    match matches.value_of("round-per") {
+        Some("day") => rounding.per = RoundingScope::Day,
+        Some(_) => rounding.per = RoundingScope::Entry,
+        None => {}
+    }
+    if let Some(minimum) = matches.value_of("min-billable") {
+        rounding.minimum = exit_on_error(parse_duration(minimum));
+    }
+    Some(rounding).filter(Rounding::is_active)
+}
+
+fn validate_number(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("\"{}\" is not a number", value))
+}
+
+fn tags(matches: &ArgMatches<'_>) -> Vec<String> {
+    matches
+        .values_of("tag")
+        .map_or(vec![], |tags| tags.map(String::from).collect())
+}
+
+fn rate(matches: &ArgMatches<'_>) -> Option<Rate> {
+    matches
+        .value_of("rate")
+        .map(|rate| exit_on_error(parse_rate(rate)))
+}
+
+/// The rate and billable flag given with `--rate`, `--clear-rate`,
+/// `--billable` and `--non-billable`, as changes of a track.
+fn billing_changes(matches: &ArgMatches<'_>) -> TrackChanges {
+    TrackChanges {
+        rate: if matches.is_present("clear-rate") {
+            Some(None)
+        } else {
+            rate(matches).map(Some)
+        },
+        billable: if matches.is_present("non-billable") {
+            Some(false)
+        } else if matches.is_present("billable") {
+            Some(true)
+        } else {
+            None
+        },
+        ..TrackChanges::default()
+    }
+}
+
+/// The billing of a new track given with `--rate` and `--non-billable`.
+fn billing(matches: &ArgMatches<'_>) -> Billing {
+    Billing {
+        rate: rate(matches),
+        billable: !matches.is_present("non-billable"),
+    }
+}
+
+fn track_query(matches: &ArgMatches<'_>) -> TrackQuery {
+    let mut query = TrackQuery::new();
+    if let Some(since) = matches.value_of("since") {
+        query = query.since(exit_on_error(parse_time(since)));
+    }
+    if let Some(until) = matches.value_of("until") {
+        query = query.until(exit_on_error(parse_time(until)));
+    }
+    if let Some(project) = matches.value_of("project") {
+        query = query.project(String::from(project));
+    }
+    if let Some(workspace) = matches.value_of("workspace") {
+        query = query.workspace(String::from(workspace));
+    }
+    if let Some(name) = matches.value_of("name") {
+        query = query.name(String::from(name));
+    }
+    if let Some(text) = matches.value_of("grep") {
+        query = query.grep(String::from(text));
+    }
+    for tag in tags(matches) {
+        query = query.tag(tag);
+    }
+    if matches.is_present("running") {
+        query = query.running_only();
+    }
+    if let Some(limit) = matches.value_of("limit") {
+        query = query.limit(limit.parse().unwrap());
+    }
+    if let Some(offset) = matches.value_of("offset") {
+        query = query.offset(offset.parse().unwrap());
+    }
+    if matches.value_of("order") == Some("desc") {
+        query = query.order(Order::Descending);
+    }
+    query
+}
+
+/// Project, workspace and tags of a new track. The flags come first, then
+/// the `.tracker.toml` of the directory and last the context in use. A
+/// project guessed from the git repository is only used when it exists.
+fn track_defaults(matches: &ArgMatches<'_>) -> (String, String, Vec<String>) {
+    let service = init_projects(matches);
+    let context = exit_on_error(service.context());
+    let directory = match env::current_dir() {
+        Ok(current) => exit_on_error(directory::discover(&current)).unwrap_or_default(),
+        Err(_) => DirectoryDefaults::default(),
+    };
+    let workspace = matches
+        .value_of("workspace")
+        .map(String::from)
+        .or(directory.workspace);
+    let project = match matches.value_of("project") {
+        Some(project) => Some(String::from(project)),
+        None if directory.path.is_some() => directory.project,
+        None => directory.project.filter(|project| {
+            workspace
+                .as_ref()
+                .or(context.workspace.as_ref())
+                .is_some_and(|workspace| service.find_project(workspace, project).is_ok())
+        }),
+    };
+    let (project, workspace) = exit_on_error(context.resolve(project, workspace));
+    let mut tags = directory.tags;
+    tags.extend(self::tags(matches));
+    (project, workspace, tags)
+}
+
+/// The query of the filters, limited to the context in use without `--all`.
+fn context_query(matches: &ArgMatches<'_>) -> TrackQuery {
+    let query = track_query(matches);
+    if matches.is_present("all") {
+        return query;
+    }
+    exit_on_error(init_projects(matches).context()).filter(query)
+}
+
+/// Reads `--columns`, `--width` and `--color`, the width defaults to the
+/// `COLUMNS` variable and colors are only used on terminals without `NO_COLOR`.
+fn list_options(matches: &ArgMatches<'_>) -> ListOptions {
+    let columns = match matches.values_of("columns") {
+        Some(names) => names.filter_map(Column::from_name).collect(),
+        None => Column::ALL.to_vec(),
+    };
+    let width = matches
+        .value_of("width")
+        .map(|width| width.parse().unwrap())
+        .or_else(|| {
+            env::var("COLUMNS")
+                .ok()
+                .and_then(|width| width.parse().ok())
+        });
+    let color = match matches.value_of("color") {
+        Some("always") => true,
+        Some("never") => false,
+        _ => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+    };
+    ListOptions {
+        columns,
+        width,
+        color,
+    }
+}
+
+fn output(matches: &ArgMatches<'_>) -> Output {
+    Output::from_name(matches.value_of("output").unwrap_or("table"))
+}
+
+fn print_track(matches: &ArgMatches<'_>, message: &str, track: &Track) {
+    match output(matches) {
+        Output::Table => {
+            println!("{}", message);
+            print_tracks(matches, std::slice::from_ref(track));
+        }
+        output => print!("{}", write_track(output, Some(track), Utc::now())),
+    }
+}
+
+fn print_tracks(matches: &ArgMatches<'_>, tracks: &[Track]) {
+    match output(matches) {
+        Output::Table => print!(
+            "{}",
+            render_tracks(tracks, &list_options(matches), Utc::now())
+        ),
+        output => print!("{}", write_tracks(output, tracks, Utc::now())),
+    }
+}
+
+fn print_workspaces(matches: &ArgMatches<'_>, message: Option<&str>, workspaces: &[Workspace]) {
+    match output(matches) {
+        Output::Table => {
+            if let Some(message) = message {
+                println!("{}", message);
+            }
+            print!("{}", render_workspaces(workspaces));
+        }
+        output => print!("{}", write_workspaces(output, workspaces)),
+    }
+}
+
+fn print_projects(matches: &ArgMatches<'_>, message: Option<&str>, projects: &[Project]) {
+    match output(matches) {
+        Output::Table => {
+            if let Some(message) = message {
+                println!("{}", message);
+            }
+            print!("{}", render_projects(projects));
+        }
+        output => print!("{}", write_projects(output, projects)),
+    }
+}
+
+/// Exit code of `status` when no track is running.
+const NOT_RUNNING: i32 = 1;
 
 fn main() {
     let create = Command::new("create")
         .description("Create track")
         .options(|app| {
-            app.args(&vec![
+            app.args(&[
                 Arg::with_name("name")
                     .takes_value(true)
                     .required(true)
@@ -14,71 +438,1431 @@ fn main() {
                     .help("Name the task"),
                 Arg::with_name("project")
                     .takes_value(true)
-                    .required(true)
                     .short("p")
-                    .help("project of the task"),
+                    .help("project of the task, the one of the directory or in use by default"),
                 Arg::with_name("workspace")
                     .takes_value(true)
-                    .required(true)
                     .short("w")
-                    .help("workspace of the project"),
+                    .help(
+                        "workspace of the project, the one of the directory or in use by default",
+                    ),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Tag the track, can be repeated"),
+                Arg::with_name("rate")
+                    .long("rate")
+                    .takes_value(true)
+                    .value_name("RATE")
+                    .help("Hourly rate of the track, e.g. \"95.50 EUR\""),
+                Arg::with_name("non-billable")
+                    .long("non-billable")
+                    .help("Leave the track out of the billed amounts"),
+                Arg::with_name("at")
+                    .long("at")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Start the track at a past moment, e.g. \"10 minutes ago\""),
             ])
         })
-        .runner(|args: &str, matches: &ArgMatches<'_>| {
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
             let name = matches.value_of("name").unwrap();
-            let project = matches.value_of("project").unwrap();
-            let workspace = matches.value_of("workspace").unwrap();
-            println!(
-                "Running create, env = {}, name = {}, project = {}, workspace = {}",
-                args, name, project, workspace
-            );
-            let mut service = tracker::init();
-            let track = service
-                .start_new_track(
-                    String::from(name),
-                    String::from(project),
-                    String::from(workspace),
-                )
-                .unwrap();
-            println!("Track created:");
-            println!("{:?}", track);
+            let (project, workspace, tags) = track_defaults(matches);
+            let at = match matches.value_of("at") {
+                Some(at) => exit_on_error(parse_time(at)),
+                None => Utc::now(),
+            };
+            let service = init(matches);
+            let track = exit_on_error(service.start_new_track_at(
+                String::from(name),
+                project,
+                workspace,
+                tags,
+                billing(matches),
+                at,
+            ));
+            print_track(matches, "Track created:", &track);
+            Ok(())
+        });
+    let add = Command::new("add")
+        .description("Add a track that already happened")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .takes_value(true)
+                    .required(true)
+                    .short("n")
+                    .help("Name the task"),
+                Arg::with_name("project")
+                    .takes_value(true)
+                    .short("p")
+                    .help("project of the task, the one of the directory or in use by default"),
+                Arg::with_name("workspace")
+                    .takes_value(true)
+                    .short("w")
+                    .help(
+                        "workspace of the project, the one of the directory or in use by default",
+                    ),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Tag the track, can be repeated"),
+                Arg::with_name("rate")
+                    .long("rate")
+                    .takes_value(true)
+                    .value_name("RATE")
+                    .help("Hourly rate of the track, e.g. \"95.50 EUR\""),
+                Arg::with_name("non-billable")
+                    .long("non-billable")
+                    .help("Leave the track out of the billed amounts"),
+                Arg::with_name("start")
+                    .long("start")
+                    .takes_value(true)
+                    .required(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("When the task started"),
+                Arg::with_name("end")
+                    .long("end")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .required_unless("duration")
+                    .conflicts_with("duration")
+                    .help("When the task ended"),
+                Arg::with_name("duration")
+                    .long("duration")
+                    .takes_value(true)
+                    .value_name("DURATION")
+                    .help("How long the task took, e.g. 45m or 1h30m"),
+                Arg::with_name("overlap")
+                    .long("overlap")
+                    .takes_value(true)
+                    .possible_values(&["reject", "trim", "allow"])
+                    .default_value("reject")
+                    .help("What to do with existing tracks in the same period"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let start = exit_on_error(parse_time(matches.value_of("start").unwrap()));
+            let end = match matches.value_of("end") {
+                Some(end) => TrackEnd::At(exit_on_error(parse_time(end))),
+                None => TrackEnd::After(exit_on_error(parse_duration(
+                    matches.value_of("duration").unwrap(),
+                ))),
+            };
+            let overlap = match matches.value_of("overlap") {
+                Some("trim") => Overlap::Trim,
+                Some("allow") => Overlap::Allow,
+                _ => Overlap::Reject,
+            };
+            let (project, workspace, tags) = track_defaults(matches);
+            let service = init(matches);
+            let track = exit_on_error(service.add_track(
+                String::from(matches.value_of("name").unwrap()),
+                project,
+                workspace,
+                tags,
+                billing(matches),
+                start,
+                end,
+                overlap,
+            ));
+            print_track(matches, "Track added:", &track);
             Ok(())
         });
     let stop = Command::new("stop")
         .description("Stop current track")
-        .runner(|_: &str, _: &ArgMatches<'_>| {
-            let mut service = tracker::init();
-            service.stop_current_track().unwrap();
-            println!("Current track stopped");
-            Ok(())
-        });
-    let list =
-        Command::new("list")
-            .description("List tracks")
-            .runner(|_: &str, _: &ArgMatches<'_>| {
-                let service = tracker::init();
-                let tracks = service.list();
-                println!("List of all tracks");
-                for track in tracks.iter() {
-                    println!("{:?}", track);
+        .options(|app| {
+            app.arg(
+                Arg::with_name("at")
+                    .long("at")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Stop the track at a past moment, e.g. 17:30"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let at = match matches.value_of("at") {
+                Some(at) => exit_on_error(parse_time(at)),
+                None => Utc::now(),
+            };
+            let service = init(matches);
+            let track = exit_on_error(service.stop_current_track_at(at));
+            print_track(matches, "Track stopped:", &track);
+            Ok(())
+        });
+    let status = Command::new("status")
+        .description("Show the running track, exits with 1 when nothing is running")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .value_name("TEMPLATE")
+                    .help("Output template using {name}, {project}, {workspace}, {start}, {elapsed}, {state}, {id} and {short_id}"),
+                Arg::with_name("quiet")
+                    .long("quiet")
+                    .short("q")
+                    .help("Print nothing, only set the exit code"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init(matches);
+            let quiet = matches.is_present("quiet");
+            let current = exit_on_error(service.current());
+            let output = output(matches);
+            if output != Output::Table {
+                if !quiet {
+                    print!("{}", write_track(output, current.as_ref(), Utc::now()));
+                }
+                if current.is_none() {
+                    process::exit(NOT_RUNNING);
+                }
+                return Ok(());
+            }
+            match current {
+                Some(track) => {
+                    if !quiet {
+                        let template = matches.value_of("format").unwrap_or(STATUS_FORMAT);
+                        println!("{}", render_status(template, &track, Utc::now()));
+                    }
+                    Ok(())
+                }
+                None => {
+                    if !quiet && !matches.is_present("format") {
+                        println!("No track running");
+                    }
+                    process::exit(NOT_RUNNING);
                 }
-                Ok(())
-            });
+            }
+        });
+    let continue_track = Command::new("continue")
+        .description("Start again an earlier track, by default the last stopped one")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("track")
+                    .takes_value(true)
+                    .help("Name, id prefix or part of the name of the track"),
+                Arg::with_name("at")
+                    .long("at")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Start the track at a past moment"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let at = match matches.value_of("at") {
+                Some(at) => exit_on_error(parse_time(at)),
+                None => Utc::now(),
+            };
+            let service = init(matches);
+            let track = exit_on_error(service.continue_track_at(matches.value_of("track"), at));
+            print_track(matches, "Track continued:", &track);
+            Ok(())
+        });
+    let note = Command::new("note")
+        .description("Append a note to the current track, or edit the notes of a track")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("note")
+                    .takes_value(true)
+                    .help("Text to append to the notes of the current track"),
+            )
+            .arg(
+                Arg::with_name("edit")
+                    .long("edit")
+                    .takes_value(true)
+                    .value_name("ID")
+                    .conflicts_with("note")
+                    .help("Edit the notes of the track with this id"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init(matches);
+            let note = matches.value_of("note");
+            let noted = match (note, matches.value_of("edit")) {
+                (_, Some(id)) => Some(exit_on_error(service.find_by_prefix(id))),
+                (None, None) => Some(exit_on_error(
+                    service
+                        .current()
+                        .and_then(|track| track.ok_or(Error::NoActiveTrack)),
+                )),
+                (Some(_), None) => None,
+            };
+            let track = match noted {
+                Some(track) => {
+                    let changes = TrackChanges {
+                        notes: Some(exit_on_error(edit_text(&track.notes, &track.id))),
+                        ..TrackChanges::default()
+                    };
+                    exit_on_error(service.edit_track(&track.id, changes))
+                }
+                None => exit_on_error(service.append_note(note.unwrap(), Local::now())),
+            };
+            print_track(matches, "Track noted:", &track);
+            Ok(())
+        });
+    let pause = Command::new("pause")
+        .description("Pause the current track")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("at")
+                    .long("at")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Pause the track at a past moment"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let at = match matches.value_of("at") {
+                Some(at) => exit_on_error(parse_time(at)),
+                None => Utc::now(),
+            };
+            let service = init(matches);
+            let track = exit_on_error(service.pause_current_track_at(at));
+            print_track(matches, "Track paused:", &track);
+            Ok(())
+        });
+    let resume = Command::new("resume")
+        .description("Resume the paused track")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("at")
+                    .long("at")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Resume the track at a past moment"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let at = match matches.value_of("at") {
+                Some(at) => exit_on_error(parse_time(at)),
+                None => Utc::now(),
+            };
+            let service = init(matches);
+            let track = exit_on_error(service.resume_current_track_at(at));
+            print_track(matches, "Track resumed:", &track);
+            Ok(())
+        });
+    let list = Command::new("list")
+        .description("List tracks")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Only tracks running after this time"),
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Only tracks started before this time"),
+                Arg::with_name("project")
+                    .long("project")
+                    .short("p")
+                    .takes_value(true)
+                    .help("Only tracks of this project"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .help("Only tracks of this workspace"),
+                Arg::with_name("all")
+                    .long("all")
+                    .short("a")
+                    .help("Ignore the context in use"),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Only tracks having this tag, can be repeated"),
+                Arg::with_name("name")
+                    .long("name")
+                    .short("n")
+                    .takes_value(true)
+                    .help("Only tracks whose name contains this text"),
+                Arg::with_name("grep")
+                    .long("grep")
+                    .short("g")
+                    .takes_value(true)
+                    .value_name("TEXT")
+                    .help("Only tracks whose name or notes contain this text"),
+                Arg::with_name("running")
+                    .long("running")
+                    .help("Only the running track"),
+                Arg::with_name("limit")
+                    .long("limit")
+                    .takes_value(true)
+                    .validator(validate_number)
+                    .help("Maximum number of tracks to show"),
+                Arg::with_name("offset")
+                    .long("offset")
+                    .takes_value(true)
+                    .validator(validate_number)
+                    .help("Number of tracks to skip"),
+                Arg::with_name("order")
+                    .long("order")
+                    .takes_value(true)
+                    .possible_values(&["asc", "desc"])
+                    .default_value("asc")
+                    .help("Sort by start time"),
+                Arg::with_name("columns")
+                    .long("columns")
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .possible_values(&[
+                        "id",
+                        "name",
+                        "project",
+                        "workspace",
+                        "tags",
+                        "start",
+                        "end",
+                        "duration",
+                    ])
+                    .help("Comma separated columns to show, all by default"),
+                Arg::with_name("width")
+                    .long("width")
+                    .takes_value(true)
+                    .validator(validate_number)
+                    .help("Truncate names to fit this many characters"),
+                Arg::with_name("color")
+                    .long("color")
+                    .takes_value(true)
+                    .possible_values(&["auto", "always", "never"])
+                    .default_value("auto")
+                    .help("When to color the output"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let query = context_query(matches);
+            let service = init(matches);
+            let tracks = exit_on_error(service.list_by(&query));
+            print_tracks(matches, &tracks);
+            Ok(())
+        });
+    let report = Command::new("report")
+        .description("Sum the tracked time of a period")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("period")
+                    .long("period")
+                    .takes_value(true)
+                    .possible_values(&[
+                        "today",
+                        "yesterday",
+                        "this-week",
+                        "last-week",
+                        "this-month",
+                        "last-month",
+                        "this-year",
+                        "last-year",
+                    ])
+                    .default_value("this-week")
+                    .help("Period to report, overridden by --since and --until"),
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Start of the reported time"),
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("End of the reported time"),
+                Arg::with_name("group-by")
+                    .long("group-by")
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .possible_values(&[
+                        "workspace",
+                        "project",
+                        "name",
+                        "tag",
+                        "day",
+                        "week",
+                        "month",
+                    ])
+                    .default_value("project")
+                    .help("Comma separated dimensions to group by, e.g. project,day"),
+                Arg::with_name("project")
+                    .long("project")
+                    .short("p")
+                    .takes_value(true)
+                    .help("Only report tracks of this project"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .help("Only report tracks of this workspace"),
+                Arg::with_name("all")
+                    .long("all")
+                    .short("a")
+                    .help("Ignore the context in use"),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Only report tracks having this tag, can be repeated"),
+                Arg::with_name("amounts")
+                    .long("amounts")
+                    .help("Add up what the billable tracks earned at their rates"),
+            ])
+            .args(&rounding_args())
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let (since, until) = exit_on_error(parse_period(matches.value_of("period").unwrap()));
+            let mut query = context_query(matches);
+            query.since = query.since.or(Some(since));
+            query.until = query.until.or(Some(until));
+            let group_by: Vec<GroupBy> = matches
+                .values_of("group-by")
+                .unwrap()
+                .map(|group| match group {
+                    "workspace" => GroupBy::Workspace,
+                    "name" => GroupBy::Name,
+                    "tag" => GroupBy::Tag,
+                    "day" => GroupBy::Day,
+                    "week" => GroupBy::Week,
+                    "month" => GroupBy::Month,
+                    _ => GroupBy::Project,
+                })
+                .collect();
+            let service = init(matches);
+            let report = exit_on_error(service.report(
+                &query,
+                &group_by,
+                Local::now(),
+                matches.is_present("amounts"),
+                rounding(matches).as_ref(),
+            ));
+            match output(matches) {
+                Output::Table => print!("{}", render_report(&report)),
+                output => print!("{}", write_report(output, &report)),
+            }
+            Ok(())
+        });
+    let export = Command::new("export")
+        .description("Export tracks to CSV, JSON or iCalendar")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["csv", "json", "ics"])
+                    .default_value("csv")
+                    .help("Format of the exported tracks"),
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Only tracks running after this time"),
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Only tracks started before this time"),
+                Arg::with_name("project")
+                    .long("project")
+                    .short("p")
+                    .takes_value(true)
+                    .help("Only tracks of this project"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .help("Only tracks of this workspace"),
+                Arg::with_name("all")
+                    .long("all")
+                    .short("a")
+                    .help("Ignore the context in use"),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Only tracks having this tag, can be repeated"),
+                Arg::with_name("file")
+                    .long("file")
+                    .short("f")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Write to this file instead of the standard output"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let format = match matches.value_of("format") {
+                Some("json") => ExportFormat::Json,
+                Some("ics") => ExportFormat::Ics,
+                _ => ExportFormat::Csv,
+            };
+            let query = context_query(matches);
+            let service = init(matches);
+            let exporter = format.exporter();
+            match matches.value_of("file") {
+                Some(path) => {
+                    let mut file = exit_on_error(File::create(path).map_err(Error::from));
+                    let count =
+                        exit_on_error(service.export(&query, &*exporter, Utc::now(), &mut file));
+                    println!("{} tracks exported to {}", count, path);
+                }
+                None => {
+                    let mut stdout = io::stdout();
+                    exit_on_error(service.export(&query, &*exporter, Utc::now(), &mut stdout));
+                }
+            }
+            Ok(())
+        });
+    let invoice = Command::new("invoice")
+        .description("Bill the tracks of a client and mark them as invoiced")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("client")
+                    .long("client")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Client of the projects, or workspace of projects without one"),
+                Arg::with_name("period")
+                    .long("period")
+                    .takes_value(true)
+                    .possible_values(&[
+                        "today",
+                        "yesterday",
+                        "this-week",
+                        "last-week",
+                        "this-month",
+                        "last-month",
+                        "this-year",
+                        "last-year",
+                    ])
+                    .default_value("last-month")
+                    .help(
+                        "Bill the tracks started in this period, overridden by --since and --until",
+                    ),
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Bill the tracks started from this time"),
+                Arg::with_name("until")
+                    .long("until")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("Bill the tracks started before this time"),
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["markdown", "html", "text"])
+                    .default_value("markdown")
+                    .help("Built-in template, and how values are escaped with --template"),
+                Arg::with_name("template")
+                    .long("template")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Template to fill instead of the built-in one"),
+                Arg::with_name("items")
+                    .long("items")
+                    .takes_value(true)
+                    .possible_values(&["tracks", "projects"])
+                    .default_value("tracks")
+                    .help("One line per track, or per project and rate"),
+                Arg::with_name("tax")
+                    .long("tax")
+                    .takes_value(true)
+                    .value_name("TAX")
+                    .help("Tax added to the subtotal, e.g. \"VAT 20%\""),
+                Arg::with_name("number")
+                    .long("number")
+                    .takes_value(true)
+                    .help("Number of the invoice, defaults to the next one of the year"),
+                Arg::with_name("file")
+                    .long("file")
+                    .short("f")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Write to this file instead of the standard output"),
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Show the invoice without marking the tracks as invoiced"),
+            ])
+            .args(&rounding_args())
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let (since, until) = exit_on_error(parse_period(matches.value_of("period").unwrap()));
+            let format = match matches.value_of("format") {
+                Some("html") => InvoiceFormat::Html,
+                Some("text") => InvoiceFormat::Text,
+                _ => InvoiceFormat::Markdown,
+            };
+            let template = match matches.value_of("template") {
+                Some(path) => exit_on_error(fs::read_to_string(path).map_err(Error::from)),
+                None => String::from(format.template()),
+            };
+            let request = InvoiceRequest {
+                client: String::from(matches.value_of("client").unwrap()),
+                since: matches
+                    .value_of("since")
+                    .map_or(since, |since| exit_on_error(parse_time(since))),
+                until: matches
+                    .value_of("until")
+                    .map_or(until, |until| exit_on_error(parse_time(until))),
+                items: match matches.value_of("items") {
+                    Some("projects") => LineItems::Projects,
+                    _ => LineItems::Tracks,
+                },
+                tax: matches
+                    .value_of("tax")
+                    .map(|tax| exit_on_error(parse_tax(tax))),
+                number: matches.value_of("number").map(String::from),
+                rounding: rounding(matches),
+            };
+            let service = init_invoices(matches);
+            let dry_run = matches.is_present("dry-run");
+            let invoice = exit_on_error(service.invoice(&request, Local::now(), dry_run));
+            let document = match output(matches) {
+                Output::Table => exit_on_error(invoice.render(&template, format)),
+                output => write_invoice(output, &invoice),
+            };
+            let status = format!(
+                "invoice {}: {} tracks {}",
+                invoice.number,
+                invoice.tracks.len(),
+                if dry_run {
+                    "would be marked as invoiced"
+                } else {
+                    "marked as invoiced"
+                }
+            );
+            match matches.value_of("file") {
+                Some(path) => {
+                    exit_on_error(fs::write(path, document).map_err(Error::from));
+                    println!("{}, written to {}", status, path);
+                }
+                None => {
+                    print!("{}", document);
+                    eprintln!("{}", status);
+                }
+            }
+            Ok(())
+        });
+    let import = Command::new("import")
+        .description("Import tracks from another time tracker")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("from")
+                    .long("from")
+                    .takes_value(true)
+                    .required(true)
+                    .possible_values(&[
+                        "toggl-csv",
+                        "clockify-csv",
+                        "timewarrior",
+                        "org",
+                        "tracker-json",
+                    ])
+                    .help("Format of the file"),
+                Arg::with_name("file").required(true).help("File to import"),
+                Arg::with_name("project")
+                    .long("project")
+                    .short("p")
+                    .takes_value(true)
+                    .help("Project of the tracks without one, by default the one in use"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .help("Workspace of the tracks without one, by default the one in use"),
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Show the tracks that would be imported without saving them"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let format = match matches.value_of("from") {
+                Some("clockify-csv") => ImportFormat::ClockifyCsv,
+                Some("timewarrior") => ImportFormat::Timewarrior,
+                Some("org") => ImportFormat::Org,
+                Some("tracker-json") => ImportFormat::TrackerJson,
+                _ => ImportFormat::TogglCsv,
+            };
+            let context = exit_on_error(init_projects(matches).context());
+            let workspace = matches
+                .value_of("workspace")
+                .map(String::from)
+                .or_else(|| context.workspace.clone())
+                .unwrap_or_default();
+            let project = matches
+                .value_of("project")
+                .map(String::from)
+                .or_else(|| {
+                    let workspace = Some(workspace.clone());
+                    context
+                        .resolve(None, workspace)
+                        .ok()
+                        .map(|(project, _)| project)
+                })
+                .unwrap_or_default();
+            let defaults = ImportDefaults { project, workspace };
+            let input = exit_on_error(
+                fs::read_to_string(matches.value_of("file").unwrap()).map_err(Error::from),
+            );
+            let tracks = exit_on_error(format.importer(defaults, Local).import(&input));
+            let service = init(matches);
+            let dry_run = matches.is_present("dry-run");
+            let summary = exit_on_error(service.import(tracks, dry_run));
+            if output(matches) == Output::Table {
+                println!(
+                    "{} tracks {}, {} duplicates skipped",
+                    summary.imported.len(),
+                    if dry_run {
+                        "would be imported"
+                    } else {
+                        "imported"
+                    },
+                    summary.duplicates.len()
+                );
+            }
+            if !summary.unknown_projects.is_empty() {
+                let names: Vec<String> = summary
+                    .unknown_projects
+                    .iter()
+                    .map(|(workspace, project)| format!("{}/{}", workspace, project))
+                    .collect();
+                eprintln!(
+                    "tracks of missing workspaces or projects left out: {}",
+                    names.join(", ")
+                );
+            }
+            print_tracks(matches, &summary.imported);
+            Ok(())
+        });
+    let edit = Command::new("edit")
+        .description("Change a track")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("id")
+                    .required(true)
+                    .help("Id of the track, or the start of it"),
+                Arg::with_name("name")
+                    .short("n")
+                    .takes_value(true)
+                    .help("New name of the track"),
+                Arg::with_name("project")
+                    .short("p")
+                    .takes_value(true)
+                    .help("Move the track to this project"),
+                Arg::with_name("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .help("Move the track to this workspace"),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .conflicts_with("clear-tags")
+                    .help("Replace the tags of the track, can be repeated"),
+                Arg::with_name("clear-tags")
+                    .long("clear-tags")
+                    .help("Remove all tags of the track"),
+                Arg::with_name("start")
+                    .long("start")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("New start time"),
+                Arg::with_name("end")
+                    .long("end")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .allow_hyphen_values(true)
+                    .help("New end time"),
+                Arg::with_name("rate")
+                    .long("rate")
+                    .takes_value(true)
+                    .value_name("RATE")
+                    .conflicts_with("clear-rate")
+                    .help("Hourly rate of the track, e.g. \"95.50 EUR\""),
+                Arg::with_name("clear-rate")
+                    .long("clear-rate")
+                    .help("Bill the track at the rate of its project or workspace"),
+                Arg::with_name("billable")
+                    .long("billable")
+                    .conflicts_with("non-billable")
+                    .help("Include the track in the billed amounts"),
+                Arg::with_name("non-billable")
+                    .long("non-billable")
+                    .help("Leave the track out of the billed amounts"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let changes = TrackChanges {
+                name: matches.value_of("name").map(String::from),
+                project: matches.value_of("project").map(String::from),
+                workspace: matches.value_of("workspace").map(String::from),
+                tags: if matches.is_present("clear-tags") {
+                    Some(vec![])
+                } else {
+                    matches.values_of("tag").map(|_| tags(matches))
+                },
+                start: matches
+                    .value_of("start")
+                    .map(|start| exit_on_error(parse_time(start))),
+                end: matches
+                    .value_of("end")
+                    .map(|end| exit_on_error(parse_time(end))),
+                notes: None,
+                ..billing_changes(matches)
+            };
+            let service = init(matches);
+            let track = exit_on_error(service.edit_track(matches.value_of("id").unwrap(), changes));
+            print_track(matches, "Track updated:", &track);
+            Ok(())
+        });
+    let rm = Command::new("rm")
+        .description("Move a track to the trash")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("id")
+                    .required(true)
+                    .help("Id of the track, or the start of it"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init(matches);
+            let track = exit_on_error(service.delete_track(matches.value_of("id").unwrap()));
+            print_track(matches, "Track moved to the trash:", &track);
+            Ok(())
+        });
+    let trash = Command::new("trash")
+        .description("List deleted tracks")
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init(matches);
+            let tracks = exit_on_error(service.trash());
+            print_tracks(matches, &tracks);
+            Ok(())
+        });
+    let restore = Command::new("restore")
+        .description("Restore a track from the trash")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("id")
+                    .required(true)
+                    .help("Id of the deleted track, or the start of it"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init(matches);
+            let track = exit_on_error(service.restore_track(matches.value_of("id").unwrap()));
+            print_track(matches, "Track restored:", &track);
+            Ok(())
+        });
+    let migrate = Command::new("migrate")
+        .description("Apply pending database migrations")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Only show the migrations that would be applied"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let connection = exit_on_error(tracker::connect(&config(matches)));
+            let migrations = if matches.is_present("dry-run") {
+                exit_on_error(migrations::pending(&connection))
+            } else {
+                exit_on_error(migrations::migrate(&connection))
+            };
+            if migrations.is_empty() {
+                println!("Database is up to date");
+            }
+            for migration in migrations.iter() {
+                let verb = if matches.is_present("dry-run") {
+                    "Would apply"
+                } else {
+                    "Applied"
+                };
+                println!(
+                    "{} migration {}: {}",
+                    verb, migration.version, migration.description
+                );
+            }
+            Ok(())
+        });
+    let version = Command::new("version")
+        .description("Show the database schema version")
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let connection = exit_on_error(tracker::connect(&config(matches)));
+            let current = exit_on_error(migrations::current_version(&connection));
+            println!("Database schema version: {}", current);
+            println!("Supported schema version: {}", migrations::latest_version());
+            Ok(())
+        });
+    let use_context = Command::new("use")
+        .description("Set the workspace and project used when none is given")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("context")
+                    .conflicts_with("clear")
+                    .value_name("WORKSPACE[/PROJECT]")
+                    .help("Workspace, optionally followed by a slash and a project"),
+                Arg::with_name("clear")
+                    .long("clear")
+                    .help("Stop using a context"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            if matches.is_present("clear") {
+                exit_on_error(service.clear_context());
+            }
+            let context = match matches.value_of("context") {
+                Some(context) => {
+                    let (workspace, project) = match context.split_once('/') {
+                        Some((workspace, project)) => (workspace, Some(project)),
+                        None => (context, None),
+                    };
+                    exit_on_error(service.use_context(workspace, project))
+                }
+                None => exit_on_error(service.context()),
+            };
+            match output(matches) {
+                Output::Table => match (&context.workspace, &context.project) {
+                    (Some(workspace), Some(project)) => println!("Using {}/{}", workspace, project),
+                    (Some(workspace), None) => println!("Using {}", workspace),
+                    _ => println!("No context in use"),
+                },
+                output => print!("{}", write_context(output, &context)),
+            }
+            Ok(())
+        });
+    let init_dir = Command::new("init-dir")
+        .description("Write the defaults of new tracks in this directory to .tracker.toml")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("project")
+                    .takes_value(true)
+                    .short("p")
+                    .help("Project of the tracks, the name of the git repository by default"),
+                Arg::with_name("workspace")
+                    .takes_value(true)
+                    .short("w")
+                    .help("Workspace of the project, the one in use by default"),
+                Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Tag the tracks, can be repeated"),
+                Arg::with_name("force")
+                    .long("force")
+                    .help("Overwrite an existing file"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let current = exit_on_error(env::current_dir().map_err(Error::from));
+            let workspace = match matches.value_of("workspace") {
+                Some(workspace) => Some(String::from(workspace)),
+                None => exit_on_error(init_projects(matches).context()).workspace,
+            };
+            let defaults = DirectoryDefaults {
+                project: matches
+                    .value_of("project")
+                    .map(String::from)
+                    .or_else(|| directory::git_project(&current)),
+                workspace,
+                tags: tags(matches),
+                path: None,
+            };
+            let path = exit_on_error(directory::write(
+                &current,
+                &defaults,
+                matches.is_present("force"),
+            ));
+            println!("Directory defaults written to {}", path.display());
+            Ok(())
+        });
+    let workspace_add = Command::new("add")
+        .description("Add a workspace")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the workspace"),
+                Arg::with_name("color")
+                    .long("color")
+                    .takes_value(true)
+                    .help("Color of the workspace, e.g. #ff8800"),
+                Arg::with_name("rate")
+                    .long("rate")
+                    .takes_value(true)
+                    .value_name("RATE")
+                    .help("Hourly rate of its tracks, e.g. \"95.50 EUR\""),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let rate = rate(matches);
+            let service = init_projects(matches);
+            let mut workspace = exit_on_error(service.add_workspace(
+                String::from(matches.value_of("name").unwrap()),
+                matches.value_of("color").map(String::from),
+            ));
+            if rate.is_some() {
+                workspace = exit_on_error(service.set_workspace_rate(&workspace.name, rate));
+            }
+            print_workspaces(matches, Some("Workspace added:"), &[workspace]);
+            Ok(())
+        });
+    let workspace_rename = Command::new("rename")
+        .description("Rename a workspace")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the workspace"),
+                Arg::with_name("new-name")
+                    .required(true)
+                    .help("New name of the workspace"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let workspace = exit_on_error(service.rename_workspace(
+                matches.value_of("name").unwrap(),
+                String::from(matches.value_of("new-name").unwrap()),
+            ));
+            print_workspaces(matches, Some("Workspace renamed:"), &[workspace]);
+            Ok(())
+        });
+    let workspace_archive = Command::new("archive")
+        .description("Hide a workspace and refuse new tracks in it")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the workspace"),
+                Arg::with_name("undo")
+                    .long("undo")
+                    .help("Bring the workspace back from the archive"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let archived = !matches.is_present("undo");
+            let workspace = exit_on_error(
+                service.archive_workspace(matches.value_of("name").unwrap(), archived),
+            );
+            let message = if archived {
+                "Workspace archived:"
+            } else {
+                "Workspace unarchived:"
+            };
+            print_workspaces(matches, Some(message), &[workspace]);
+            Ok(())
+        });
+    let workspace_rate = Command::new("rate")
+        .description("Set the hourly rate of the tracks of a workspace")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the workspace"),
+                Arg::with_name("rate")
+                    .required_unless("clear")
+                    .conflicts_with("clear")
+                    .value_name("RATE")
+                    .help("Amount and currency, e.g. \"95.50 EUR\""),
+                Arg::with_name("clear")
+                    .long("clear")
+                    .help("Remove the rate"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let workspace = exit_on_error(
+                service.set_workspace_rate(matches.value_of("name").unwrap(), rate(matches)),
+            );
+            print_workspaces(matches, Some("Workspace rate set:"), &[workspace]);
+            Ok(())
+        });
+    let workspace_list = Command::new("list")
+        .description("List workspaces")
+        .options(|app| {
+            app.arg(
+                Arg::with_name("archived")
+                    .long("archived")
+                    .help("Include archived workspaces"),
+            )
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let workspaces = exit_on_error(service.workspaces(matches.is_present("archived")));
+            print_workspaces(matches, None, &workspaces);
+            Ok(())
+        });
+    let workspace = Commander::new()
+        .add_cmd(workspace_add)
+        .add_cmd(workspace_rename)
+        .add_cmd(workspace_archive)
+        .add_cmd(workspace_rate)
+        .add_cmd(workspace_list)
+        .into_cmd("workspace")
+        .description("Manage workspaces");
+    let project_add = Command::new("add")
+        .description("Add a project to a workspace")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the project"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Workspace of the project"),
+                Arg::with_name("color")
+                    .long("color")
+                    .takes_value(true)
+                    .help("Color of the project, e.g. #ff8800"),
+                Arg::with_name("client")
+                    .long("client")
+                    .takes_value(true)
+                    .help("Client the project is done for"),
+                Arg::with_name("rate")
+                    .long("rate")
+                    .takes_value(true)
+                    .value_name("RATE")
+                    .help("Hourly rate of its tracks, e.g. \"95.50 EUR\""),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let rate = rate(matches);
+            let service = init_projects(matches);
+            let mut project = exit_on_error(service.add_project(
+                String::from(matches.value_of("name").unwrap()),
+                matches.value_of("workspace").unwrap(),
+                matches.value_of("color").map(String::from),
+                matches.value_of("client").map(String::from),
+            ));
+            if rate.is_some() {
+                project = exit_on_error(service.set_project_rate(
+                    &project.workspace,
+                    &project.name,
+                    rate,
+                ));
+            }
+            print_projects(matches, Some("Project added:"), &[project]);
+            Ok(())
+        });
+    let project_rename = Command::new("rename")
+        .description("Rename a project")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the project"),
+                Arg::with_name("new-name")
+                    .required(true)
+                    .help("New name of the project"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Workspace of the project"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let project = exit_on_error(service.rename_project(
+                matches.value_of("workspace").unwrap(),
+                matches.value_of("name").unwrap(),
+                String::from(matches.value_of("new-name").unwrap()),
+            ));
+            print_projects(matches, Some("Project renamed:"), &[project]);
+            Ok(())
+        });
+    let project_archive = Command::new("archive")
+        .description("Hide a project and refuse new tracks in it")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the project"),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Workspace of the project"),
+                Arg::with_name("undo")
+                    .long("undo")
+                    .help("Bring the project back from the archive"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let archived = !matches.is_present("undo");
+            let project = exit_on_error(service.archive_project(
+                matches.value_of("workspace").unwrap(),
+                matches.value_of("name").unwrap(),
+                archived,
+            ));
+            let message = if archived {
+                "Project archived:"
+            } else {
+                "Project unarchived:"
+            };
+            print_projects(matches, Some(message), &[project]);
+            Ok(())
+        });
+    let project_rate = Command::new("rate")
+        .description("Set the hourly rate of the tracks of a project")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the project"),
+                Arg::with_name("rate")
+                    .required_unless("clear")
+                    .conflicts_with("clear")
+                    .value_name("RATE")
+                    .help("Amount and currency, e.g. \"95.50 EUR\""),
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Workspace of the project"),
+                Arg::with_name("clear")
+                    .long("clear")
+                    .help("Remove the rate, the one of the workspace applies"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let project = exit_on_error(service.set_project_rate(
+                matches.value_of("workspace").unwrap(),
+                matches.value_of("name").unwrap(),
+                rate(matches),
+            ));
+            print_projects(matches, Some("Project rate set:"), &[project]);
+            Ok(())
+        });
+    let project_list = Command::new("list")
+        .description("List projects")
+        .options(|app| {
+            app.args(&[
+                Arg::with_name("workspace")
+                    .long("workspace")
+                    .short("w")
+                    .takes_value(true)
+                    .help("Only projects of this workspace"),
+                Arg::with_name("archived")
+                    .long("archived")
+                    .help("Include archived projects"),
+            ])
+        })
+        .runner(|_: &str, matches: &ArgMatches<'_>| {
+            let service = init_projects(matches);
+            let projects = exit_on_error(service.projects(
+                matches.value_of("workspace"),
+                matches.is_present("archived"),
+            ));
+            print_projects(matches, None, &projects);
+            Ok(())
+        });
+    let project = Commander::new()
+        .add_cmd(project_add)
+        .add_cmd(project_rename)
+        .add_cmd(project_archive)
+        .add_cmd(project_rate)
+        .add_cmd(project_list)
+        .into_cmd("project")
+        .description("Manage the projects of the workspaces");
+    let db = Commander::new()
+        .add_cmd(migrate)
+        .add_cmd(version)
+        .into_cmd("db")
+        .description("Manage the tracks database");
 
     Commander::new()
         .options(|app| {
-            app.args(&vec![Arg::with_name("environment")
-                .short("e")
-                .long("env")
-                .global(true)
-                .takes_value(true)
-                .value_name("STRING")
-                .help("Sets an environment value, defaults to \"dev\"")])
+            app.args(&[
+                Arg::with_name("environment")
+                    .short("e")
+                    .long("env")
+                    .global(true)
+                    .takes_value(true)
+                    .value_name("STRING")
+                    .help("Sets an environment value, defaults to \"dev\""),
+                Arg::with_name("db")
+                    .long("db")
+                    .global(true)
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Path of the tracks database, overrides TRACKER_DB and the config file"),
+                Arg::with_name("output")
+                    .long("output")
+                    .short("o")
+                    .global(true)
+                    .takes_value(true)
+                    .possible_values(&["table", "json", "ndjson", "csv"])
+                    .help("Output format, defaults to a table for people"),
+            ])
         })
         .args(|_args, matches| matches.value_of("environment").unwrap_or("dev"))
         .add_cmd(create)
+        .add_cmd(add)
         .add_cmd(stop)
+        .add_cmd(status)
+        .add_cmd(continue_track)
+        .add_cmd(note)
+        .add_cmd(pause)
+        .add_cmd(resume)
         .add_cmd(list)
+        .add_cmd(report)
+        .add_cmd(export)
+        .add_cmd(invoice)
+        .add_cmd(import)
+        .add_cmd(edit)
+        .add_cmd(rm)
+        .add_cmd(trash)
+        .add_cmd(restore)
+        .add_cmd(use_context)
+        .add_cmd(init_dir)
+        .add_cmd(workspace)
+        .add_cmd(project)
+        .add_cmd(db)
         .no_cmd(|_args, _matches| {
             println!("No subcommand matched");
             Ok(())
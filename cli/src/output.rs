@@ -0,0 +1,395 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracker::export::{state, CsvRow, CSV_HEADER};
+use tracker::invoice::{Invoice, InvoiceLine};
+use tracker::model::{Project, Track, Workspace};
+use tracker::report::{Report, ReportGroup};
+use tracker::service::Context;
+
+/// Format selected with the global `--output` flag. Everything but `Table`
+/// is meant for scripts and follows the schema documented in the readme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl Output {
+    pub fn from_name(name: &str) -> Output {
+        match name {
+            "json" => Output::Json,
+            "ndjson" => Output::Ndjson,
+            "csv" => Output::Csv,
+            _ => Output::Table,
+        }
+    }
+}
+
+/// A track with its duration in seconds until now and its state.
+#[derive(Serialize)]
+struct TrackOutput<'a> {
+    #[serde(flatten)]
+    track: &'a Track,
+    duration: i64,
+    state: &'static str,
+}
+
+impl<'a> TrackOutput<'a> {
+    fn from(track: &'a Track, now: DateTime<Utc>) -> TrackOutput<'a> {
+        TrackOutput {
+            track,
+            duration: track.duration_at(now).num_seconds(),
+            state: state(track),
+        }
+    }
+}
+
+fn to_csv<T: Serialize>(header: &[&str], rows: impl Iterator<Item = T>) -> String {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(header).unwrap();
+    for row in rows {
+        writer.serialize(row).unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+fn to_json_line<T: Serialize>(value: &T) -> String {
+    let mut line = serde_json::to_string(value).unwrap();
+    line.push('\n');
+    line
+}
+
+/// Writes the tracks as a JSON array, one JSON object per line or CSV rows.
+pub fn write_tracks(output: Output, tracks: &[Track], now: DateTime<Utc>) -> String {
+    match output {
+        Output::Json => to_json_line(
+            &tracks
+                .iter()
+                .map(|track| TrackOutput::from(track, now))
+                .collect::<Vec<_>>(),
+        ),
+        Output::Ndjson => tracks
+            .iter()
+            .map(|track| to_json_line(&TrackOutput::from(track, now)))
+            .collect(),
+        Output::Csv | Output::Table => to_csv(
+            CSV_HEADER,
+            tracks.iter().map(|track| CsvRow::from(track, now)),
+        ),
+    }
+}
+
+/// Writes a single track, `null` in JSON when there is none.
+pub fn write_track(output: Output, track: Option<&Track>, now: DateTime<Utc>) -> String {
+    match output {
+        Output::Json => to_json_line(&track.map(|track| TrackOutput::from(track, now))),
+        _ => write_tracks(output, &track.into_iter().cloned().collect::<Vec<_>>(), now),
+    }
+}
+
+const WORKSPACE_HEADER: &[&str] = &["id", "name", "color", "archived", "rate"];
+
+const PROJECT_HEADER: &[&str] = &[
+    "id",
+    "name",
+    "workspace",
+    "color",
+    "client",
+    "archived",
+    "rate",
+];
+
+fn write_rows<T: Serialize>(output: Output, header: &[&str], rows: &[T]) -> String {
+    match output {
+        Output::Json => to_json_line(&rows),
+        Output::Ndjson => rows.iter().map(to_json_line).collect(),
+        Output::Csv | Output::Table => to_csv(header, rows.iter()),
+    }
+}
+
+pub fn write_workspaces(output: Output, workspaces: &[Workspace]) -> String {
+    write_rows(output, WORKSPACE_HEADER, workspaces)
+}
+
+pub fn write_projects(output: Output, projects: &[Project]) -> String {
+    write_rows(output, PROJECT_HEADER, projects)
+}
+
+pub fn write_context(output: Output, context: &Context) -> String {
+    match output {
+        Output::Json | Output::Ndjson => to_json_line(context),
+        Output::Csv | Output::Table => to_csv(&["workspace", "project"], [context].into_iter()),
+    }
+}
+
+/// A line of an invoice, its duration in seconds.
+#[derive(Serialize)]
+struct InvoiceRow<'a> {
+    date: Option<String>,
+    project: &'a str,
+    workspace: &'a str,
+    description: &'a str,
+    duration: i64,
+    rate: String,
+    amount: String,
+    currency: &'a str,
+}
+
+impl<'a> InvoiceRow<'a> {
+    fn from(line: &'a InvoiceLine) -> InvoiceRow<'a> {
+        InvoiceRow {
+            date: line.date.map(|date| date.to_string()),
+            project: &line.project,
+            workspace: &line.workspace,
+            description: &line.description,
+            duration: line.duration.num_seconds(),
+            rate: line.rate.amount.to_string(),
+            amount: line.amount.amount.to_string(),
+            currency: &line.amount.currency,
+        }
+    }
+}
+
+const INVOICE_HEADER: &[&str] = &[
+    "date",
+    "project",
+    "workspace",
+    "description",
+    "duration",
+    "rate",
+    "amount",
+    "currency",
+];
+
+/// Writes the invoice as one JSON object, or its lines as CSV rows.
+pub fn write_invoice(output: Output, invoice: &Invoice) -> String {
+    match output {
+        Output::Json | Output::Ndjson => to_json_line(invoice),
+        Output::Csv | Output::Table => {
+            to_csv(INVOICE_HEADER, invoice.lines.iter().map(InvoiceRow::from))
+        }
+    }
+}
+
+fn report_rows<'a>(
+    groups: &'a [ReportGroup],
+    keys: &mut Vec<String>,
+    rows: &mut Vec<(Vec<String>, &'a ReportGroup)>,
+) {
+    for group in groups.iter() {
+        keys.push(group.key.clone());
+        if group.groups.is_empty() {
+            rows.push((keys.clone(), group));
+        } else {
+            report_rows(&group.groups, keys, rows);
+        }
+        keys.pop();
+    }
+}
+
+/// Writes the report as one JSON object, or as one row per innermost group
+/// with a column for each dimension, the duration in seconds, the rounded one
+/// and the amount of each currency when computed.
+pub fn write_report(output: Output, report: &Report) -> String {
+    let mut rows = vec![];
+    report_rows(&report.groups, &mut vec![], &mut rows);
+    let mut header: Vec<&str> = report.group_by.iter().map(|group| group.title()).collect();
+    header.push("duration");
+    if report.rounded.is_some() {
+        header.push("rounded");
+    }
+    let currencies: Vec<&str> = report
+        .amounts
+        .iter()
+        .map(|money| money.currency.as_str())
+        .collect();
+    header.extend(currencies.iter());
+    let amounts = |group: &ReportGroup| -> Vec<Option<String>> {
+        currencies
+            .iter()
+            .map(|currency| {
+                group
+                    .amounts
+                    .iter()
+                    .find(|money| money.currency == *currency)
+                    .map(|money| money.amount.to_string())
+            })
+            .collect()
+    };
+    match output {
+        Output::Json => to_json_line(report),
+        Output::Ndjson => rows
+            .into_iter()
+            .map(|(keys, group)| {
+                let mut row = serde_json::Map::new();
+                for (title, key) in header.iter().zip(keys) {
+                    row.insert(String::from(*title), serde_json::Value::from(key));
+                }
+                row.insert(
+                    String::from("duration"),
+                    serde_json::Value::from(group.duration.num_seconds()),
+                );
+                if let Some(rounded) = group.rounded {
+                    row.insert(
+                        String::from("rounded"),
+                        serde_json::Value::from(rounded.num_seconds()),
+                    );
+                }
+                for (currency, amount) in currencies.iter().zip(amounts(group)) {
+                    row.insert(String::from(*currency), serde_json::Value::from(amount));
+                }
+                to_json_line(&row)
+            })
+            .collect(),
+        Output::Csv | Output::Table => to_csv(
+            &header,
+            rows.into_iter().map(|(mut keys, group)| {
+                keys.push(group.duration.num_seconds().to_string());
+                keys.extend(
+                    group
+                        .rounded
+                        .map(|rounded| rounded.num_seconds().to_string()),
+                );
+                keys.extend(amounts(group).into_iter().map(Option::unwrap_or_default));
+                keys
+            }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracker::billing::Rates;
+    use tracker::parser::parse_rate;
+    use tracker::report::GroupBy;
+    use tracker::rounding::Rounding;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2022-01-01T{}:00Z", time)
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+    }
+
+    fn create_tracks() -> Vec<Track> {
+        vec![
+            Track::create(
+                String::from("a1"),
+                String::from("Write, docs"),
+                at("09:00"),
+                Some(at("10:30")),
+                String::from("Project1"),
+                String::from("Workspace"),
+            ),
+            Track::create(
+                String::from("b1"),
+                String::from("Review"),
+                at("11:00"),
+                None,
+                String::from("Project2"),
+                String::from("Workspace"),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_write_tracks() {
+        assert_eq!(
+            write_tracks(Output::Csv, &create_tracks(), at("11:30")),
+            "id,name,project,workspace,tags,start,end,duration,state,notes,rate,billable,invoice\n\
+             a1,\"Write, docs\",Project1,Workspace,,2022-01-01T09:00:00Z,2022-01-01T10:30:00Z,5400,stopped,,,true,\n\
+             b1,Review,Project2,Workspace,,2022-01-01T11:00:00Z,,1800,running,,,true,\n"
+        );
+        assert_eq!(
+            write_tracks(Output::Ndjson, &create_tracks()[1..], at("11:30")),
+            "{\"id\":\"b1\",\"name\":\"Review\",\"start\":\"2022-01-01T11:00:00Z\",\"end\":null,\
+             \"project\":\"Project2\",\"workspace\":\"Workspace\",\"tags\":[],\"notes\":\"\",\"pauses\":[],\
+             \"rate\":null,\"billable\":true,\"invoice\":null,\"duration\":1800,\"state\":\"running\"}\n"
+        );
+        assert_eq!(write_track(Output::Json, None, at("11:30")), "null\n");
+    }
+
+    #[test]
+    fn test_write_projects() {
+        let mut project = Project::new(String::from("Website"), String::from("Acme"));
+        project.id = String::from("p1");
+        project.client = Some(String::from("Acme, Inc"));
+        project.rate = Some(parse_rate("95.50 EUR").unwrap());
+        assert_eq!(
+            write_projects(Output::Csv, &[project.clone()]),
+            "id,name,workspace,color,client,archived,rate\n\
+             p1,Website,Acme,,\"Acme, Inc\",false,95.50 EUR\n"
+        );
+        assert_eq!(
+            write_projects(Output::Ndjson, &[project]),
+            "{\"id\":\"p1\",\"name\":\"Website\",\"workspace\":\"Acme\",\"color\":null,\
+             \"client\":\"Acme, Inc\",\"archived\":false,\"rate\":\"95.50 EUR\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let report = Report::build(
+            &create_tracks(),
+            &[GroupBy::Workspace, GroupBy::Project],
+            None,
+            None,
+            at("11:30"),
+            None,
+            None,
+        );
+        assert_eq!(
+            write_report(Output::Csv, &report),
+            "workspace,project,duration\n\
+             Workspace,Project1,5400\n\
+             Workspace,Project2,1800\n"
+        );
+        let mut tracks = create_tracks();
+        tracks[0].rate = Some(parse_rate("100 EUR").unwrap());
+        let report = Report::build(
+            &tracks,
+            &[GroupBy::Project],
+            None,
+            None,
+            at("11:30"),
+            Some(&Rates::default()),
+            None,
+        );
+        assert_eq!(
+            write_report(Output::Csv, &report),
+            "project,duration,EUR\n\
+             Project1,5400,150.00\n\
+             Project2,1800,\n"
+        );
+        assert_eq!(
+            write_report(Output::Ndjson, &report)
+                .lines()
+                .next()
+                .unwrap(),
+            "{\"EUR\":\"150.00\",\"duration\":5400,\"project\":\"Project1\"}"
+        );
+        let rounding = Rounding {
+            increment: chrono::Duration::hours(1),
+            ..Rounding::default()
+        };
+        let report = Report::build(
+            &tracks,
+            &[GroupBy::Project],
+            None,
+            None,
+            at("11:30"),
+            Some(&Rates::default()),
+            Some(&rounding),
+        );
+        assert_eq!(
+            write_report(Output::Csv, &report),
+            "project,duration,rounded,EUR\n\
+             Project1,5400,7200,200.00\n\
+             Project2,1800,3600,\n"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::table::Table;
+use chrono::{DateTime, Duration, Local, Utc};
+use tracker::billing::Money;
+use tracker::model::{Project, Rate, Track, Workspace};
+use tracker::report::{GroupBy, Report, ReportGroup};
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Columns `list` can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Id,
+    Name,
+    Project,
+    Workspace,
+    Tags,
+    Start,
+    End,
+    Duration,
+}
+
+impl Column {
+    pub const ALL: &'static [Column] = &[
+        Column::Id,
+        Column::Name,
+        Column::Project,
+        Column::Workspace,
+        Column::Tags,
+        Column::Start,
+        Column::End,
+        Column::Duration,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Column> {
+        Column::ALL
+            .iter()
+            .copied()
+            .find(|column| column.title() == name)
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::Name => "name",
+            Column::Project => "project",
+            Column::Workspace => "workspace",
+            Column::Tags => "tags",
+            Column::Start => "start",
+            Column::End => "end",
+            Column::Duration => "duration",
+        }
+    }
+}
+
+pub struct ListOptions {
+    pub columns: Vec<Column>,
+    /// Width the rows are truncated to, `None` never truncates.
+    pub width: Option<usize>,
+    pub color: bool,
+}
+
+fn paint(text: String, style: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text
+    }
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(8)]
+}
+
+fn format_end(track: &Track, color: bool) -> String {
+    match track.end {
+        Some(end) => {
+            let start_day = track.start.with_timezone(&Local).date();
+            let end = end.with_timezone(&Local);
+            let days = (end.date() - start_day).num_days();
+            if days > 0 {
+                format!("{}+{}", end.format("%H:%M"), days)
+            } else {
+                end.format("%H:%M").to_string()
+            }
+        }
+        None if track.is_paused() => paint(String::from("paused"), YELLOW, color),
+        None => paint(String::from("running"), GREEN, color),
+    }
+}
+
+fn cell(track: &Track, column: Column, now: DateTime<Utc>, color: bool) -> String {
+    match column {
+        Column::Id => paint(String::from(short_id(&track.id)), DIM, color),
+        Column::Name => track.name.clone(),
+        Column::Project => track.project.clone(),
+        Column::Workspace => track.workspace.clone(),
+        Column::Tags => track.tags.join(", "),
+        Column::Start => track
+            .start
+            .with_timezone(&Local)
+            .format("%H:%M")
+            .to_string(),
+        Column::End => format_end(track, color),
+        Column::Duration => format_duration(track.duration_at(now)),
+    }
+}
+
+/// Renders the tracks as a table, under a heading with the total of each
+/// local day. Consecutive tracks started the same day share a heading.
+pub fn render_tracks(tracks: &[Track], options: &ListOptions, now: DateTime<Utc>) -> String {
+    let headers: Vec<&str> = options.columns.iter().map(Column::title).collect();
+    let mut table = Table::new(&headers);
+    for (index, column) in options.columns.iter().enumerate() {
+        table = match column {
+            Column::Name | Column::Project | Column::Workspace | Column::Tags => {
+                table.shrinkable(index)
+            }
+            Column::Duration => table.align_right(index),
+            _ => table,
+        };
+    }
+    let mut days = tracks.iter().peekable();
+    while let Some(first) = days.next() {
+        let day = first.start.with_timezone(&Local).date();
+        let mut day_tracks = vec![first];
+        while let Some(track) =
+            days.next_if(|track| track.start.with_timezone(&Local).date() == day)
+        {
+            day_tracks.push(track);
+        }
+        let total = day_tracks.iter().fold(Duration::zero(), |total, track| {
+            total + track.duration_at(now)
+        });
+        let heading = format!(
+            "{}  total {}",
+            day.format("%a %Y-%m-%d"),
+            format_duration(total)
+        );
+        table.push_line(paint(heading, BOLD, options.color));
+        for track in day_tracks.into_iter() {
+            table.push(
+                options
+                    .columns
+                    .iter()
+                    .map(|column| cell(track, *column, now, options.color))
+                    .collect(),
+            );
+        }
+    }
+    table.render_within(options.width)
+}
+
+pub const STATUS_FORMAT: &str = "{name} ({project} / {workspace}) {state} since {start}, {elapsed}";
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    format!("{}h{:02}m", minutes / 60, minutes % 60)
+}
+
+/// Fills the `{id}`, `{short_id}`, `{name}`, `{project}`, `{workspace}`,
+/// `{tags}`, `{start}`, `{elapsed}` and `{state}` placeholders of a status
+/// template.
+pub fn render_status(template: &str, track: &Track, now: DateTime<Utc>) -> String {
+    let state = if track.is_paused() {
+        "paused"
+    } else {
+        "running"
+    };
+    template
+        .replace("{id}", &track.id)
+        .replace("{short_id}", &track.id[..track.id.len().min(8)])
+        .replace("{name}", &track.name)
+        .replace("{project}", &track.project)
+        .replace("{workspace}", &track.workspace)
+        .replace("{tags}", &track.tags.join(", "))
+        .replace(
+            "{start}",
+            &track
+                .start
+                .with_timezone(&Local)
+                .format("%H:%M")
+                .to_string(),
+        )
+        .replace("{elapsed}", &format_duration(track.duration_at(now)))
+        .replace("{state}", state)
+}
+
+fn rate(rate: &Option<Rate>) -> String {
+    rate.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn archived(archived: bool) -> String {
+    String::from(if archived { "archived" } else { "" })
+}
+
+pub fn render_workspaces(workspaces: &[Workspace]) -> String {
+    let mut table = Table::new(&["name", "color", "rate", "state"]);
+    for workspace in workspaces.iter() {
+        table.push(vec![
+            workspace.name.clone(),
+            workspace.color.clone().unwrap_or_default(),
+            rate(&workspace.rate),
+            archived(workspace.archived),
+        ]);
+    }
+    table.render()
+}
+
+pub fn render_projects(projects: &[Project]) -> String {
+    let mut table = Table::new(&["workspace", "name", "client", "color", "rate", "state"]);
+    for project in projects.iter() {
+        table.push(vec![
+            project.workspace.clone(),
+            project.name.clone(),
+            project.client.clone().unwrap_or_default(),
+            project.color.clone().unwrap_or_default(),
+            rate(&project.rate),
+            archived(project.archived),
+        ]);
+    }
+    table.render()
+}
+
+/// Cells of the amounts of a group, one per currency of the report.
+fn amount_cells(amounts: &[Money], currencies: &[&str]) -> Vec<String> {
+    currencies
+        .iter()
+        .map(|currency| {
+            amounts
+                .iter()
+                .find(|money| money.currency == *currency)
+                .map(|money| money.amount.to_string())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn push_report_groups(
+    table: &mut Table,
+    groups: &[ReportGroup],
+    depth: usize,
+    dimensions: usize,
+    currencies: &[&str],
+) {
+    for group in groups.iter() {
+        let mut row = vec![String::new(); dimensions];
+        row[depth] = group.key.clone();
+        row.push(format_duration(group.duration));
+        row.extend(group.rounded.map(format_duration));
+        row.extend(amount_cells(&group.amounts, currencies));
+        table.push(row);
+        push_report_groups(table, &group.groups, depth + 1, dimensions, currencies);
+    }
+}
+
+/// One row per group indented by dimension, then the duration, the rounded
+/// duration and, when computed, a column of amounts per currency titled by
+/// its code.
+pub fn render_report(report: &Report) -> String {
+    let currencies: Vec<&str> = report
+        .amounts
+        .iter()
+        .map(|money| money.currency.as_str())
+        .collect();
+    let mut headers: Vec<&str> = report.group_by.iter().map(GroupBy::title).collect();
+    let dimensions = headers.len();
+    headers.push("duration");
+    if report.rounded.is_some() {
+        headers.push("rounded");
+    }
+    headers.extend(currencies.iter());
+    let mut table = Table::new(&headers);
+    for column in dimensions..headers.len() {
+        table = table.align_right(column);
+    }
+    push_report_groups(&mut table, &report.groups, 0, dimensions, &currencies);
+    let mut total = vec![String::new(); dimensions];
+    total[0] = String::from("TOTAL");
+    total.push(format_duration(report.total));
+    total.extend(report.rounded.map(format_duration));
+    total.extend(amount_cells(&report.amounts, &currencies));
+    table.push(total);
+    table.render()
+}
@@ -0,0 +1,180 @@
+enum Row {
+    Cells(Vec<String>),
+    Line(String),
+}
+
+/// Plain text table with columns padded to their widest cell. Cells may hold
+/// ANSI color codes, they are not counted in the widths.
+pub struct Table {
+    headers: Vec<String>,
+    right_aligned: Vec<bool>,
+    shrinkable: Vec<bool>,
+    rows: Vec<Row>,
+}
+
+/// Shrinkable columns are never truncated below this width.
+const MIN_WIDTH: usize = 6;
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Table {
+        Table {
+            headers: headers.iter().map(|header| header.to_uppercase()).collect(),
+            right_aligned: vec![false; headers.len()],
+            shrinkable: vec![false; headers.len()],
+            rows: vec![],
+        }
+    }
+
+    pub fn align_right(mut self, column: usize) -> Table {
+        self.right_aligned[column] = true;
+        self
+    }
+
+    /// Allows truncating the column to fit the table in a width. Cells of
+    /// shrinkable columns must not hold color codes.
+    pub fn shrinkable(mut self, column: usize) -> Table {
+        self.shrinkable[column] = true;
+        self
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(Row::Cells(row));
+    }
+
+    /// Adds a line printed as is, between the rows.
+    pub fn push_line(&mut self, line: String) {
+        self.rows.push(Row::Line(line));
+    }
+
+    pub fn render(&self) -> String {
+        self.render_within(None)
+    }
+
+    /// Renders the table truncating the widest shrinkable columns until the
+    /// rows fit in `max_width` characters.
+    pub fn render_within(&self, max_width: Option<usize>) -> String {
+        let mut widths: Vec<usize> = (0..self.headers.len())
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| match row {
+                        Row::Cells(cells) => Some(visible_width(&cells[column])),
+                        Row::Line(_) => None,
+                    })
+                    .chain([visible_width(&self.headers[column])])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        if let Some(max_width) = max_width {
+            let separators = 2 * widths.len().saturating_sub(1);
+            while widths.iter().sum::<usize>() + separators > max_width {
+                let widest = (0..widths.len())
+                    .filter(|column| self.shrinkable[*column] && widths[*column] > MIN_WIDTH)
+                    .max_by_key(|column| widths[*column]);
+                match widest {
+                    Some(column) => widths[column] -= 1,
+                    None => break,
+                }
+            }
+        }
+        let mut output = String::new();
+        output.push_str(&self.render_cells(&self.headers, &widths));
+        for row in self.rows.iter() {
+            match row {
+                Row::Cells(cells) => output.push_str(&self.render_cells(cells, &widths)),
+                Row::Line(line) => {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+        }
+        output
+    }
+
+    fn render_cells(&self, cells: &[String], widths: &[usize]) -> String {
+        let cells: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| {
+                let cell = truncate(cell, widths[column]);
+                let padding = " ".repeat(widths[column].saturating_sub(visible_width(&cell)));
+                if self.right_aligned[column] {
+                    format!("{}{}", padding, cell)
+                } else {
+                    format!("{}{}", cell, padding)
+                }
+            })
+            .collect();
+        let mut line = String::from(cells.join("  ").trim_end());
+        line.push('\n');
+        line
+    }
+}
+
+/// Number of characters shown for the text, without ANSI escape sequences.
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut escaped = false;
+    for character in text.chars() {
+        match character {
+            '\x1b' => escaped = true,
+            'm' if escaped => escaped = false,
+            _ if escaped => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if visible_width(text) <= width {
+        return String::from(text);
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_table() -> Table {
+        let mut table = Table::new(&["name", "duration"])
+            .shrinkable(0)
+            .align_right(1);
+        table.push(vec![
+            String::from("Write documentation"),
+            String::from("1h30m"),
+        ]);
+        table.push_line(String::from("-"));
+        table.push(vec![
+            String::from("\x1b[32mReview\x1b[0m"),
+            String::from("10h00m"),
+        ]);
+        table
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            create_table().render(),
+            "NAME                 DURATION\n\
+             Write documentation     1h30m\n\
+             -\n\
+             \x1b[32mReview\x1b[0m                 10h00m\n"
+        );
+    }
+
+    #[test]
+    fn test_render_within() {
+        assert_eq!(
+            create_table().render_within(Some(20)),
+            "NAME        DURATION\n\
+             Write doc…     1h30m\n\
+             -\n\
+             \x1b[32mReview\x1b[0m        10h00m\n"
+        );
+    }
+}
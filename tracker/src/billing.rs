@@ -0,0 +1,182 @@
+use crate::model::{Project, Rate, Track, Workspace};
+use chrono::Duration;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// A sum of money in one currency.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: String,
+}
+
+impl Money {
+    /// Adds up the amounts of each currency and rounds them to cents, half
+    /// away from zero, sorted by currency.
+    pub fn totals<'a>(amounts: impl IntoIterator<Item = &'a Money>) -> Vec<Money> {
+        let mut totals: BTreeMap<&str, Decimal> = BTreeMap::new();
+        for money in amounts {
+            *totals.entry(&money.currency).or_default() += money.amount;
+        }
+        totals
+            .into_iter()
+            .map(|(currency, amount)| {
+                let mut amount =
+                    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+                amount.rescale(2);
+                Money {
+                    amount,
+                    currency: String::from(currency),
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+/// The hourly rates of the workspaces and projects. Names are compared
+/// ignoring case, as in the database.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    workspaces: HashMap<String, Rate>,
+    projects: HashMap<(String, String), Rate>,
+}
+
+impl Rates {
+    pub fn new(workspaces: &[Workspace], projects: &[Project]) -> Rates {
+        Rates {
+            workspaces: workspaces
+                .iter()
+                .filter_map(|workspace| {
+                    let rate = workspace.rate.clone()?;
+                    Some((workspace.name.to_lowercase(), rate))
+                })
+                .collect(),
+            projects: projects
+                .iter()
+                .filter_map(|project| {
+                    let rate = project.rate.clone()?;
+                    let key = (
+                        project.workspace.to_lowercase(),
+                        project.name.to_lowercase(),
+                    );
+                    Some((key, rate))
+                })
+                .collect(),
+        }
+    }
+
+    /// The most specific rate of a track: its own, else the one of its
+    /// project, else the one of its workspace.
+    pub fn rate_of<'a>(&'a self, track: &'a Track) -> Option<&'a Rate> {
+        let workspace = track.workspace.to_lowercase();
+        track
+            .rate
+            .as_ref()
+            .or_else(|| {
+                self.projects
+                    .get(&(workspace.clone(), track.project.to_lowercase()))
+            })
+            .or_else(|| self.workspaces.get(&workspace))
+    }
+
+    /// What `duration` of a track earns, nothing when it is not billable or
+    /// has no rate. Not rounded.
+    pub fn amount(&self, track: &Track, duration: Duration) -> Option<Money> {
+        if !track.billable {
+            return None;
+        }
+        self.rate_of(track).map(|rate| Money {
+            amount: rate.amount_for(duration),
+            currency: rate.currency.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn create_track(project: &str) -> Track {
+        Track::create(
+            String::from("a1"),
+            String::from("Write docs"),
+            "2022-01-01T09:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            None,
+            String::from(project),
+            String::from("Acme"),
+        )
+    }
+
+    fn create_rates() -> Rates {
+        let mut workspace = Workspace::new(String::from("ACME"));
+        workspace.rate = Some(Rate::new(Decimal::from(80), "EUR"));
+        let mut project = Project::new(String::from("website"), String::from("acme"));
+        project.rate = Some(Rate::new(Decimal::from(100), "EUR"));
+        Rates::new(&[workspace], &[project])
+    }
+
+    #[test]
+    fn test_most_specific_rate_wins() {
+        let rates = create_rates();
+        let mut track = create_track("Website");
+        assert_eq!(
+            rates.rate_of(&track).map(|rate| rate.amount),
+            Some(Decimal::from(100))
+        );
+        track.rate = Some(Rate::new(Decimal::from(120), "USD"));
+        assert_eq!(
+            rates.rate_of(&track).map(|rate| rate.amount),
+            Some(Decimal::from(120))
+        );
+        let track = create_track("Support");
+        assert_eq!(
+            rates.rate_of(&track).map(|rate| rate.amount),
+            Some(Decimal::from(80))
+        );
+        assert_eq!(Rates::default().rate_of(&track), None);
+    }
+
+    #[test]
+    fn test_amount_of_billable_tracks() {
+        let rates = create_rates();
+        let mut track = create_track("Website");
+        assert_eq!(
+            rates.amount(&track, Duration::minutes(45)),
+            Some(Money {
+                amount: Decimal::from(75),
+                currency: String::from("EUR"),
+            })
+        );
+        track.billable = false;
+        assert_eq!(rates.amount(&track, Duration::minutes(45)), None);
+    }
+
+    #[test]
+    fn test_money_totals() {
+        let money = |amount: i64, currency: &str| Money {
+            amount: Decimal::new(amount, 3),
+            currency: String::from(currency),
+        };
+        assert_eq!(
+            Money::totals(&[
+                money(10_004, "USD"),
+                money(5_005, "EUR"),
+                money(10_004, "USD")
+            ]),
+            vec![money(5_010, "EUR"), money(20_010, "USD")]
+        );
+        assert_eq!(
+            Money::totals(&[money(90_000, "EUR")])[0].to_string(),
+            "90.00 EUR"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use crate::error::{Error, Result};
+use crate::parser::parse_duration;
+use crate::rounding::{Rounding, RoundingMode, RoundingScope};
+use chrono::Duration;
+use serde::{Deserialize, Deserializer};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DB_ENV: &str = "TRACKER_DB";
+pub const CONFIG_ENV: &str = "TRACKER_CONFIG";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Database {
+    File(PathBuf),
+    Memory,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub database: Database,
+    /// How reports and invoices round durations by default.
+    pub rounding: Option<Rounding>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    db: Option<PathBuf>,
+    rounding: Option<RoundingSection>,
+}
+
+/// The `[rounding]` table, with durations written like `15m`.
+#[derive(Debug, Deserialize)]
+struct RoundingSection {
+    #[serde(default, deserialize_with = "duration")]
+    increment: Option<Duration>,
+    #[serde(default)]
+    mode: RoundingMode,
+    #[serde(default)]
+    per: RoundingScope,
+    #[serde(default, deserialize_with = "duration")]
+    minimum: Option<Duration>,
+}
+
+impl From<RoundingSection> for Rounding {
+    fn from(section: RoundingSection) -> Rounding {
+        Rounding {
+            increment: section.increment.unwrap_or_else(Duration::zero),
+            mode: section.mode,
+            per: section.per,
+            minimum: section.minimum.unwrap_or_else(Duration::zero),
+        }
+    }
+}
+
+fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Resolves where the tracks are stored. An explicit database wins over the
+/// `TRACKER_DB` variable, which wins over the `db` key of the config file,
+/// falling back to `tracker.sqlite` in the user data directory.
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    database: Option<Database>,
+    config_file: Option<PathBuf>,
+    ignore_env: bool,
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn database_path<P: Into<PathBuf>>(mut self, path: P) -> ConfigBuilder {
+        self.database = Some(Database::File(path.into()));
+        self
+    }
+
+    pub fn in_memory(mut self) -> ConfigBuilder {
+        self.database = Some(Database::Memory);
+        self
+    }
+
+    pub fn config_file<P: Into<PathBuf>>(mut self, path: P) -> ConfigBuilder {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    pub fn ignore_env(mut self) -> ConfigBuilder {
+        self.ignore_env = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Config> {
+        let file = self.read_config_file()?;
+        let database = match self.database.clone() {
+            Some(database) => database,
+            None => self.resolve_database(file.db)?,
+        };
+        Ok(Config {
+            database,
+            rounding: file.rounding.map(Rounding::from),
+        })
+    }
+
+    fn resolve_database(&self, configured: Option<PathBuf>) -> Result<Database> {
+        if !self.ignore_env {
+            if let Some(path) = env::var_os(DB_ENV).filter(|path| !path.is_empty()) {
+                return Ok(Database::File(PathBuf::from(path)));
+            }
+        }
+        if let Some(path) = configured {
+            return Ok(Database::File(path));
+        }
+        default_database_path().map(Database::File)
+    }
+
+    fn read_config_file(&self) -> Result<ConfigFile> {
+        let path = match &self.config_file {
+            Some(path) => path.clone(),
+            None => match self.env_config_file().or_else(default_config_path) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(ConfigFile::default()),
+            },
+        };
+        parse_config_file(&path)
+    }
+
+    fn env_config_file(&self) -> Option<PathBuf> {
+        if self.ignore_env {
+            return None;
+        }
+        env::var_os(CONFIG_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }
+}
+
+fn parse_config_file(path: &Path) -> Result<ConfigFile> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| Error::Config(format!("{}: {}", path.display(), error)))?;
+    toml::from_str(&content)
+        .map_err(|error| Error::Config(format!("{}: {}", path.display(), error)))
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("tracker").join("config.toml"))
+}
+
+pub fn default_database_path() -> Result<PathBuf> {
+    dirs::data_dir()
+        .map(|dir| dir.join("tracker").join("tracker.sqlite"))
+        .ok_or_else(|| Error::Config(String::from("could not find the user data directory")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, content: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "tracker-config-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_explicit_database_wins() {
+        let path = write_config("explicit", "db = \"/tmp/from-file.sqlite\"");
+        let config = Config::builder()
+            .config_file(&path)
+            .database_path("/tmp/explicit.sqlite")
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.database,
+            Database::File(PathBuf::from("/tmp/explicit.sqlite"))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_database() {
+        let config = Config::builder().in_memory().build().unwrap();
+        assert_eq!(config.database, Database::Memory);
+    }
+
+    #[test]
+    fn test_database_from_config_file() {
+        let path = write_config("file", "db = \"/tmp/from-file.sqlite\"");
+        let config = Config::builder()
+            .ignore_env()
+            .config_file(&path)
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.database,
+            Database::File(PathBuf::from("/tmp/from-file.sqlite"))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rounding_from_config_file() {
+        let path = write_config(
+            "rounding",
+            "[rounding]\nincrement = \"15m\"\nmode = \"up\"\nminimum = \"30m\"\n",
+        );
+        let config = Config::builder()
+            .in_memory()
+            .config_file(&path)
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.rounding,
+            Some(Rounding {
+                increment: Duration::minutes(15),
+                mode: RoundingMode::Up,
+                per: RoundingScope::Entry,
+                minimum: Duration::minutes(30),
+            })
+        );
+        fs::remove_file(&path).unwrap();
+        let path = write_config("rounding-invalid", "[rounding]\nincrement = \"soon\"\n");
+        let result = Config::builder().in_memory().config_file(&path).build();
+        assert!(matches!(result, Err(Error::Config(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_config_file() {
+        let path = write_config("invalid", "db = ");
+        let result = Config::builder().ignore_env().config_file(&path).build();
+        assert!(matches!(result, Err(Error::Config(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
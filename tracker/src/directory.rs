@@ -0,0 +1,215 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DIRECTORY_FILE: &str = ".tracker.toml";
+
+/// Defaults for the tracks started inside a directory tree, read from the
+/// closest `.tracker.toml` or guessed from the git repository.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryDefaults {
+    pub project: Option<String>,
+    pub workspace: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The file the defaults were read from, `None` when guessed from git.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// Finds the defaults for `start`: the first `.tracker.toml` walking up the
+/// directories, else a project named after the git repository.
+pub fn discover(start: &Path) -> Result<Option<DirectoryDefaults>> {
+    if let Some(path) = find_upwards(start, DIRECTORY_FILE) {
+        return read(&path).map(Some);
+    }
+    Ok(git_project(start).map(|project| DirectoryDefaults {
+        project: Some(project),
+        ..DirectoryDefaults::default()
+    }))
+}
+
+pub fn read(path: &Path) -> Result<DirectoryDefaults> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| Error::Config(format!("{}: {}", path.display(), error)))?;
+    let defaults: DirectoryDefaults = toml::from_str(&content)
+        .map_err(|error| Error::Config(format!("{}: {}", path.display(), error)))?;
+    Ok(DirectoryDefaults {
+        path: Some(path.to_path_buf()),
+        ..defaults
+    })
+}
+
+/// Writes the defaults to the `.tracker.toml` of `directory`, which must not
+/// have one unless `force`.
+pub fn write(directory: &Path, defaults: &DirectoryDefaults, force: bool) -> Result<PathBuf> {
+    let path = directory.join(DIRECTORY_FILE);
+    if path.exists() && !force {
+        return Err(Error::AlreadyExists(path.display().to_string()));
+    }
+    let content = toml::to_string(defaults).map_err(|error| Error::Config(error.to_string()))?;
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+fn find_upwards(start: &Path, name: &str) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|directory| directory.join(name))
+        .find(|path| path.exists())
+}
+
+/// Name of the git repository holding `start`, from the url of its `origin`
+/// remote or else from its directory.
+pub fn git_project(start: &Path) -> Option<String> {
+    let dot_git = find_upwards(start, ".git")?;
+    let url = git_dir(&dot_git)
+        .and_then(|git_dir| fs::read_to_string(git_dir.join("config")).ok())
+        .and_then(|config| origin_url(&config));
+    match url {
+        Some(url) => repository_name(&url),
+        None => dot_git
+            .parent()
+            .and_then(|root| root.file_name())
+            .map(|name| name.to_string_lossy().into_owned()),
+    }
+}
+
+/// The directory holding the git config, following the `gitdir:` link of
+/// worktrees and submodules.
+fn git_dir(dot_git: &Path) -> Option<PathBuf> {
+    if dot_git.is_dir() {
+        return Some(dot_git.to_path_buf());
+    }
+    let link = fs::read_to_string(dot_git).ok()?;
+    let git_dir = dot_git
+        .parent()?
+        .join(link.trim().strip_prefix("gitdir:")?.trim());
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => Some(git_dir.join(common.trim())),
+        Err(_) => Some(git_dir),
+    }
+}
+
+fn origin_url(config: &str) -> Option<String> {
+    let mut in_origin = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_origin = line == "[remote \"origin\"]";
+        } else if in_origin {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "url" {
+                    return Some(String::from(value.trim()));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Last part of a remote url without `.git`, e.g. `tracker` for
+/// `git@github.com:felipedasilva/tracker.git`.
+fn repository_name(url: &str) -> Option<String> {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()?
+        .trim_end_matches(".git");
+    if name.is_empty() {
+        None
+    } else {
+        Some(String::from(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn create_directory(name: &str) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("tracker-directory-{}-{}", name, std::process::id()));
+        fs::create_dir_all(path.join("src").join("nested")).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_discover_file_upwards() {
+        let root = create_directory("file");
+        fs::write(
+            root.join(DIRECTORY_FILE),
+            "project = \"Website\"\nworkspace = \"Acme\"\ntags = [\"client\"]\n",
+        )
+        .unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        assert_eq!(
+            discover(&root.join("src").join("nested")).unwrap(),
+            Some(DirectoryDefaults {
+                project: Some(String::from("Website")),
+                workspace: Some(String::from("Acme")),
+                tags: vec![String::from("client")],
+                path: Some(root.join(DIRECTORY_FILE)),
+            })
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_discover_git_remote() {
+        let root = create_directory("git");
+        fs::create_dir(root.join(".git")).unwrap();
+        assert_eq!(
+            git_project(&root.join("src")),
+            Some(format!("tracker-directory-git-{}", std::process::id()))
+        );
+        fs::write(
+            root.join(".git").join("config"),
+            "[core]\n\tbare = false\n[remote \"upstream\"]\n\turl = https://example.com/other.git\n\
+             [remote \"origin\"]\n\turl = git@github.com:felipedasilva/tracker.git\n",
+        )
+        .unwrap();
+        assert_eq!(
+            discover(&root.join("src")).unwrap(),
+            Some(DirectoryDefaults {
+                project: Some(String::from("tracker")),
+                ..DirectoryDefaults::default()
+            })
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_repository_name() {
+        assert_eq!(
+            repository_name("https://github.com/felipedasilva/tracker/"),
+            Some(String::from("tracker"))
+        );
+        assert_eq!(
+            repository_name("/srv/git/tracker.git"),
+            Some(String::from("tracker"))
+        );
+        assert_eq!(repository_name(""), None);
+    }
+
+    #[test]
+    fn test_write_refuses_existing_file() {
+        let root = create_directory("write");
+        let defaults = DirectoryDefaults {
+            project: Some(String::from("Website")),
+            workspace: None,
+            tags: vec![],
+            path: None,
+        };
+        let path = write(&root, &defaults, false).unwrap();
+        assert_eq!(read(&path).unwrap().project, defaults.project);
+        assert_eq!(read(&path).unwrap().path, Some(path.clone()));
+        assert_eq!(
+            write(&root, &defaults, false).unwrap_err(),
+            Error::AlreadyExists(path.display().to_string())
+        );
+        assert!(write(&root, &defaults, true).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
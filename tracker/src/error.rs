@@ -0,0 +1,88 @@
+use crate::model::Track;
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    NotFound(String),
+    NoActiveTrack,
+    Storage(String),
+    InvalidTimestamp(String),
+    Config(String),
+    UnsupportedSchema(i64, i64),
+    AmbiguousId(String),
+    InvalidInterval(DateTime<Utc>, DateTime<Utc>),
+    Overlap(Vec<Track>),
+    InvalidDuration(String),
+    AlreadyPaused(String),
+    Io(String),
+    Import(String),
+    UnknownWorkspace(String),
+    UnknownProject(String, String),
+    AlreadyExists(String),
+    Archived(String),
+    NoContext(String),
+    InvalidRate(String),
+    MissingRate(String),
+    Template(String),
+    NothingToInvoice(String),
+    InvalidTax(String),
+    NotPaused(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(id) => write!(f, "track {} not found", id),
+            Error::NoActiveTrack => write!(f, "there is no running track"),
+            Error::Storage(message) => write!(f, "storage error: {}", message),
+            Error::InvalidTimestamp(value) => write!(f, "invalid timestamp: {}", value),
+            Error::Config(message) => write!(f, "configuration error: {}", message),
+            Error::UnsupportedSchema(found, supported) => write!(
+                f,
+                "database schema version {} is newer than the supported version {}",
+                found, supported
+            ),
+            Error::AmbiguousId(prefix) => write!(f, "id prefix {} matches several tracks", prefix),
+            Error::InvalidInterval(start, end) => {
+                write!(f, "end {} is not after start {}", end, start)
+            }
+            Error::Overlap(tracks) => write!(f, "overlaps {} existing tracks", tracks.len()),
+            Error::InvalidDuration(value) => write!(f, "invalid duration: {}", value),
+            Error::AlreadyPaused(name) => write!(f, "track {} is already paused", name),
+            Error::Io(message) => write!(f, "input/output error: {}", message),
+            Error::Import(message) => write!(f, "import error: {}", message),
+            Error::UnknownWorkspace(name) => write!(f, "workspace {} not found", name),
+            Error::UnknownProject(name, workspace) => {
+                write!(f, "project {} not found in workspace {}", name, workspace)
+            }
+            Error::AlreadyExists(name) => write!(f, "{} already exists", name),
+            Error::Archived(name) => write!(f, "{} is archived", name),
+            Error::NoContext(what) => write!(f, "no {} given or in use", what),
+            Error::InvalidRate(value) => write!(f, "invalid rate: {}", value),
+            Error::MissingRate(what) => write!(f, "no hourly rate for {}", what),
+            Error::Template(message) => write!(f, "template error: {}", message),
+            Error::NothingToInvoice(client) => {
+                write!(f, "nothing to invoice for client {}", client)
+            }
+            Error::InvalidTax(value) => write!(f, "invalid tax: {}", value),
+            Error::NotPaused(name) => write!(f, "track {} is not paused", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sqlite::Error> for Error {
+    fn from(error: sqlite::Error) -> Self {
+        Error::Storage(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
@@ -0,0 +1,283 @@
+use crate::error::{Error, Result};
+use crate::model::Track;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::io::Write;
+
+/// Writes tracks in a format other tools understand. Running tracks are
+/// exported as if they ended at `now`.
+pub trait Exporter {
+    fn export(&self, tracks: &[Track], now: DateTime<Utc>, writer: &mut dyn Write) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Ics,
+}
+
+impl ExportFormat {
+    pub fn exporter(&self) -> Box<dyn Exporter> {
+        match self {
+            ExportFormat::Csv => Box::new(CsvExporter),
+            ExportFormat::Json => Box::new(JsonExporter),
+            ExportFormat::Ics => Box::new(IcsExporter),
+        }
+    }
+}
+
+/// Whether the track is `stopped`, `paused` or `running`.
+pub fn state(track: &Track) -> &'static str {
+    if !track.is_tracking() {
+        "stopped"
+    } else if track.is_paused() {
+        "paused"
+    } else {
+        "running"
+    }
+}
+
+/// Columns of `CsvRow`.
+pub const CSV_HEADER: &[&str] = &[
+    "id",
+    "name",
+    "project",
+    "workspace",
+    "tags",
+    "start",
+    "end",
+    "duration",
+    "state",
+    "notes",
+    "rate",
+    "billable",
+    "invoice",
+];
+
+/// A track as a CSV row, shared by the exporter and `--output csv`: the
+/// fields of the JSON output without the pauses, with the `tags` separated by
+/// commas and the `duration` in seconds until now.
+#[derive(Serialize)]
+pub struct CsvRow<'a> {
+    id: &'a str,
+    name: &'a str,
+    project: &'a str,
+    workspace: &'a str,
+    tags: String,
+    start: String,
+    end: Option<String>,
+    duration: i64,
+    state: &'static str,
+    notes: &'a str,
+    rate: Option<String>,
+    billable: bool,
+    invoice: Option<&'a str>,
+}
+
+impl<'a> CsvRow<'a> {
+    pub fn from(track: &'a Track, now: DateTime<Utc>) -> CsvRow<'a> {
+        CsvRow {
+            id: &track.id,
+            name: &track.name,
+            project: &track.project,
+            workspace: &track.workspace,
+            tags: track.tags.join(","),
+            start: rfc3339(track.start),
+            end: track.end.map(rfc3339),
+            duration: track.duration_at(now).num_seconds(),
+            state: state(track),
+            notes: &track.notes,
+            rate: track.rate.as_ref().map(ToString::to_string),
+            billable: track.billable,
+            invoice: track.invoice.as_deref(),
+        }
+    }
+}
+
+/// One `CsvRow` per track under the `CSV_HEADER`.
+pub struct CsvExporter;
+
+impl Exporter for CsvExporter {
+    fn export(&self, tracks: &[Track], now: DateTime<Utc>, writer: &mut dyn Write) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        let csv_error = |error: csv::Error| Error::Io(error.to_string());
+        writer.write_record(CSV_HEADER).map_err(csv_error)?;
+        for track in tracks.iter() {
+            writer
+                .serialize(CsvRow::from(track, now))
+                .map_err(csv_error)?;
+        }
+        Ok(writer.flush()?)
+    }
+}
+
+/// A JSON array of tracks, in the format `tracker import` reads back.
+pub struct JsonExporter;
+
+impl Exporter for JsonExporter {
+    fn export(&self, tracks: &[Track], _: DateTime<Utc>, writer: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, tracks)
+            .map_err(|error| Error::Io(error.to_string()))?;
+        Ok(writeln!(writer)?)
+    }
+}
+
+/// An iCalendar file with one event per track, categorized by project,
+/// workspace and tags and described by the notes.
+pub struct IcsExporter;
+
+impl Exporter for IcsExporter {
+    fn export(&self, tracks: &[Track], now: DateTime<Utc>, writer: &mut dyn Write) -> Result<()> {
+        let mut lines = vec![
+            String::from("BEGIN:VCALENDAR"),
+            String::from("VERSION:2.0"),
+            String::from("PRODID:-//tracker//tracker//EN"),
+        ];
+        for track in tracks.iter() {
+            lines.push(String::from("BEGIN:VEVENT"));
+            lines.push(format!("UID:{}@tracker", track.id));
+            lines.push(format!("DTSTAMP:{}", ics_time(now)));
+            lines.push(format!("DTSTART:{}", ics_time(track.start)));
+            lines.push(format!("DTEND:{}", ics_time(track.end.unwrap_or(now))));
+            lines.push(format!("SUMMARY:{}", ics_text(&track.name)));
+            if !track.notes.is_empty() {
+                lines.push(format!("DESCRIPTION:{}", ics_text(track.notes.trim_end())));
+            }
+            let categories: Vec<String> = [&track.project, &track.workspace]
+                .into_iter()
+                .chain(track.tags.iter())
+                .map(|category| ics_text(category))
+                .collect();
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+            lines.push(String::from("END:VEVENT"));
+        }
+        lines.push(String::from("END:VCALENDAR"));
+        for line in lines.iter() {
+            write!(writer, "{}\r\n", fold(line))?;
+        }
+        Ok(())
+    }
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn ics_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits lines longer than 75 bytes as RFC 5545 requires, continuation
+/// lines start with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(character);
+        length += character.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2022-01-01T{}:00Z", time)
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+    }
+
+    fn create_tracks() -> Vec<Track> {
+        let mut tracks = vec![
+            Track::create(
+                String::from("a1"),
+                String::from("Write, docs"),
+                at("09:00"),
+                Some(at("10:30")),
+                String::from("Project1"),
+                String::from("Workspace"),
+            ),
+            Track::create(
+                String::from("b1"),
+                String::from("Review"),
+                at("11:00"),
+                None,
+                String::from("Project2"),
+                String::from("Workspace"),
+            ),
+        ];
+        tracks[0].set_tags(vec![String::from("docs"), String::from("billable")]);
+        tracks[0].notes = String::from("[09:30] first draft\n[10:15] examples\n");
+        tracks
+    }
+
+    fn export(format: ExportFormat, tracks: &[Track]) -> String {
+        let mut output = vec![];
+        format
+            .exporter()
+            .export(tracks, at("11:30"), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_export_csv() {
+        assert_eq!(
+            export(ExportFormat::Csv, &create_tracks()),
+            "id,name,project,workspace,tags,start,end,duration,state,notes,rate,billable,invoice\n\
+             a1,\"Write, docs\",Project1,Workspace,\"billable,docs\",2022-01-01T09:00:00Z,2022-01-01T10:30:00Z,5400,stopped,\"[09:30] first draft\n[10:15] examples\n\",,true,\n\
+             b1,Review,Project2,Workspace,,2022-01-01T11:00:00Z,,1800,running,,,true,\n"
+        );
+    }
+
+    #[test]
+    fn test_export_json() {
+        let tracks = create_tracks();
+        let json = export(ExportFormat::Json, &tracks);
+        assert_eq!(serde_json::from_str::<Vec<Track>>(&json).unwrap(), tracks);
+    }
+
+    #[test]
+    fn test_export_ics() {
+        assert_eq!(
+            export(ExportFormat::Ics, &create_tracks()[..1]),
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//tracker//tracker//EN\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:a1@tracker\r\n\
+             DTSTAMP:20220101T113000Z\r\n\
+             DTSTART:20220101T090000Z\r\n\
+             DTEND:20220101T103000Z\r\n\
+             SUMMARY:Write\\, docs\r\n\
+             DESCRIPTION:[09:30] first draft\\n[10:15] examples\r\n\
+             CATEGORIES:Project1,Workspace,billable,docs\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn test_fold_long_lines() {
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|part| part.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+}
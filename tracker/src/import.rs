@@ -0,0 +1,505 @@
+use crate::error::{Error, Result};
+use crate::model::Track;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+/// Reads tracks written by another time tracker. Intervals still running in
+/// the source are skipped, the tracks get new ids unless the source has them.
+pub trait Importer {
+    fn import(&self, input: &str) -> Result<Vec<Track>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    TogglCsv,
+    ClockifyCsv,
+    Timewarrior,
+    Org,
+    TrackerJson,
+}
+
+/// Project and workspace given to the tracks whose source has none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportDefaults {
+    pub project: String,
+    pub workspace: String,
+}
+
+impl ImportFormat {
+    /// Local dates and times of the source are read in `timezone`.
+    pub fn importer<Tz: TimeZone + 'static>(
+        &self,
+        defaults: ImportDefaults,
+        timezone: Tz,
+    ) -> Box<dyn Importer> {
+        match self {
+            ImportFormat::TogglCsv | ImportFormat::ClockifyCsv => {
+                Box::new(CsvImporter { defaults, timezone })
+            }
+            ImportFormat::Timewarrior => Box::new(TimewarriorImporter { defaults }),
+            ImportFormat::Org => Box::new(OrgImporter { defaults, timezone }),
+            ImportFormat::TrackerJson => Box::new(TrackerJsonImporter),
+        }
+    }
+}
+
+fn or_default(value: &str, default: &str) -> String {
+    String::from(if value.is_empty() { default } else { value })
+}
+
+fn local_to_utc<Tz: TimeZone>(timezone: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn stopped_track(
+    name: String,
+    project: String,
+    workspace: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    line: usize,
+) -> Result<Track> {
+    if end <= start {
+        return Err(Error::Import(format!("line {}: end is before start", line)));
+    }
+    Ok(Track::new_track(name, project, workspace, start, Some(end)))
+}
+
+/// Toggl and Clockify detailed reports: `Description` becomes the name,
+/// `Project` the project, `Client` the workspace and `Tags` the tags.
+struct CsvImporter<Tz: TimeZone> {
+    defaults: ImportDefaults,
+    timezone: Tz,
+}
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"];
+const TIME_FORMATS: &[&str] = &["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+
+impl<Tz: TimeZone> CsvImporter<Tz> {
+    fn parse_time(&self, date: &str, time: &str, line: usize) -> Result<DateTime<Utc>> {
+        let date = DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(date.trim(), format).ok());
+        let time = TIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(&time.trim().to_uppercase(), format).ok());
+        date.zip(time)
+            .and_then(|(date, time)| local_to_utc(&self.timezone, date.and_time(time)))
+            .ok_or_else(|| Error::Import(format!("line {}: invalid date or time", line)))
+    }
+}
+
+impl<Tz: TimeZone> Importer for CsvImporter<Tz> {
+    fn import(&self, input: &str) -> Result<Vec<Track>> {
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        let headers = reader
+            .headers()
+            .map_err(|error| Error::Import(error.to_string()))?
+            .clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+                .ok_or_else(|| Error::Import(format!("missing column {}", name)))
+        };
+        let name = column("Description")?;
+        let project = column("Project")?;
+        let workspace = column("Client").ok();
+        let tags = column("Tags").ok();
+        let start_date = column("Start date")?;
+        let start_time = column("Start time")?;
+        let end_date = column("End date")?;
+        let end_time = column("End time")?;
+        let mut tracks = vec![];
+        for (index, record) in reader.records().enumerate() {
+            let line = index + 2;
+            let record = record.map_err(|error| Error::Import(error.to_string()))?;
+            let field = |column: usize| record.get(column).unwrap_or("").trim();
+            let mut track = stopped_track(
+                String::from(field(name)),
+                or_default(field(project), &self.defaults.project),
+                or_default(workspace.map_or("", field), &self.defaults.workspace),
+                self.parse_time(field(start_date), field(start_time), line)?,
+                self.parse_time(field(end_date), field(end_time), line)?,
+                line,
+            )?;
+            track.set_tags(
+                tags.map_or("", field)
+                    .split(',')
+                    .map(String::from)
+                    .collect(),
+            );
+            tracks.push(track);
+        }
+        Ok(tracks)
+    }
+}
+
+/// Timewarrior data files, one `inc START - END # TAGS` line per interval in
+/// UTC. The tags are kept and joined to name the track.
+struct TimewarriorImporter {
+    defaults: ImportDefaults,
+}
+
+fn parse_timewarrior_time(time: &str, line: usize) -> Result<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(time, "%Y%m%dT%H%M%SZ")
+        .map(|time| DateTime::from_utc(time, Utc))
+        .map_err(|_| Error::Import(format!("line {}: invalid time {}", line, time)))
+}
+
+fn parse_timewarrior_tags(tags: &str) -> Vec<String> {
+    let mut parsed = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for character in tags.chars() {
+        match character {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if !current.is_empty() {
+                    parsed.push(current.clone());
+                    current.clear();
+                }
+            }
+            _ => current.push(character),
+        }
+    }
+    if !current.is_empty() {
+        parsed.push(current);
+    }
+    parsed
+}
+
+impl Importer for TimewarriorImporter {
+    fn import(&self, input: &str) -> Result<Vec<Track>> {
+        let mut tracks = vec![];
+        for (index, text) in input.lines().enumerate() {
+            let line = index + 1;
+            let text = text.trim();
+            let interval = match text.strip_prefix("inc ") {
+                Some(interval) => interval,
+                None if text.is_empty() => continue,
+                None => return Err(Error::Import(format!("line {}: expected inc", line))),
+            };
+            let (times, tags) = interval.split_once(" # ").unwrap_or((interval, ""));
+            let (start, end) = match times.trim().split_once(" - ") {
+                Some((start, end)) => (start.trim(), end.trim()),
+                None => continue,
+            };
+            let tags = parse_timewarrior_tags(tags);
+            let mut track = stopped_track(
+                tags.join(" "),
+                self.defaults.project.clone(),
+                self.defaults.workspace.clone(),
+                parse_timewarrior_time(start, line)?,
+                parse_timewarrior_time(end, line)?,
+                line,
+            )?;
+            track.set_tags(tags);
+            tracks.push(track);
+        }
+        Ok(tracks)
+    }
+}
+
+/// Org-mode `CLOCK:` lines. The enclosing heading names the track and gives
+/// its tags, the top level heading above it is the project.
+struct OrgImporter<Tz: TimeZone> {
+    defaults: ImportDefaults,
+    timezone: Tz,
+}
+
+const TODO_KEYWORDS: &[&str] = &[
+    "TODO",
+    "NEXT",
+    "WAIT",
+    "WAITING",
+    "HOLD",
+    "DONE",
+    "CANCELLED",
+];
+
+/// Splits a heading into its title, without the TODO keyword and priority,
+/// and its `:tags:`.
+fn org_heading(heading: &str) -> (String, Vec<String>) {
+    let mut tags = vec![];
+    let mut words: Vec<&str> = heading.split_whitespace().collect();
+    if words
+        .first()
+        .is_some_and(|word| TODO_KEYWORDS.contains(word))
+    {
+        words.remove(0);
+    }
+    if words
+        .first()
+        .is_some_and(|word| word.starts_with("[#") && word.ends_with(']'))
+    {
+        words.remove(0);
+    }
+    if words
+        .last()
+        .is_some_and(|word| word.len() > 1 && word.starts_with(':') && word.ends_with(':'))
+    {
+        tags = words
+            .pop()
+            .unwrap_or("")
+            .split(':')
+            .map(String::from)
+            .collect();
+    }
+    (words.join(" "), tags)
+}
+
+impl<Tz: TimeZone> OrgImporter<Tz> {
+    /// Reads an org timestamp such as `[2022-01-03 Mon 09:00]`.
+    fn parse_time(&self, timestamp: &str, line: usize) -> Result<DateTime<Utc>> {
+        let invalid = || Error::Import(format!("line {}: invalid timestamp {}", line, timestamp));
+        let inner = timestamp
+            .trim()
+            .strip_prefix('[')
+            .and_then(|timestamp| timestamp.strip_suffix(']'))
+            .ok_or_else(invalid)?;
+        let fields: Vec<&str> = inner.split_whitespace().collect();
+        let date = fields
+            .first()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+        let time = fields
+            .last()
+            .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok());
+        date.zip(time)
+            .and_then(|(date, time)| local_to_utc(&self.timezone, date.and_time(time)))
+            .ok_or_else(invalid)
+    }
+}
+
+impl<Tz: TimeZone> Importer for OrgImporter<Tz> {
+    fn import(&self, input: &str) -> Result<Vec<Track>> {
+        let mut headings: Vec<(String, Vec<String>)> = vec![];
+        let mut tracks = vec![];
+        for (index, text) in input.lines().enumerate() {
+            let line = index + 1;
+            let level = text
+                .chars()
+                .take_while(|character| *character == '*')
+                .count();
+            if level > 0 && text[level..].starts_with(' ') {
+                headings.truncate(level - 1);
+                headings.resize(level - 1, (String::new(), vec![]));
+                headings.push(org_heading(&text[level..]));
+                continue;
+            }
+            let clock = match text.trim().strip_prefix("CLOCK:") {
+                Some(clock) => clock,
+                None => continue,
+            };
+            let interval = clock.split("=>").next().unwrap_or("").trim();
+            let (start, end) = match interval.split_once("--") {
+                Some(interval) => interval,
+                None => continue,
+            };
+            let (name, tags) = headings
+                .last()
+                .ok_or_else(|| Error::Import(format!("line {}: clock outside a heading", line)))?;
+            let project = match headings.first() {
+                Some((project, _)) if headings.len() > 1 => project.clone(),
+                _ => self.defaults.project.clone(),
+            };
+            let mut track = stopped_track(
+                name.clone(),
+                project,
+                self.defaults.workspace.clone(),
+                self.parse_time(start, line)?,
+                self.parse_time(end, line)?,
+                line,
+            )?;
+            track.set_tags(tags.clone());
+            tracks.push(track);
+        }
+        Ok(tracks)
+    }
+}
+
+/// The JSON written by `tracker export --format json`, ids are kept.
+struct TrackerJsonImporter;
+
+impl Importer for TrackerJsonImporter {
+    fn import(&self, input: &str) -> Result<Vec<Track>> {
+        let tracks: Vec<Track> =
+            serde_json::from_str(input).map_err(|error| Error::Import(error.to_string()))?;
+        Ok(tracks
+            .into_iter()
+            .filter(|track| !track.is_tracking())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn import(format: ImportFormat, input: &str) -> Result<Vec<Track>> {
+        let defaults = ImportDefaults {
+            project: String::from("Imported"),
+            workspace: String::from("Workspace"),
+        };
+        format
+            .importer(defaults, FixedOffset::east(3600))
+            .import(input)
+    }
+
+    fn summary(tracks: &[Track]) -> Vec<(&str, &str, &str, String, String)> {
+        tracks
+            .iter()
+            .map(|track| {
+                (
+                    track.name.as_str(),
+                    track.project.as_str(),
+                    track.workspace.as_str(),
+                    track.start.to_rfc3339(),
+                    track.end.unwrap().to_rfc3339(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_import_toggl_csv() {
+        let input = "\
+User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags,Amount ()
+Ana,ana@example.com,Acme,Website,,\"Fix header, again\",No,2022-01-03,09:00:00,2022-01-03,10:30:00,01:30:00,\"Web, Billable\",
+Ana,ana@example.com,,,,Emails,No,2022-01-03,23:30:00,2022-01-04,00:15:00,00:45:00,,
+";
+        let tracks = import(ImportFormat::TogglCsv, input).unwrap();
+        assert_eq!(tracks[0].tags, vec!["billable", "web"]);
+        assert!(tracks[1].tags.is_empty());
+        assert_eq!(
+            summary(&tracks),
+            vec![
+                (
+                    "Fix header, again",
+                    "Website",
+                    "Acme",
+                    String::from("2022-01-03T08:00:00+00:00"),
+                    String::from("2022-01-03T09:30:00+00:00")
+                ),
+                (
+                    "Emails",
+                    "Imported",
+                    "Workspace",
+                    String::from("2022-01-03T22:30:00+00:00"),
+                    String::from("2022-01-03T23:15:00+00:00")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_clockify_csv() {
+        let input = "\
+\"Project\",\"Client\",\"Description\",\"Task\",\"User\",\"Email\",\"Tags\",\"Billable\",\"Start Date\",\"Start Time\",\"End Date\",\"End Time\",\"Duration (h)\"
+\"Website\",\"Acme\",\"Review\",\"\",\"Ana\",\"ana@example.com\",\"\",\"Yes\",\"01/03/2022\",\"02:00:00 PM\",\"01/03/2022\",\"03:15:00 PM\",\"01:15:00\"
+";
+        assert_eq!(
+            summary(&import(ImportFormat::ClockifyCsv, input).unwrap()),
+            vec![(
+                "Review",
+                "Website",
+                "Acme",
+                String::from("2022-01-03T13:00:00+00:00"),
+                String::from("2022-01-03T14:15:00+00:00")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_import_csv_missing_column() {
+        assert_eq!(
+            import(ImportFormat::TogglCsv, "Description,Project\nA,B\n"),
+            Err(Error::Import(String::from("missing column Start date")))
+        );
+    }
+
+    #[test]
+    fn test_import_timewarrior() {
+        let input = "\
+inc 20220103T090000Z - 20220103T100000Z # code \"code review\"
+inc 20220103T110000Z - 20220103T113000Z
+inc 20220103T120000Z # running
+";
+        let tracks = import(ImportFormat::Timewarrior, input).unwrap();
+        assert_eq!(tracks[0].tags, vec!["code", "code review"]);
+        assert_eq!(
+            summary(&tracks),
+            vec![
+                (
+                    "code code review",
+                    "Imported",
+                    "Workspace",
+                    String::from("2022-01-03T09:00:00+00:00"),
+                    String::from("2022-01-03T10:00:00+00:00")
+                ),
+                (
+                    "",
+                    "Imported",
+                    "Workspace",
+                    String::from("2022-01-03T11:00:00+00:00"),
+                    String::from("2022-01-03T11:30:00+00:00")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_org() {
+        let input = "\
+* Website
+** TODO [#A] Fix header :work:Urgent:
+   :LOGBOOK:
+   CLOCK: [2022-01-03 Mon 09:00]--[2022-01-03 Mon 10:30] =>  1:30
+   CLOCK: [2022-01-04 Tue 09:00]
+   :END:
+* Reading
+  CLOCK: [2022-01-05 Wed 20:00]--[2022-01-05 Wed 21:00] =>  1:00
+";
+        let tracks = import(ImportFormat::Org, input).unwrap();
+        assert_eq!(tracks[0].tags, vec!["urgent", "work"]);
+        assert_eq!(
+            summary(&tracks),
+            vec![
+                (
+                    "Fix header",
+                    "Website",
+                    "Workspace",
+                    String::from("2022-01-03T08:00:00+00:00"),
+                    String::from("2022-01-03T09:30:00+00:00")
+                ),
+                (
+                    "Reading",
+                    "Imported",
+                    "Workspace",
+                    String::from("2022-01-05T19:00:00+00:00"),
+                    String::from("2022-01-05T20:00:00+00:00")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_tracker_json() {
+        let track = Track::create(
+            String::from("a1"),
+            String::from("MyTrack"),
+            "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            Some("2022-01-01T02:00:00Z".parse::<DateTime<Utc>>().unwrap()),
+            String::from("Project1"),
+            String::from("Workspace"),
+        );
+        let input = serde_json::to_string(std::slice::from_ref(&track)).unwrap();
+        assert_eq!(
+            import(ImportFormat::TrackerJson, &input).unwrap(),
+            vec![track]
+        );
+    }
+}
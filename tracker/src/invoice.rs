@@ -0,0 +1,570 @@
+use crate::billing::{Money, Rates};
+use crate::error::{Error, Result};
+use crate::model::{Rate, Track};
+use crate::report::seconds;
+use crate::rounding::Rounding;
+use crate::template;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Whether an invoice lists every track or one line per project and rate.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineItems {
+    Tracks,
+    Projects,
+}
+
+/// The built-in invoice templates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvoiceFormat {
+    Markdown,
+    Html,
+    /// Plain text with no wide columns, ready to print or convert to PDF.
+    Text,
+}
+
+impl InvoiceFormat {
+    pub fn template(&self) -> &'static str {
+        match self {
+            InvoiceFormat::Markdown => include_str!("templates/invoice.md"),
+            InvoiceFormat::Html => include_str!("templates/invoice.html"),
+            InvoiceFormat::Text => include_str!("templates/invoice.txt"),
+        }
+    }
+
+    fn escape(&self) -> fn(&str) -> String {
+        match self {
+            InvoiceFormat::Markdown => template::escape_markdown,
+            InvoiceFormat::Html => template::escape_html,
+            InvoiceFormat::Text => template::escape_nothing,
+        }
+    }
+}
+
+/// A tax added on top of the subtotals, e.g. 20% of VAT.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Tax {
+    pub name: String,
+    pub percent: Decimal,
+}
+
+/// What to invoice: the billable tracks of a client started in
+/// `since..until` and not invoiced yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceRequest {
+    pub client: String,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub items: LineItems,
+    pub tax: Option<Tax>,
+    /// Chosen by the service when `None`.
+    pub number: Option<String>,
+    /// Applied to the time of the tracks before it is billed.
+    pub rounding: Option<Rounding>,
+}
+
+/// A track, or the tracks of a project at one rate. Project lines have no
+/// date. The amount is rounded to cents and billed for `duration`, which is
+/// the `tracked` time once rounded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvoiceLine {
+    pub date: Option<NaiveDate>,
+    pub project: String,
+    pub workspace: String,
+    pub description: String,
+    #[serde(serialize_with = "seconds")]
+    pub duration: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub tracked: Duration,
+    pub rate: Rate,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvoiceTax {
+    pub name: String,
+    pub percent: Decimal,
+    pub amount: Money,
+}
+
+/// A bill for the time spent for a client. Days are taken in the timezone
+/// the invoice is built in, `until` being the last day of the period.
+/// Subtotals, taxes and totals come one per currency.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Invoice {
+    pub id: String,
+    pub number: String,
+    pub client: String,
+    pub issued: DateTime<Utc>,
+    pub date: NaiveDate,
+    pub since: NaiveDate,
+    pub until: NaiveDate,
+    pub items: LineItems,
+    pub rounding: Option<Rounding>,
+    pub lines: Vec<InvoiceLine>,
+    pub subtotals: Vec<Money>,
+    pub taxes: Vec<InvoiceTax>,
+    pub totals: Vec<Money>,
+    /// Ids of the invoiced tracks.
+    pub tracks: Vec<String>,
+}
+
+impl Invoice {
+    /// Bills the stopped, billable and not yet invoiced tracks starting in the
+    /// period of `request`, failing when there are none or one has no rate.
+    pub fn build<Tz: TimeZone>(
+        number: String,
+        request: &InvoiceRequest,
+        tracks: &[Track],
+        rates: &Rates,
+        now: DateTime<Tz>,
+    ) -> Result<Invoice> {
+        let timezone = now.timezone();
+        let day = |time: DateTime<Utc>| time.with_timezone(&timezone).date().naive_local();
+        let mut billed: Vec<(&Track, &Rate)> = vec![];
+        for track in tracks.iter().filter(|track| {
+            track.billable
+                && !track.is_tracking()
+                && track.invoice.is_none()
+                && request.since <= track.start
+                && track.start < request.until
+        }) {
+            let rate = rates.rate_of(track).ok_or_else(|| {
+                Error::MissingRate(format!("track {} of project {}", track.name, track.project))
+            })?;
+            billed.push((track, rate));
+        }
+        if billed.is_empty() {
+            return Err(Error::NothingToInvoice(request.client.clone()));
+        }
+        billed.sort_by_key(|(track, _)| track.start);
+        let tracked: Vec<(&Track, NaiveDate, Duration)> = billed
+            .iter()
+            .map(|(track, _)| (*track, day(track.start), track.duration()))
+            .collect();
+        let durations = match &request.rounding {
+            Some(rounding) => rounding.round(&tracked),
+            None => tracked.iter().map(|(_, _, duration)| *duration).collect(),
+        };
+        let lines: Vec<InvoiceLine> = match request.items {
+            LineItems::Tracks => billed
+                .iter()
+                .zip(tracked.iter().zip(durations))
+                .map(|((track, rate), ((_, day, tracked), duration))| {
+                    line(
+                        Some(*day),
+                        track,
+                        track.name.clone(),
+                        (duration, *tracked),
+                        rate,
+                    )
+                })
+                .collect(),
+            LineItems::Projects => {
+                let mut projects: BTreeMap<_, (&Track, &Rate, Duration, Duration, usize)> =
+                    BTreeMap::new();
+                for ((track, rate), ((_, _, tracked), duration)) in
+                    billed.iter().zip(tracked.iter().zip(durations))
+                {
+                    let key = (
+                        track.workspace.to_lowercase(),
+                        track.project.to_lowercase(),
+                        rate.currency.clone(),
+                        rate.amount,
+                    );
+                    let entry = projects.entry(key).or_insert((
+                        track,
+                        rate,
+                        Duration::zero(),
+                        Duration::zero(),
+                        0,
+                    ));
+                    entry.2 = entry.2 + duration;
+                    entry.3 = entry.3 + *tracked;
+                    entry.4 += 1;
+                }
+                projects
+                    .into_values()
+                    .map(|(track, rate, duration, tracked, count)| {
+                        let description = match count {
+                            1 => String::from("1 track"),
+                            count => format!("{} tracks", count),
+                        };
+                        line(None, track, description, (duration, tracked), rate)
+                    })
+                    .collect()
+            }
+        };
+        let subtotals = Money::totals(lines.iter().map(|line| &line.amount));
+        let taxes: Vec<InvoiceTax> = request
+            .tax
+            .iter()
+            .flat_map(|tax| {
+                subtotals.iter().map(|subtotal| InvoiceTax {
+                    name: tax.name.clone(),
+                    percent: tax.percent,
+                    amount: cents(
+                        subtotal.amount * tax.percent / Decimal::from(100),
+                        &subtotal.currency,
+                    ),
+                })
+            })
+            .collect();
+        let totals = Money::totals(subtotals.iter().chain(taxes.iter().map(|tax| &tax.amount)));
+        Ok(Invoice {
+            id: Uuid::new_v4().hyphenated().to_string(),
+            number,
+            client: request.client.clone(),
+            issued: now.with_timezone(&Utc),
+            date: now.date().naive_local(),
+            since: day(request.since),
+            until: day(request.until - Duration::milliseconds(1)),
+            items: request.items,
+            rounding: request.rounding.clone(),
+            lines,
+            subtotals,
+            taxes,
+            totals,
+            tracks: billed.iter().map(|(track, _)| track.id.clone()).collect(),
+        })
+    }
+
+    /// The billed time, rounded.
+    pub fn duration(&self) -> Duration {
+        self.lines
+            .iter()
+            .fold(Duration::zero(), |total, line| total + line.duration)
+    }
+
+    /// The time actually tracked.
+    pub fn tracked(&self) -> Duration {
+        self.lines
+            .iter()
+            .fold(Duration::zero(), |total, line| total + line.tracked)
+    }
+
+    /// Fills `template`, escaping the values as `format` requires. See
+    /// `template::render` for the syntax and `context` for the values.
+    pub fn render(&self, template: &str, format: InvoiceFormat) -> Result<String> {
+        template::render(template, &self.context(), format.escape())
+    }
+
+    /// The values templates can use: `number`, `client`, `date`, `since`,
+    /// `until`, `hours`, `tracked_hours`, `rounded`, the `lines` with their
+    /// `date`, `project`, `workspace`, `description`, `hours`,
+    /// `tracked_hours`, `rate` and `amount`, the `subtotals` and `totals` and
+    /// the `taxes` with their `name`, `percent` and `amount`. Hours are
+    /// decimal and billed, `tracked_hours` before rounding and `rounded` true
+    /// when a rounding applies. Money and rates end with the currency.
+    pub fn context(&self) -> Value {
+        let lines: Vec<Value> = self
+            .lines
+            .iter()
+            .map(|line| {
+                json!({
+                    "date": line.date.map(|date| date.to_string()),
+                    "project": line.project,
+                    "workspace": line.workspace,
+                    "description": line.description,
+                    "hours": hours(line.duration),
+                    "tracked_hours": hours(line.tracked),
+                    "rate": line.rate.to_string(),
+                    "amount": line.amount.to_string(),
+                })
+            })
+            .collect();
+        let taxes: Vec<Value> = self
+            .taxes
+            .iter()
+            .map(|tax| {
+                json!({
+                    "name": tax.name,
+                    "percent": tax.percent.normalize().to_string(),
+                    "amount": tax.amount.to_string(),
+                })
+            })
+            .collect();
+        let texts = |amounts: &[Money]| -> Vec<String> {
+            amounts.iter().map(|money| money.to_string()).collect()
+        };
+        json!({
+            "number": self.number,
+            "client": self.client,
+            "date": self.date.to_string(),
+            "since": self.since.to_string(),
+            "until": self.until.to_string(),
+            "hours": hours(self.duration()),
+            "tracked_hours": hours(self.tracked()),
+            "rounded": self.rounding.as_ref().is_some_and(Rounding::is_active),
+            "lines": lines,
+            "subtotals": texts(&self.subtotals),
+            "taxes": taxes,
+            "totals": texts(&self.totals),
+        })
+    }
+}
+
+/// A line billing `duration` out of the `tracked` time.
+fn line(
+    date: Option<NaiveDate>,
+    track: &Track,
+    description: String,
+    (duration, tracked): (Duration, Duration),
+    rate: &Rate,
+) -> InvoiceLine {
+    InvoiceLine {
+        date,
+        project: track.project.clone(),
+        workspace: track.workspace.clone(),
+        description,
+        duration,
+        tracked,
+        rate: rate.clone(),
+        amount: cents(rate.amount_for(duration), &rate.currency),
+    }
+}
+
+/// Money rounded to cents half away from zero.
+fn cents(amount: Decimal, currency: &str) -> Money {
+    let mut amount = amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    amount.rescale(2);
+    Money {
+        amount,
+        currency: String::from(currency),
+    }
+}
+
+/// Decimal hours with two decimals, e.g. `1.25` for 1h15m.
+fn hours(duration: Duration) -> String {
+    let mut hours = (Decimal::from(duration.num_seconds()) / Decimal::from(3600))
+        .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    hours.rescale(2);
+    hours.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Project, Workspace};
+    use crate::rounding::RoundingMode;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    fn create_track(id: &str, project: &str, start: &str, end: Option<&str>) -> Track {
+        Track::create(
+            String::from(id),
+            format!("Work {}", id),
+            at(start),
+            end.map(at),
+            String::from(project),
+            String::from("Acme"),
+        )
+    }
+
+    fn create_tracks() -> Vec<Track> {
+        let mut tracks = vec![
+            create_track(
+                "a1",
+                "Website",
+                "2022-01-03T09:00:00Z",
+                Some("2022-01-03T10:30:00Z"),
+            ),
+            create_track(
+                "a2",
+                "Support",
+                "2022-01-04T09:00:00Z",
+                Some("2022-01-04T09:20:00Z"),
+            ),
+            create_track(
+                "a3",
+                "Website",
+                "2022-01-05T09:00:00Z",
+                Some("2022-01-05T10:00:00Z"),
+            ),
+            create_track(
+                "a4",
+                "Website",
+                "2022-01-06T09:00:00Z",
+                Some("2022-01-06T10:00:00Z"),
+            ),
+            create_track(
+                "a5",
+                "Website",
+                "2021-12-31T09:00:00Z",
+                Some("2021-12-31T10:00:00Z"),
+            ),
+            create_track("a6", "Website", "2022-01-07T09:00:00Z", None),
+            create_track(
+                "a7",
+                "Website",
+                "2022-01-07T08:00:00Z",
+                Some("2022-01-07T08:30:00Z"),
+            ),
+        ];
+        tracks[3].billable = false;
+        tracks[6].invoice = Some(String::from("2022-0001"));
+        tracks
+    }
+
+    fn create_rates() -> Rates {
+        let mut workspace = Workspace::new(String::from("Acme"));
+        workspace.rate = Some(Rate::new(Decimal::from(80), "EUR"));
+        let mut project = Project::new(String::from("Website"), String::from("Acme"));
+        project.rate = Some(Rate::new(Decimal::new(9550, 2), "EUR"));
+        Rates::new(&[workspace], &[project])
+    }
+
+    fn create_request(items: LineItems) -> InvoiceRequest {
+        InvoiceRequest {
+            client: String::from("Acme"),
+            since: at("2022-01-01T00:00:00Z"),
+            until: at("2022-02-01T00:00:00Z"),
+            items,
+            tax: Some(Tax {
+                name: String::from("VAT"),
+                percent: Decimal::from(20),
+            }),
+            number: None,
+            rounding: None,
+        }
+    }
+
+    fn build(items: LineItems) -> Invoice {
+        Invoice::build(
+            String::from("2022-0002"),
+            &create_request(items),
+            &create_tracks(),
+            &create_rates(),
+            at("2022-02-01T12:00:00Z"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_invoice_tracks() {
+        let invoice = build(LineItems::Tracks);
+        assert_eq!(invoice.tracks, vec!["a1", "a2", "a3"]);
+        let amounts: Vec<String> = invoice
+            .lines
+            .iter()
+            .map(|line| line.amount.to_string())
+            .collect();
+        assert_eq!(amounts, vec!["143.25 EUR", "26.67 EUR", "95.50 EUR"]);
+        assert_eq!(invoice.subtotals[0].to_string(), "265.42 EUR");
+        assert_eq!(invoice.taxes[0].amount.to_string(), "53.08 EUR");
+        assert_eq!(invoice.totals[0].to_string(), "318.50 EUR");
+        assert_eq!(invoice.since.to_string(), "2022-01-01");
+        assert_eq!(invoice.until.to_string(), "2022-01-31");
+    }
+
+    #[test]
+    fn test_invoice_projects() {
+        let invoice = build(LineItems::Projects);
+        let lines: Vec<(String, String, String)> = invoice
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.project.clone(),
+                    line.description.clone(),
+                    line.amount.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (
+                    String::from("Support"),
+                    String::from("1 track"),
+                    String::from("26.67 EUR")
+                ),
+                (
+                    String::from("Website"),
+                    String::from("2 tracks"),
+                    String::from("238.75 EUR")
+                ),
+            ]
+        );
+        assert_eq!(invoice.totals[0].to_string(), "318.50 EUR");
+    }
+
+    #[test]
+    fn test_invoice_rounding() {
+        let mut request = create_request(LineItems::Tracks);
+        request.rounding = Some(Rounding {
+            increment: Duration::hours(1),
+            mode: RoundingMode::Up,
+            ..Rounding::default()
+        });
+        let invoice = Invoice::build(
+            String::from("2022-0002"),
+            &request,
+            &create_tracks(),
+            &create_rates(),
+            at("2022-02-01T12:00:00Z"),
+        )
+        .unwrap();
+        let hours: Vec<(i64, i64)> = invoice
+            .lines
+            .iter()
+            .map(|line| (line.tracked.num_minutes(), line.duration.num_minutes()))
+            .collect();
+        assert_eq!(hours, vec![(90, 120), (20, 60), (60, 60)]);
+        assert_eq!(invoice.tracked(), Duration::minutes(170));
+        assert_eq!(invoice.duration(), Duration::minutes(240));
+        assert_eq!(invoice.subtotals[0].to_string(), "366.50 EUR");
+        let text = invoice
+            .render(InvoiceFormat::Text.template(), InvoiceFormat::Text)
+            .unwrap();
+        assert!(text.contains("Total hours: 4.00 (tracked 2.83)\n"));
+    }
+
+    #[test]
+    fn test_invoice_needs_rates_and_tracks() {
+        let request = create_request(LineItems::Tracks);
+        let now = at("2022-02-01T12:00:00Z");
+        assert!(matches!(
+            Invoice::build(
+                String::from("1"),
+                &request,
+                &create_tracks(),
+                &Rates::default(),
+                now
+            ),
+            Err(Error::MissingRate(_))
+        ));
+        assert_eq!(
+            Invoice::build(String::from("1"), &request, &[], &create_rates(), now),
+            Err(Error::NothingToInvoice(String::from("Acme")))
+        );
+    }
+
+    #[test]
+    fn test_render_builtin_templates() {
+        let invoice = build(LineItems::Tracks);
+        let markdown = invoice
+            .render(InvoiceFormat::Markdown.template(), InvoiceFormat::Markdown)
+            .unwrap();
+        assert!(markdown.starts_with("# Invoice 2022-0002\n"));
+        assert!(markdown
+            .contains("| 2022-01-03 | Website | Work a1 | 1.50 | 95.50 EUR/h | 143.25 EUR |\n"));
+        assert!(markdown.contains("**VAT 20%:** 53.08 EUR"));
+        let html = invoice
+            .render(InvoiceFormat::Html.template(), InvoiceFormat::Html)
+            .unwrap();
+        assert!(html.contains("<th class=\"number\">318.50 EUR</th>"));
+        let text = build(LineItems::Projects)
+            .render(InvoiceFormat::Text.template(), InvoiceFormat::Text)
+            .unwrap();
+        assert!(text.contains("\nWebsite: 2 tracks\n    2.50 h at 95.50 EUR/h = 238.75 EUR\n"));
+        assert!(text.contains("TOTAL: 318.50 EUR\n"));
+    }
+}
@@ -1,16 +1,81 @@
+pub mod billing;
+pub mod config;
+pub mod directory;
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod invoice;
+pub mod migrations;
 pub mod model;
+pub mod parser;
+pub mod report;
 pub mod repository;
 pub mod repository_sqlite;
+pub mod rounding;
 pub mod service;
+pub mod template;
 
-use repository_sqlite::{RepositorySQLite, SCHEME};
-use service::TrackService;
-use sqlite;
-use std::sync::Arc;
+pub use config::{Config, Database};
+pub use error::{Error, Result};
+use repository_sqlite::{
+    InvoiceRepositorySQLite, ProjectRepositorySQLite, RepositorySQLite, SettingsRepositorySQLite,
+    WorkspaceRepositorySQLite,
+};
+use service::{InvoiceService, ProjectService, TrackService};
+use std::fs;
+use std::rc::Rc;
 
-pub fn init() -> TrackService {
-    let connection = Arc::new(sqlite::open("bd.sqlite").unwrap());
-    connection.execute(SCHEME).unwrap();
-    let repository = Box::new(RepositorySQLite::create(connection));
-    TrackService::create(repository)
+/// Opens the configured database, bringing its schema up to date.
+pub fn init(config: &Config) -> Result<TrackService> {
+    let connection = Rc::new(connect(config)?);
+    migrations::migrate(&connection)?;
+    Ok(track_service(connection))
+}
+
+/// Opens the configured database to bill tracks.
+pub fn init_invoices(config: &Config) -> Result<InvoiceService> {
+    let connection = Rc::new(connect(config)?);
+    migrations::migrate(&connection)?;
+    let invoices = Box::new(InvoiceRepositorySQLite::create(connection.clone()));
+    Ok(InvoiceService::create(track_service(connection), invoices))
+}
+
+/// Opens the configured database to manage workspaces, projects and the
+/// context in use.
+pub fn init_projects(config: &Config) -> Result<ProjectService> {
+    let connection = Rc::new(connect(config)?);
+    migrations::migrate(&connection)?;
+    Ok(project_service(connection))
+}
+
+fn track_service(connection: Rc<sqlite::Connection>) -> TrackService {
+    TrackService::create(Box::new(RepositorySQLite::create(connection.clone())))
+        .with_projects(project_service(connection))
+}
+
+fn project_service(connection: Rc<sqlite::Connection>) -> ProjectService {
+    ProjectService::create(
+        Box::new(WorkspaceRepositorySQLite::create(connection.clone())),
+        Box::new(ProjectRepositorySQLite::create(connection.clone())),
+        Box::new(SettingsRepositorySQLite::create(connection)),
+    )
+}
+
+/// Opens the configured database as is, without running migrations.
+pub fn connect(config: &Config) -> Result<sqlite::Connection> {
+    let connection = match &config.database {
+        Database::Memory => sqlite::open(":memory:")?,
+        Database::File(path) => {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(parent)
+                    .map_err(|error| Error::Storage(format!("{}: {}", parent.display(), error)))?;
+            }
+            sqlite::open(path)?
+        }
+    };
+    connection.execute("PRAGMA foreign_keys = ON")?;
+    Ok(connection)
 }
@@ -0,0 +1,544 @@
+use crate::error::{Error, Result};
+
+#[derive(Debug, PartialEq)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Ordered list of schema changes. A migration is never edited once released,
+/// new changes are appended with the next version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create tracks table",
+        sql: "
+            CREATE TABLE IF NOT EXISTS tracks (
+                id TEXT,
+                name TEXT,
+                start TEXT,
+                end TEXT,
+                project TEXT,
+                workspace TEXT
+            );
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "store timestamps as epoch milliseconds, add primary key and indexes",
+        sql: "
+            CREATE TABLE tracks_v2 (
+                id TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                start INTEGER NOT NULL,
+                end INTEGER,
+                project TEXT NOT NULL,
+                workspace TEXT NOT NULL
+            );
+            -- A timestamp that does not parse leaves start NULL, failing the migration.
+            INSERT INTO tracks_v2 (id, name, start, end, project, workspace)
+            SELECT
+                id,
+                name,
+                CASE WHEN end IS NOT NULL AND end <> ''
+                        AND julianday(replace(end, ' UTC', '')) IS NULL THEN NULL
+                    ELSE CAST(ROUND((julianday(replace(start, ' UTC', '')) - 2440587.5) * 86400000) AS INTEGER)
+                END,
+                CASE WHEN end IS NULL OR end = '' THEN NULL
+                    ELSE CAST(ROUND((julianday(replace(end, ' UTC', '')) - 2440587.5) * 86400000) AS INTEGER)
+                END,
+                project,
+                workspace
+            FROM tracks;
+            DROP TABLE tracks;
+            ALTER TABLE tracks_v2 RENAME TO tracks;
+            CREATE INDEX tracks_start ON tracks (start);
+            CREATE INDEX tracks_project ON tracks (project);
+            CREATE INDEX tracks_workspace ON tracks (workspace);
+        ",
+    },
+    Migration {
+        version: 3,
+        description: "index running tracks",
+        sql: "CREATE INDEX tracks_running ON tracks (start) WHERE end IS NULL;",
+    },
+    Migration {
+        version: 4,
+        description: "soft delete tracks",
+        sql: "ALTER TABLE tracks ADD COLUMN deleted_at INTEGER;",
+    },
+    Migration {
+        version: 5,
+        description: "create pauses table",
+        sql: "
+            CREATE TABLE pauses (
+                track_id TEXT NOT NULL REFERENCES tracks (id),
+                start INTEGER NOT NULL,
+                end INTEGER,
+                PRIMARY KEY (track_id, start)
+            );
+        ",
+    },
+    Migration {
+        version: 6,
+        description: "create tags tables",
+        sql: "
+            CREATE TABLE tags (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE
+            );
+            CREATE TABLE track_tags (
+                track_id TEXT NOT NULL REFERENCES tracks (id),
+                tag_id INTEGER NOT NULL REFERENCES tags (id),
+                PRIMARY KEY (track_id, tag_id)
+            );
+            CREATE INDEX track_tags_tag ON track_tags (tag_id);
+        ",
+    },
+    Migration {
+        version: 7,
+        description: "add notes to tracks",
+        sql: "ALTER TABLE tracks ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+    },
+    Migration {
+        version: 8,
+        description: "create workspaces and projects, deduplicating the names of tracks",
+        sql: "
+            CREATE TABLE workspaces (
+                id TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                color TEXT,
+                archived INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE projects (
+                id TEXT PRIMARY KEY NOT NULL,
+                workspace_id TEXT NOT NULL REFERENCES workspaces (id),
+                name TEXT NOT NULL COLLATE NOCASE,
+                color TEXT,
+                client TEXT,
+                archived INTEGER NOT NULL DEFAULT 0,
+                UNIQUE (workspace_id, name)
+            );
+            INSERT OR IGNORE INTO workspaces (id, name)
+            SELECT
+                lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2)
+                    || '-' || substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2)
+                    || '-' || hex(randomblob(6))),
+                trim(workspace)
+            FROM tracks
+            ORDER BY rowid;
+            INSERT OR IGNORE INTO projects (id, workspace_id, name)
+            SELECT
+                lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2)
+                    || '-' || substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2)
+                    || '-' || hex(randomblob(6))),
+                workspaces.id,
+                trim(tracks.project)
+            FROM tracks
+            JOIN workspaces ON workspaces.name = trim(tracks.workspace)
+            ORDER BY tracks.rowid;
+            CREATE TABLE tracks_v8 (
+                id TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                start INTEGER NOT NULL,
+                end INTEGER,
+                project_id TEXT NOT NULL REFERENCES projects (id),
+                notes TEXT NOT NULL DEFAULT '',
+                deleted_at INTEGER
+            );
+            INSERT INTO tracks_v8 (id, name, start, end, project_id, notes, deleted_at)
+            SELECT tracks.id, tracks.name, tracks.start, tracks.end, projects.id, tracks.notes, tracks.deleted_at
+            FROM tracks
+            JOIN workspaces ON workspaces.name = trim(tracks.workspace)
+            JOIN projects ON projects.workspace_id = workspaces.id AND projects.name = trim(tracks.project)
+            ORDER BY tracks.rowid;
+            DROP TABLE tracks;
+            ALTER TABLE tracks_v8 RENAME TO tracks;
+            CREATE INDEX tracks_start ON tracks (start);
+            CREATE INDEX tracks_running ON tracks (start) WHERE end IS NULL;
+            CREATE INDEX tracks_project ON tracks (project_id);
+            CREATE VIEW tracks_named AS
+            SELECT tracks.rowid AS rowid, tracks.*, projects.name AS project, workspaces.name AS workspace
+            FROM tracks
+            JOIN projects ON projects.id = tracks.project_id
+            JOIN workspaces ON workspaces.id = projects.workspace_id;
+        ",
+    },
+    Migration {
+        version: 9,
+        description: "create settings table",
+        sql: "
+            CREATE TABLE settings (
+                key TEXT PRIMARY KEY NOT NULL,
+                value TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 10,
+        description: "add hourly rates and the billable flag",
+        sql: "
+            ALTER TABLE workspaces ADD COLUMN rate TEXT;
+            ALTER TABLE workspaces ADD COLUMN currency TEXT;
+            ALTER TABLE projects ADD COLUMN rate TEXT;
+            ALTER TABLE projects ADD COLUMN currency TEXT;
+            ALTER TABLE tracks ADD COLUMN rate TEXT;
+            ALTER TABLE tracks ADD COLUMN currency TEXT;
+            ALTER TABLE tracks ADD COLUMN billable INTEGER NOT NULL DEFAULT 1;
+            DROP VIEW tracks_named;
+            CREATE VIEW tracks_named AS
+            SELECT tracks.rowid AS rowid, tracks.*, projects.name AS project, workspaces.name AS workspace
+            FROM tracks
+            JOIN projects ON projects.id = tracks.project_id
+            JOIN workspaces ON workspaces.id = projects.workspace_id;
+        ",
+    },
+    Migration {
+        version: 11,
+        description: "create invoices table",
+        sql: "
+            CREATE TABLE invoices (
+                id TEXT PRIMARY KEY NOT NULL,
+                number TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                client TEXT NOT NULL,
+                issued_at INTEGER NOT NULL
+            );
+            ALTER TABLE tracks ADD COLUMN invoice_id TEXT REFERENCES invoices (id);
+            CREATE INDEX tracks_invoice ON tracks (invoice_id);
+            DROP VIEW tracks_named;
+            CREATE VIEW tracks_named AS
+            SELECT tracks.rowid AS rowid, tracks.*, projects.name AS project, workspaces.name AS workspace,
+                coalesce(projects.client, workspaces.name) AS client, invoices.number AS invoice
+            FROM tracks
+            JOIN projects ON projects.id = tracks.project_id
+            JOIN workspaces ON workspaces.id = projects.workspace_id
+            LEFT JOIN invoices ON invoices.id = tracks.invoice_id;
+        ",
+    },
+];
+
+pub fn latest_version() -> i64 {
+    latest_of(MIGRATIONS)
+}
+
+pub fn current_version(connection: &sqlite::Connection) -> Result<i64> {
+    let mut cursor = connection.prepare("PRAGMA user_version")?.into_cursor();
+    match cursor.next()? {
+        Some(row) => Ok(row[0].as_integer().unwrap_or(0)),
+        None => Ok(0),
+    }
+}
+
+/// Migrations not yet applied to the database, in the order they will run.
+pub fn pending(connection: &sqlite::Connection) -> Result<Vec<&'static Migration>> {
+    pending_of(connection, MIGRATIONS)
+}
+
+/// Applies every pending migration, each one in its own transaction, and
+/// returns the applied ones.
+pub fn migrate(connection: &sqlite::Connection) -> Result<Vec<&'static Migration>> {
+    migrate_with(connection, MIGRATIONS)
+}
+
+fn latest_of(migrations: &[Migration]) -> i64 {
+    migrations.last().map_or(0, |migration| migration.version)
+}
+
+fn pending_of<'a>(
+    connection: &sqlite::Connection,
+    migrations: &'a [Migration],
+) -> Result<Vec<&'a Migration>> {
+    let version = current_version(connection)?;
+    let latest = latest_of(migrations);
+    if version > latest {
+        return Err(Error::UnsupportedSchema(version, latest));
+    }
+    Ok(migrations
+        .iter()
+        .filter(|migration| migration.version > version)
+        .collect())
+}
+
+fn migrate_with<'a>(
+    connection: &sqlite::Connection,
+    migrations: &'a [Migration],
+) -> Result<Vec<&'a Migration>> {
+    let pending = pending_of(connection, migrations)?;
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+    // Dropping a rebuilt table would delete the rows referencing it, so the
+    // foreign keys are only checked once each migration ran.
+    let enforced = foreign_keys_enforced(connection)?;
+    connection.execute("PRAGMA foreign_keys = OFF")?;
+    let result = pending
+        .iter()
+        .try_for_each(|migration| apply(connection, migration));
+    if enforced {
+        connection.execute("PRAGMA foreign_keys = ON")?;
+    }
+    result.map(|_| pending)
+}
+
+fn foreign_keys_enforced(connection: &sqlite::Connection) -> Result<bool> {
+    let mut cursor = connection.prepare("PRAGMA foreign_keys")?.into_cursor();
+    match cursor.next()? {
+        Some(row) => Ok(row[0].as_integer() == Some(1)),
+        None => Ok(false),
+    }
+}
+
+/// Describes the first row referencing a missing one, if any.
+fn foreign_key_violation(connection: &sqlite::Connection) -> Result<Option<String>> {
+    let mut cursor = connection
+        .prepare("PRAGMA foreign_key_check")?
+        .into_cursor();
+    Ok(cursor.next()?.map(|row| {
+        format!(
+            "row {} of {} references a missing row of {}",
+            row[1].as_integer().unwrap_or(0),
+            row[0].as_string().unwrap_or(""),
+            row[2].as_string().unwrap_or("")
+        )
+    }))
+}
+
+fn apply(connection: &sqlite::Connection, migration: &Migration) -> Result<()> {
+    connection.execute("BEGIN")?;
+    let result = connection
+        .execute(migration.sql)
+        .and_then(|_| connection.execute(format!("PRAGMA user_version = {}", migration.version)))
+        .map_err(|error| error.to_string())
+        .and_then(|_| match foreign_key_violation(connection) {
+            Ok(None) => Ok(()),
+            Ok(Some(violation)) => Err(violation),
+            Err(error) => Err(error.to_string()),
+        });
+    match result {
+        Ok(_) => Ok(connection.execute("COMMIT")?),
+        Err(error) => {
+            connection.execute("ROLLBACK")?;
+            Err(Error::Storage(format!(
+                "migration {} ({}) failed: {}",
+                migration.version, migration.description, error
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_connection() -> sqlite::Connection {
+        sqlite::open(":memory:").unwrap()
+    }
+
+    #[test]
+    fn test_migrate_new_database() {
+        let connection = create_connection();
+        let applied = migrate(&connection).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        assert!(pending(&connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let connection = create_connection();
+        migrate(&connection).unwrap();
+        assert!(migrate(&connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refuse_newer_database() {
+        let connection = create_connection();
+        connection
+            .execute(format!("PRAGMA user_version = {}", latest_version() + 1))
+            .unwrap();
+        assert_eq!(
+            migrate(&connection).unwrap_err(),
+            Error::UnsupportedSchema(latest_version() + 1, latest_version())
+        );
+    }
+
+    #[test]
+    fn test_migrate_text_timestamps() {
+        let connection = create_connection();
+        migrate_with(&connection, &MIGRATIONS[..1]).unwrap();
+        connection
+            .execute(
+                "INSERT INTO tracks VALUES
+                    ('a1', 'MyTrack', '2022-01-01 01:00:00 UTC', '2022-01-01 01:01:00.250 UTC', 'Project1', 'Workspace');
+                INSERT INTO tracks VALUES
+                    ('a2', 'MyTrack2', '2022-01-01 02:00:00.123456789 UTC', '', 'Project1', 'Workspace');",
+            )
+            .unwrap();
+        migrate(&connection).unwrap();
+        let mut rows = vec![];
+        connection
+            .iterate(
+                "SELECT id, start, end FROM tracks ORDER BY start",
+                |pairs| {
+                    rows.push(
+                        pairs
+                            .iter()
+                            .map(|(_, value)| value.map(String::from))
+                            .collect::<Vec<_>>(),
+                    );
+                    true
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Some(String::from("a1")),
+                    Some(String::from("1640998800000")),
+                    Some(String::from("1640998860250")),
+                ],
+                vec![
+                    Some(String::from("a2")),
+                    Some(String::from("1641002400123")),
+                    None,
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_migrate_rejects_unparseable_timestamps() {
+        for (start, end) in [("yesterday", ""), ("2022-01-01 01:00:00 UTC", "later")] {
+            let connection = create_connection();
+            migrate_with(&connection, &MIGRATIONS[..1]).unwrap();
+            connection
+                .execute(format!(
+                    "INSERT INTO tracks VALUES
+                        ('a1', 'MyTrack', '2022-01-01 01:00:00 UTC', '', 'Project1', 'Workspace');
+                    INSERT INTO tracks VALUES
+                        ('a2', 'MyTrack2', '{}', '{}', 'Project1', 'Workspace');",
+                    start, end
+                ))
+                .unwrap();
+            match migrate(&connection) {
+                Err(Error::Storage(_)) => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+            assert_eq!(current_version(&connection).unwrap(), 1);
+            let mut count = 0;
+            connection
+                .iterate("SELECT id FROM tracks", |_| {
+                    count += 1;
+                    true
+                })
+                .unwrap();
+            assert_eq!(count, 2);
+        }
+    }
+
+    #[test]
+    fn test_migrate_deduplicates_projects() {
+        let connection = create_connection();
+        migrate_with(&connection, &MIGRATIONS[..7]).unwrap();
+        connection
+            .execute(
+                "INSERT INTO tracks (id, name, start, end, project, workspace) VALUES
+                    ('a1', 'First', 1, 2, 'Project1', 'Workspace'),
+                    ('a2', 'Second', 3, 4, 'project1 ', 'WORKSPACE'),
+                    ('a3', 'Third', 5, NULL, 'Project2', 'workspace'),
+                    ('a4', 'Fourth', 6, NULL, 'Project1', 'Other');",
+            )
+            .unwrap();
+        migrate(&connection).unwrap();
+        let mut rows = vec![];
+        connection
+            .iterate(
+                "SELECT tracks_named.id, project, workspace, projects.id
+                FROM tracks_named JOIN projects ON projects.id = project_id
+                ORDER BY tracks_named.id",
+                |pairs| {
+                    rows.push(
+                        pairs
+                            .iter()
+                            .map(|(_, value)| value.unwrap_or(""))
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    );
+                    true
+                },
+            )
+            .unwrap();
+        let names: Vec<&str> = rows
+            .iter()
+            .map(|row| &row[..row.rfind(' ').unwrap()])
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "a1 Project1 Workspace",
+                "a2 Project1 Workspace",
+                "a3 Project2 Workspace",
+                "a4 Project1 Other"
+            ]
+        );
+        assert_eq!(rows[0].rsplit(' ').next(), rows[1].rsplit(' ').next());
+        assert_ne!(rows[0].rsplit(' ').next(), rows[3].rsplit(' ').next());
+    }
+
+    #[test]
+    fn test_migrate_keeps_foreign_keys_enforced() {
+        let connection = create_connection();
+        connection.execute("PRAGMA foreign_keys = ON").unwrap();
+        migrate_with(&connection, &MIGRATIONS[..1]).unwrap();
+        connection
+            .execute(
+                "INSERT INTO tracks VALUES
+                    ('a1', 'MyTrack', '2022-01-01 01:00:00 UTC', '', 'Project1', 'Workspace');",
+            )
+            .unwrap();
+        migrate(&connection).unwrap();
+        assert!(foreign_keys_enforced(&connection).unwrap());
+        assert!(connection
+            .execute("INSERT INTO tracks (id, name, start, project_id) VALUES ('a2', 'MyTrack', 0, 'missing')")
+            .is_err());
+    }
+
+    #[test]
+    fn test_migration_breaking_foreign_keys_is_rolled_back() {
+        let connection = create_connection();
+        let migrations = [Migration {
+            version: 1,
+            description: "dangling reference",
+            sql: "CREATE TABLE parent (id TEXT PRIMARY KEY);
+                CREATE TABLE child (parent_id TEXT REFERENCES parent (id));
+                INSERT INTO child VALUES ('missing');",
+        }];
+        match migrate_with(&connection, &migrations) {
+            Err(Error::Storage(message)) => assert!(message.contains("missing row of parent")),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(current_version(&connection).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let connection = create_connection();
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "create table",
+                sql: "CREATE TABLE first (id TEXT);",
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                sql: "CREATE TABLE second (id TEXT); INSERT INTO missing VALUES (1);",
+            },
+        ];
+        assert!(migrate_with(&connection, &migrations).is_err());
+        assert_eq!(current_version(&connection).unwrap(), 1);
+        assert!(connection.execute("SELECT * FROM second").is_err());
+    }
+}
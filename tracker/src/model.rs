@@ -1,7 +1,69 @@
+use crate::error::{Error, Result};
 use chrono::prelude::{DateTime, Utc};
+use chrono::{Duration, TimeZone};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+/// Money earned per hour, in an ISO 4217 currency like `EUR`. Amounts are
+/// decimals so cents add up exactly. Serialized as written, e.g. `95.50 EUR`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rate {
+    pub amount: Decimal,
+    pub currency: String,
+}
+
+impl Rate {
+    pub fn new(amount: Decimal, currency: &str) -> Rate {
+        Rate {
+            amount,
+            currency: currency.to_uppercase(),
+        }
+    }
+
+    /// What `duration` is worth at this rate, not rounded.
+    pub fn amount_for(&self, duration: Duration) -> Decimal {
+        self.amount * Decimal::from(duration.num_seconds()) / Decimal::from(3600)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+impl TryFrom<String> for Rate {
+    type Error = Error;
+
+    fn try_from(text: String) -> Result<Rate> {
+        crate::parser::parse_rate(&text)
+    }
+}
+
+impl From<Rate> for String {
+    fn from(rate: Rate) -> String {
+        rate.to_string()
+    }
+}
+
+fn billable_by_default() -> bool {
+    true
+}
+
+/// A break inside a track, open while the track is paused.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pause {
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// Serialized with RFC 3339 timestamps in UTC, `end` is null while running.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Track {
     pub id: String,
     pub name: String,
@@ -9,6 +71,21 @@ pub struct Track {
     pub end: Option<DateTime<Utc>>,
     pub project: String,
     pub workspace: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Free text about what was done, one line per appended note.
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub pauses: Vec<Pause>,
+    /// Overrides the rates of the project and workspace.
+    #[serde(default)]
+    pub rate: Option<Rate>,
+    #[serde(default = "billable_by_default")]
+    pub billable: bool,
+    /// Number of the invoice billing the track, set by `tracker invoice`.
+    #[serde(default)]
+    pub invoice: Option<String>,
 }
 
 impl Track {
@@ -27,13 +104,23 @@ impl Track {
             end,
             project,
             workspace,
+            tags: vec![],
+            notes: String::new(),
+            pauses: vec![],
+            rate: None,
+            billable: true,
+            invoice: None,
         }
     }
 
-    pub fn start_new_track(name: String, project: String, workspace: String) -> Track {
+    pub fn new_track(
+        name: String,
+        project: String,
+        workspace: String,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> Track {
         let id = Uuid::new_v4().hyphenated().to_string();
-        let start = Utc::now();
-        let end = None;
         Track {
             id,
             name,
@@ -41,17 +128,207 @@ impl Track {
             end,
             project,
             workspace,
+            tags: vec![],
+            notes: String::new(),
+            pauses: vec![],
+            rate: None,
+            billable: true,
+            invoice: None,
+        }
+    }
+
+    pub fn start_new_track(name: String, project: String, workspace: String) -> Track {
+        Track::new_track(name, project, workspace, Utc::now(), None)
+    }
+
+    /// Replaces the tags, lowercased, sorted and without duplicates or blanks.
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        let mut tags: Vec<String> = tags
+            .into_iter()
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        self.tags = tags;
+    }
+
+    /// Adds a line to the notes prefixed with the time of `at`, e.g.
+    /// `[14:05] fixed the flaky test`.
+    pub fn append_note<Tz: TimeZone>(&mut self, note: &str, at: DateTime<Tz>) {
+        if !self.notes.is_empty() && !self.notes.ends_with('\n') {
+            self.notes.push('\n');
         }
+        self.notes.push_str(&format!(
+            "[{}] {}\n",
+            at.naive_local().format("%H:%M"),
+            note.trim()
+        ));
     }
 
     pub fn stop_track(&mut self) {
-        self.end = Some(Utc::now());
+        self.stop_track_at(Utc::now());
+    }
+
+    pub fn stop_track_at(&mut self, end: DateTime<Utc>) {
+        if let Some(pause) = self.pauses.last_mut().filter(|pause| pause.end.is_none()) {
+            pause.end = Some(end);
+        }
+        self.end = Some(end);
     }
 
     pub fn is_tracking(&self) -> bool {
-        match self.end {
-            Some(_) => false,
-            None => true,
+        self.end.is_none()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pauses.last().is_some_and(|pause| pause.end.is_none())
+    }
+
+    pub fn pause_at(&mut self, at: DateTime<Utc>) -> Result<()> {
+        if !self.is_tracking() {
+            return Err(Error::NoActiveTrack);
+        }
+        if self.is_paused() {
+            return Err(Error::AlreadyPaused(self.name.clone()));
+        }
+        let resumed = self
+            .pauses
+            .last()
+            .and_then(|pause| pause.end)
+            .unwrap_or(self.start);
+        if at <= resumed {
+            return Err(Error::InvalidInterval(resumed, at));
+        }
+        self.pauses.push(Pause {
+            start: at,
+            end: None,
+        });
+        Ok(())
+    }
+
+    pub fn resume_at(&mut self, at: DateTime<Utc>) -> Result<()> {
+        match self.pauses.last_mut() {
+            Some(pause) if pause.end.is_none() => {
+                if at <= pause.start {
+                    return Err(Error::InvalidInterval(pause.start, at));
+                }
+                pause.end = Some(at);
+                Ok(())
+            }
+            _ => Err(Error::NotPaused(self.name.clone())),
+        }
+    }
+
+    /// Drops the parts of the pauses falling outside the track interval.
+    pub fn clip_pauses(&mut self) {
+        let (start, end) = (self.start, self.end);
+        self.pauses.retain(|pause| {
+            pause.end.is_none_or(|pause_end| pause_end > start)
+                && end.is_none_or(|end| pause.start < end)
+        });
+        for pause in self.pauses.iter_mut() {
+            pause.start = pause.start.max(start);
+            if let Some(end) = end {
+                pause.end = Some(pause.end.map_or(end, |pause_end| pause_end.min(end)));
+            }
+        }
+    }
+
+    /// Time spent on the track until `now` for running tracks, without pauses.
+    pub fn duration_at(&self, now: DateTime<Utc>) -> Duration {
+        self.duration_between(self.start, self.end.unwrap_or(now), now)
+    }
+
+    /// Time spent on the track inside `from..to`, without pauses.
+    pub fn duration_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Duration {
+        let start = self.start.max(from);
+        let end = self.end.unwrap_or(now).min(to);
+        if end <= start {
+            return Duration::zero();
+        }
+        let paused = self
+            .pauses
+            .iter()
+            .map(|pause| pause.end.unwrap_or(end).min(end) - pause.start.max(start))
+            .filter(|pause| *pause > Duration::zero())
+            .fold(Duration::zero(), |total, pause| total + pause);
+        end - start - paused
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration_at(Utc::now())
+    }
+
+    /// Hash of what was tracked, ignoring the id and pauses, to recognize the
+    /// same track coming twice. Timestamps count to the millisecond as stored,
+    /// project and workspace names ignore case as they do in the database.
+    /// Only meant to be compared within a single run.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.name.hash(&mut hasher);
+        self.project.to_lowercase().hash(&mut hasher);
+        self.workspace.to_lowercase().hash(&mut hasher);
+        self.start.timestamp_millis().hash(&mut hasher);
+        self.end.map(|end| end.timestamp_millis()).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A client or employer grouping projects. Names are unique ignoring case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+    /// Archived workspaces are hidden and take no new tracks.
+    pub archived: bool,
+    /// Applies to the tracks of projects without a rate.
+    pub rate: Option<Rate>,
+}
+
+impl Workspace {
+    pub fn new(name: String) -> Workspace {
+        Workspace {
+            id: Uuid::new_v4().hyphenated().to_string(),
+            name,
+            color: None,
+            archived: false,
+            rate: None,
+        }
+    }
+}
+
+/// What tracks are filed under, inside a workspace. Names are unique in their
+/// workspace ignoring case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub workspace: String,
+    pub color: Option<String>,
+    pub client: Option<String>,
+    /// Archived projects are hidden and take no new tracks.
+    pub archived: bool,
+    /// Applies to the tracks without a rate, before the workspace one.
+    pub rate: Option<Rate>,
+}
+
+impl Project {
+    pub fn new(name: String, workspace: String) -> Project {
+        Project {
+            id: Uuid::new_v4().hyphenated().to_string(),
+            name,
+            workspace,
+            color: None,
+            client: None,
+            archived: false,
+            rate: None,
         }
     }
 }
@@ -59,7 +336,7 @@ impl Track {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{Duration, Utc};
+    use chrono::Utc;
 
     #[test]
     fn test_create_track() {
@@ -105,6 +382,23 @@ mod tests {
         assert!(track.start.lt(&Utc::now()));
     }
 
+    #[test]
+    fn test_new_track() {
+        let start = "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let end = "2022-01-01T01:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let track = Track::new_track(
+            String::from("MyTrack"),
+            String::from("Project1"),
+            String::from("Workspace"),
+            start,
+            Some(end),
+        );
+        assert_ne!(track.id.len(), 0);
+        assert_eq!(track.start, start);
+        assert_eq!(track.end, Some(end));
+        assert!(!track.is_tracking());
+    }
+
     #[test]
     fn test_stop_track() {
         let mut track = Track::start_new_track(
@@ -118,7 +412,7 @@ mod tests {
             assert!(end.gt(&datetime_that_should_be_lower_than_end));
             assert!(end.lt(&Utc::now()));
         } else {
-            assert!(false, "It's expeced to has a DateTime");
+            panic!("It's expeced to has a DateTime");
         }
     }
 
@@ -129,8 +423,178 @@ mod tests {
             String::from("Project1"),
             String::from("Workspace"),
         );
-        assert_eq!(track.is_tracking(), true);
+        assert!(track.is_tracking());
         track.stop_track();
-        assert_eq!(track.is_tracking(), false);
+        assert!(!track.is_tracking());
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2022-01-01T{}:00Z", time)
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+    }
+
+    fn create_running_track() -> Track {
+        Track::new_track(
+            String::from("MyTrack"),
+            String::from("Project1"),
+            String::from("Workspace"),
+            at("09:00"),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut track = create_running_track();
+        track.pause_at(at("12:00")).unwrap();
+        assert!(track.is_paused());
+        assert!(track.is_tracking());
+        assert_eq!(
+            track.pause_at(at("12:10")),
+            Err(Error::AlreadyPaused(String::from("MyTrack")))
+        );
+        assert_eq!(
+            track.resume_at(at("11:00")),
+            Err(Error::InvalidInterval(at("12:00"), at("11:00")))
+        );
+        track.resume_at(at("13:00")).unwrap();
+        assert!(!track.is_paused());
+        assert_eq!(
+            track.resume_at(at("13:10")),
+            Err(Error::NotPaused(String::from("MyTrack")))
+        );
+        assert_eq!(
+            track.pause_at(at("12:30")),
+            Err(Error::InvalidInterval(at("13:00"), at("12:30")))
+        );
+        track.stop_track_at(at("18:00"));
+        assert_eq!(track.duration_at(at("23:00")), Duration::hours(8));
+    }
+
+    #[test]
+    fn test_duration_while_paused() {
+        let mut track = create_running_track();
+        assert_eq!(track.duration_at(at("10:30")), Duration::minutes(90));
+        track.pause_at(at("10:00")).unwrap();
+        assert_eq!(track.duration_at(at("10:30")), Duration::hours(1));
+        track.stop_track_at(at("11:00"));
+        assert_eq!(
+            track.pauses,
+            vec![Pause {
+                start: at("10:00"),
+                end: Some(at("11:00"))
+            }]
+        );
+        assert_eq!(track.duration_at(at("23:00")), Duration::hours(1));
+    }
+
+    #[test]
+    fn test_duration_between() {
+        let mut track = create_running_track();
+        track.pause_at(at("10:00")).unwrap();
+        track.resume_at(at("11:00")).unwrap();
+        assert_eq!(
+            track.duration_between(at("08:00"), at("10:30"), at("12:00")),
+            Duration::hours(1)
+        );
+        assert_eq!(
+            track.duration_between(at("10:30"), at("23:00"), at("12:00")),
+            Duration::hours(1)
+        );
+        assert_eq!(
+            track.duration_between(at("12:30"), at("23:00"), at("12:00")),
+            Duration::zero()
+        );
+    }
+
+    #[test]
+    fn test_serialize_track() {
+        let mut track = create_running_track();
+        track.id = String::from("a1");
+        track.pause_at(at("10:00")).unwrap();
+        let json = serde_json::to_string(&track).unwrap();
+        assert_eq!(
+            json,
+            "{\"id\":\"a1\",\"name\":\"MyTrack\",\"start\":\"2022-01-01T09:00:00Z\",\"end\":null,\
+             \"project\":\"Project1\",\"workspace\":\"Workspace\",\"tags\":[],\"notes\":\"\",\
+             \"pauses\":[{\"start\":\"2022-01-01T10:00:00Z\",\"end\":null}],\"rate\":null,\"billable\":true,\"invoice\":null}"
+        );
+        assert_eq!(serde_json::from_str::<Track>(&json).unwrap(), track);
+        track.rate = Some(Rate::new(Decimal::new(9550, 2), "eur"));
+        let json = serde_json::to_string(&track).unwrap();
+        assert!(json.ends_with("\"rate\":\"95.50 EUR\",\"billable\":true,\"invoice\":null}"));
+        assert_eq!(serde_json::from_str::<Track>(&json).unwrap(), track);
+    }
+
+    #[test]
+    fn test_rate_amount() {
+        let rate = Rate::new(Decimal::new(9550, 2), "EUR");
+        assert_eq!(
+            rate.amount_for(Duration::minutes(90)),
+            Decimal::new(14325, 2)
+        );
+        assert_eq!(
+            Rate::new(Decimal::from(100), "USD")
+                .amount_for(Duration::minutes(20))
+                .round_dp(2),
+            Decimal::new(3333, 2)
+        );
+        assert_eq!(rate.to_string(), "95.50 EUR");
+    }
+
+    #[test]
+    fn test_set_tags() {
+        let mut track = create_running_track();
+        track.set_tags(vec![
+            String::from("Review"),
+            String::from(" billable "),
+            String::from(""),
+            String::from("review"),
+        ]);
+        assert_eq!(track.tags, vec!["billable", "review"]);
+    }
+
+    #[test]
+    fn test_append_note() {
+        let mut track = create_running_track();
+        let offset = chrono::FixedOffset::east(3600);
+        track.append_note(" fixed the flaky test ", at("10:05").with_timezone(&offset));
+        track.notes.push_str("edited by hand");
+        track.append_note("review", at("11:30").with_timezone(&offset));
+        assert_eq!(
+            track.notes,
+            "[11:05] fixed the flaky test\nedited by hand\n[12:30] review\n"
+        );
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let track = create_running_track();
+        let mut copy = create_running_track();
+        assert_eq!(track.content_hash(), copy.content_hash());
+        copy.start = copy.start + Duration::microseconds(10);
+        assert_eq!(track.content_hash(), copy.content_hash());
+        copy.name = String::from("Other");
+        assert_ne!(track.content_hash(), copy.content_hash());
+    }
+
+    #[test]
+    fn test_clip_pauses() {
+        let mut track = create_running_track();
+        track.pause_at(at("10:00")).unwrap();
+        track.resume_at(at("11:00")).unwrap();
+        track.pause_at(at("12:00")).unwrap();
+        track.resume_at(at("13:00")).unwrap();
+        track.start = at("10:30");
+        track.end = Some(at("12:00"));
+        track.clip_pauses();
+        assert_eq!(
+            track.pauses,
+            vec![Pause {
+                start: at("10:30"),
+                end: Some(at("11:00"))
+            }]
+        );
     }
 }
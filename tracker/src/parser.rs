@@ -0,0 +1,443 @@
+use crate::error::{Error, Result};
+use crate::invoice::Tax;
+use crate::model::Rate;
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Parses a moment written by a person, relative to the current local time.
+///
+/// Understands `now`, `today`/`yesterday`/`tomorrow` and weekday names with an
+/// optional time (`yesterday 14:00`, `monday 9am`), a bare time of today
+/// (`14:00`, `9:30pm`), relative offsets (`10 minutes ago`, `-1h30m`, `+15m`),
+/// ISO dates with an optional time and RFC 3339 timestamps.
+pub fn parse_time(input: &str) -> Result<DateTime<Utc>> {
+    parse_time_from(input, Local::now())
+}
+
+/// Same as `parse_time`, reading relative inputs from `now` and local dates
+/// and times in the timezone of `now`.
+pub fn parse_time_from<Tz: TimeZone>(input: &str, now: DateTime<Tz>) -> Result<DateTime<Utc>> {
+    let invalid = || Error::InvalidTimestamp(String::from(input));
+    let text = input.trim().to_lowercase();
+    if let Ok(time) = DateTime::parse_from_rfc3339(&text.to_uppercase()) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if text == "now" {
+        return Ok(now.with_timezone(&Utc));
+    }
+    let offset = |offset: &str, sign: i32| {
+        let duration = parse_duration(offset).map_err(|_| invalid())?;
+        let time = if sign < 0 {
+            now.clone().checked_sub_signed(duration)
+        } else {
+            now.clone().checked_add_signed(duration)
+        };
+        time.map(|time| time.with_timezone(&Utc))
+            .ok_or_else(invalid)
+    };
+    if let Some(duration) = text.strip_prefix('-') {
+        return offset(duration, -1);
+    }
+    if let Some(duration) = text.strip_prefix('+') {
+        return offset(duration, 1);
+    }
+    if let Some(duration) = text.strip_suffix("ago") {
+        return offset(duration, -1);
+    }
+    let (day, time) = match text.split_once(' ') {
+        Some((day, time)) => (day, Some(time.trim())),
+        None if text.len() > 10 && text.as_bytes()[10] == b't' => (&text[..10], Some(&text[11..])),
+        None => (text.as_str(), None),
+    };
+    let (day, time) = match (parse_day(day, &now), time) {
+        (Some(day), Some(time)) => (day, parse_time_of_day(time).ok_or_else(invalid)?),
+        (Some(day), None) => (day, NaiveTime::from_hms(0, 0, 0)),
+        (None, _) => (
+            now.date().naive_local(),
+            parse_time_of_day(&text).ok_or_else(invalid)?,
+        ),
+    };
+    local_to_utc(&now.timezone(), day.and_time(time)).ok_or_else(invalid)
+}
+
+/// Parses a named period such as `today`, `this-week` or `last-month` into
+/// the local start of the period and the start of the next one.
+pub fn parse_period(input: &str) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    parse_period_from(input, Local::now())
+}
+
+/// Same as `parse_period`, with the periods around `now` in its timezone.
+/// Weeks start on Monday.
+pub fn parse_period_from<Tz: TimeZone>(
+    input: &str,
+    now: DateTime<Tz>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let invalid = || Error::InvalidTimestamp(String::from(input));
+    let today = now.date().naive_local();
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let first_of_month = today.with_day(1).unwrap();
+    let (start, end) = match input.trim().to_lowercase().as_str() {
+        "today" => (today, today + Duration::days(1)),
+        "yesterday" => (today - Duration::days(1), today),
+        "this-week" => (monday, monday + Duration::weeks(1)),
+        "last-week" => (monday - Duration::weeks(1), monday),
+        "this-month" => (first_of_month, add_months(first_of_month, 1)),
+        "last-month" => (add_months(first_of_month, -1), first_of_month),
+        "this-year" => (
+            NaiveDate::from_ymd(today.year(), 1, 1),
+            NaiveDate::from_ymd(today.year() + 1, 1, 1),
+        ),
+        "last-year" => (
+            NaiveDate::from_ymd(today.year() - 1, 1, 1),
+            NaiveDate::from_ymd(today.year(), 1, 1),
+        ),
+        _ => return Err(invalid()),
+    };
+    let midnight = |day: NaiveDate| local_to_utc(&now.timezone(), day.and_hms(0, 0, 0));
+    Ok((
+        midnight(start).ok_or_else(invalid)?,
+        midnight(end).ok_or_else(invalid)?,
+    ))
+}
+
+fn add_months(first_of_month: NaiveDate, months: i32) -> NaiveDate {
+    let month = first_of_month.year() * 12 + first_of_month.month0() as i32 + months;
+    NaiveDate::from_ymd(month.div_euclid(12), month.rem_euclid(12) as u32 + 1, 1)
+}
+
+/// Parses a length of time such as `45m`, `1h30m`, `1.5h`, `90 seconds` or
+/// `2 hours 10 minutes`, of at most `i64::MAX` milliseconds.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let invalid = || Error::InvalidDuration(String::from(input));
+    let text = input.trim().to_lowercase();
+    let mut duration = Duration::zero();
+    let mut rest = text.as_str();
+    let mut parts = 0;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|character: char| !(character.is_ascii_digit() || character == '.'))
+            .unwrap_or(rest.len());
+        let amount = rest[..number_end].parse::<f64>().map_err(|_| invalid())?;
+        rest = rest[number_end..].trim_start();
+        let unit_end = rest
+            .find(|character: char| !character.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let seconds = unit_seconds(&rest[..unit_end]).ok_or_else(invalid)?;
+        rest = rest[unit_end..].trim_start();
+        let millis = (amount * seconds * 1000.0).round();
+        if millis >= i64::MAX as f64 {
+            return Err(invalid());
+        }
+        duration = duration
+            .checked_add(&Duration::milliseconds(millis as i64))
+            .ok_or_else(invalid)?;
+        parts += 1;
+    }
+    if parts == 0 || duration <= Duration::zero() {
+        return Err(invalid());
+    }
+    Ok(duration)
+}
+
+/// Parses an hourly rate such as `95.50 EUR`, `95.5eur` or `USD 120`. The
+/// currency is a three letter code.
+pub fn parse_rate(input: &str) -> Result<Rate> {
+    let invalid = || Error::InvalidRate(String::from(input));
+    let text = input.trim();
+    let letters = text
+        .find(|character: char| character.is_ascii_alphabetic())
+        .ok_or_else(invalid)?;
+    let (amount, currency) = if letters == 0 {
+        let end = text
+            .find(|character: char| !character.is_ascii_alphabetic())
+            .ok_or_else(invalid)?;
+        (&text[end..], &text[..end])
+    } else {
+        (&text[..letters], &text[letters..])
+    };
+    let (amount, currency) = (amount.trim(), currency.trim());
+    if currency.len() != 3
+        || !currency
+            .chars()
+            .all(|character| character.is_ascii_alphabetic())
+    {
+        return Err(invalid());
+    }
+    let amount = Decimal::from_str(amount).map_err(|_| invalid())?;
+    if amount.is_sign_negative() {
+        return Err(invalid());
+    }
+    Ok(Rate::new(amount, currency))
+}
+
+/// Parses a tax percentage such as `20`, `20%` or `VAT 20%`, named `Tax`
+/// when no name is given.
+pub fn parse_tax(input: &str) -> Result<Tax> {
+    let invalid = || Error::InvalidTax(String::from(input));
+    let text = input.trim().trim_end_matches('%').trim_end();
+    let (name, percent) = match text.rsplit_once(char::is_whitespace) {
+        Some((name, percent)) => (name.trim(), percent),
+        None => ("Tax", text),
+    };
+    let percent = Decimal::from_str(percent).map_err(|_| invalid())?;
+    if percent.is_sign_negative() || name.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Tax {
+        name: String::from(name),
+        percent,
+    })
+}
+
+fn unit_seconds(unit: &str) -> Option<f64> {
+    match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(3600.0),
+        "d" | "day" | "days" => Some(86400.0),
+        "w" | "week" | "weeks" => Some(604800.0),
+        _ => None,
+    }
+}
+
+fn parse_day<Tz: TimeZone>(day: &str, now: &DateTime<Tz>) -> Option<NaiveDate> {
+    let today = now.date().naive_local();
+    match day {
+        "today" => Some(today),
+        "yesterday" => Some(today - Duration::days(1)),
+        "tomorrow" => Some(today + Duration::days(1)),
+        _ => match parse_weekday(day) {
+            Some(weekday) => {
+                let days_back = (7 + today.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                Some(today - Duration::days(days_back as i64))
+            }
+            None => NaiveDate::parse_from_str(day, "%Y-%m-%d").ok(),
+        },
+    }
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day {
+        "monday" | "mon" => Some(Weekday::Mon),
+        "tuesday" | "tue" => Some(Weekday::Tue),
+        "wednesday" | "wed" => Some(Weekday::Wed),
+        "thursday" | "thu" => Some(Weekday::Thu),
+        "friday" | "fri" => Some(Weekday::Fri),
+        "saturday" | "sat" => Some(Weekday::Sat),
+        "sunday" | "sun" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn parse_time_of_day(time: &str) -> Option<NaiveTime> {
+    let (clock, meridiem) = if let Some(clock) = time.strip_suffix("am") {
+        (clock.trim(), Some(0))
+    } else if let Some(clock) = time.strip_suffix("pm") {
+        (clock.trim(), Some(12))
+    } else {
+        (time, None)
+    };
+    let mut fields = clock.split(':');
+    let hour = fields.next()?.parse::<u32>().ok()?;
+    let minute = fields
+        .next()
+        .map_or(Some(0), |minute| minute.parse::<u32>().ok())?;
+    let second = fields
+        .next()
+        .map_or(Some(0), |second| second.parse::<u32>().ok())?;
+    if fields.next().is_some() {
+        return None;
+    }
+    let hour = match meridiem {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None if clock.contains(':') => hour,
+        None => return None,
+    };
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
+fn local_to_utc<Tz: TimeZone>(timezone: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    // Wednesday
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2022-01-05T15:30:00+02:00").unwrap()
+    }
+
+    fn parse(input: &str) -> DateTime<Utc> {
+        parse_time_from(input, now()).unwrap()
+    }
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        time.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    #[test]
+    fn test_parse_absolute() {
+        assert_eq!(parse("2022-01-01T01:00:00Z"), utc("2022-01-01T01:00:00Z"));
+        assert_eq!(parse("2022-01-01"), utc("2021-12-31T22:00:00Z"));
+        assert_eq!(parse("2022-01-01 14:00"), utc("2022-01-01T12:00:00Z"));
+        assert_eq!(parse("2022-01-01T14:00"), utc("2022-01-01T12:00:00Z"));
+    }
+
+    #[test]
+    fn test_parse_time_of_today() {
+        assert_eq!(parse("now"), utc("2022-01-05T13:30:00Z"));
+        assert_eq!(parse("09:15"), utc("2022-01-05T07:15:00Z"));
+        assert_eq!(parse("9am"), utc("2022-01-05T07:00:00Z"));
+        assert_eq!(parse("12am"), utc("2022-01-04T22:00:00Z"));
+        assert_eq!(parse("9:30 PM"), utc("2022-01-05T19:30:00Z"));
+    }
+
+    #[test]
+    fn test_parse_named_days() {
+        assert_eq!(parse("today"), utc("2022-01-04T22:00:00Z"));
+        assert_eq!(parse("yesterday 14:00"), utc("2022-01-04T12:00:00Z"));
+        assert_eq!(parse("tomorrow 8am"), utc("2022-01-06T06:00:00Z"));
+        assert_eq!(parse("monday 9am"), utc("2022-01-03T07:00:00Z"));
+        assert_eq!(parse("wednesday 10:00"), utc("2022-01-05T08:00:00Z"));
+        assert_eq!(parse("thu"), utc("2021-12-29T22:00:00Z"));
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        assert_eq!(parse("10 minutes ago"), utc("2022-01-05T13:20:00Z"));
+        assert_eq!(parse("2h ago"), utc("2022-01-05T11:30:00Z"));
+        assert_eq!(parse("-1h30m"), utc("2022-01-05T12:00:00Z"));
+        assert_eq!(parse("+15m"), utc("2022-01-05T13:45:00Z"));
+    }
+
+    #[test]
+    fn test_parse_invalid_time() {
+        for input in [
+            "",
+            "soon",
+            "25:00",
+            "13pm",
+            "monday noon",
+            "-1x",
+            "9",
+            "-99999999w",
+            "+99999999w",
+            "99999999 weeks ago",
+            "-99999999999w",
+        ] {
+            assert_eq!(
+                parse_time_from(input, now()),
+                Err(Error::InvalidTimestamp(String::from(input))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_period() {
+        let period = |input| parse_period_from(input, now()).unwrap();
+        assert_eq!(
+            period("today"),
+            (utc("2022-01-04T22:00:00Z"), utc("2022-01-05T22:00:00Z"))
+        );
+        assert_eq!(
+            period("this-week"),
+            (utc("2022-01-02T22:00:00Z"), utc("2022-01-09T22:00:00Z"))
+        );
+        assert_eq!(
+            period("last-week"),
+            (utc("2021-12-26T22:00:00Z"), utc("2022-01-02T22:00:00Z"))
+        );
+        assert_eq!(
+            period("last-month"),
+            (utc("2021-11-30T22:00:00Z"), utc("2021-12-31T22:00:00Z"))
+        );
+        assert_eq!(
+            parse_period_from("fortnight", now()),
+            Err(Error::InvalidTimestamp(String::from("fortnight")))
+        );
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        let rate = Rate::new(Decimal::new(9550, 2), "EUR");
+        assert_eq!(parse_rate("95.50 EUR").unwrap(), rate);
+        assert_eq!(parse_rate("95.50eur").unwrap(), rate);
+        assert_eq!(parse_rate("EUR 95.50").unwrap(), rate);
+        assert_eq!(parse_rate("0 usd").unwrap().amount, Decimal::ZERO);
+        for input in ["", "95", "EUR", "95 EURO", "-5 EUR", "9x5 EUR", "95 EUR 2"] {
+            assert_eq!(
+                parse_rate(input),
+                Err(Error::InvalidRate(String::from(input)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_tax() {
+        let tax = |name: &str, percent| Tax {
+            name: String::from(name),
+            percent,
+        };
+        assert_eq!(parse_tax("20").unwrap(), tax("Tax", Decimal::from(20)));
+        assert_eq!(
+            parse_tax("Sales tax 8.5%").unwrap(),
+            tax("Sales tax", Decimal::new(85, 1))
+        );
+        for input in ["", "VAT", "-5%", "VAT twenty"] {
+            assert_eq!(
+                parse_tax(input),
+                Err(Error::InvalidTax(String::from(input)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45m").unwrap(), Duration::minutes(45));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::minutes(90));
+        assert_eq!(
+            parse_duration("2 hours 10 minutes").unwrap(),
+            Duration::minutes(130)
+        );
+        assert_eq!(parse_duration("90 seconds").unwrap(), Duration::seconds(90));
+        assert_eq!(
+            parse_duration("99999999w").unwrap(),
+            Duration::weeks(99_999_999)
+        );
+        assert_eq!(
+            parse_duration("9000000000000000s").unwrap(),
+            Duration::seconds(9_000_000_000_000_000)
+        );
+        for input in [
+            "",
+            "10",
+            "1x",
+            "0m",
+            "h",
+            "99999999999999w",
+            "9999999999999999999999h",
+            "9000000000000000s 9000000000000000s",
+        ] {
+            assert_eq!(
+                parse_duration(input),
+                Err(Error::InvalidDuration(String::from(input)))
+            );
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use crate::billing::{Money, Rates};
+use crate::model::Track;
+use crate::rounding::Rounding;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+
+pub const UNTAGGED: &str = "(untagged)";
+
+/// A dimension the tracked time can be aggregated by. Days, weeks and months
+/// are taken in the timezone the report is built in. A track counts in the
+/// group of each of its tags, untagged ones in `UNTAGGED`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Workspace,
+    Project,
+    Name,
+    Tag,
+    Day,
+    Week,
+    Month,
+}
+
+impl GroupBy {
+    pub fn title(&self) -> &'static str {
+        match self {
+            GroupBy::Workspace => "workspace",
+            GroupBy::Project => "project",
+            GroupBy::Name => "name",
+            GroupBy::Tag => "tag",
+            GroupBy::Day => "day",
+            GroupBy::Week => "week",
+            GroupBy::Month => "month",
+        }
+    }
+
+    fn keys(&self, track: &Track, day: NaiveDate) -> Vec<String> {
+        let key = match self {
+            GroupBy::Workspace => track.workspace.clone(),
+            GroupBy::Project => track.project.clone(),
+            GroupBy::Name => track.name.clone(),
+            GroupBy::Tag if track.tags.is_empty() => String::from(UNTAGGED),
+            GroupBy::Tag => return track.tags.clone(),
+            GroupBy::Day => day.format("%Y-%m-%d").to_string(),
+            GroupBy::Week => {
+                let week = day.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            GroupBy::Month => day.format("%Y-%m").to_string(),
+        };
+        vec![key]
+    }
+}
+
+/// Time spent on one value of a dimension, split by the next dimensions.
+/// With tags the groups can add up to more than their parent.
+/// Durations are serialized as whole seconds, the rounded duration and the
+/// amounts only when computed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportGroup {
+    pub key: String,
+    #[serde(serialize_with = "seconds")]
+    pub duration: Duration,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "optional_seconds"
+    )]
+    pub rounded: Option<Duration>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub amounts: Vec<Money>,
+    pub groups: Vec<ReportGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub group_by: Vec<GroupBy>,
+    #[serde(serialize_with = "seconds")]
+    pub total: Duration,
+    /// The total with the durations rounded.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "optional_seconds"
+    )]
+    pub rounded: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    /// Earned by billable tracks, one per currency.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub amounts: Vec<Money>,
+    pub groups: Vec<ReportGroup>,
+}
+
+impl Report {
+    /// Aggregates the time of the tracks spent inside `since..until`, with
+    /// running tracks lasting until `now`. Groups are sorted by key. With
+    /// `rates` the amounts earned are added up too, and with `rounding` the
+    /// rounded time, amounts being computed on it.
+    pub fn build<Tz: TimeZone>(
+        tracks: &[Track],
+        group_by: &[GroupBy],
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        now: DateTime<Tz>,
+        rates: Option<&Rates>,
+        rounding: Option<&Rounding>,
+    ) -> Report {
+        let timezone = now.timezone();
+        let now = now.with_timezone(&Utc);
+        let mut segments = vec![];
+        for track in tracks.iter() {
+            let start = since.map_or(track.start, |since| since.max(track.start));
+            let end = until.map_or(track.end.unwrap_or(now), |until| {
+                until.min(track.end.unwrap_or(now))
+            });
+            let mut day = start.with_timezone(&timezone).date().naive_local();
+            let mut day_start = start;
+            while day_start < end {
+                let next_day = day.succ();
+                let day_end = timezone
+                    .from_local_datetime(&next_day.and_hms(0, 0, 0))
+                    .earliest()
+                    .map_or(end, |midnight| midnight.with_timezone(&Utc).min(end));
+                let duration = track.duration_between(day_start, day_end, now);
+                if duration > Duration::zero() {
+                    segments.push((track, day, duration));
+                }
+                day = next_day;
+                day_start = day_end;
+            }
+        }
+        let rounded: Vec<Option<Duration>> = match rounding {
+            Some(rounding) => rounding.round(&segments).into_iter().map(Some).collect(),
+            None => vec![None; segments.len()],
+        };
+        let mut total = Duration::zero();
+        let mut entries = vec![];
+        let mut amounts = vec![];
+        for ((track, day, duration), rounded) in segments.iter().zip(rounded.iter().copied()) {
+            total = total + *duration;
+            let amount = rates.and_then(|rates| rates.amount(track, rounded.unwrap_or(*duration)));
+            amounts.extend(amount.clone());
+            let mut combinations = vec![vec![]];
+            for group in group_by.iter() {
+                let keys = group.keys(track, *day);
+                combinations = combinations
+                    .into_iter()
+                    .flat_map(|combination: Vec<String>| {
+                        keys.iter().map(move |key| {
+                            let mut combination = combination.clone();
+                            combination.push(key.clone());
+                            combination
+                        })
+                    })
+                    .collect();
+            }
+            entries.extend(
+                combinations
+                    .into_iter()
+                    .map(|keys| (keys, *duration, rounded, amount.clone())),
+            );
+        }
+        Report {
+            since,
+            until,
+            group_by: group_by.to_vec(),
+            total,
+            rounded: sum_rounded(rounded.iter().copied()),
+            rounding: rounding.cloned(),
+            amounts: Money::totals(&amounts),
+            groups: group(entries),
+        }
+    }
+}
+
+pub(crate) fn seconds<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(duration.num_seconds())
+}
+
+fn optional_seconds<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => seconds(duration, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Keys of the remaining dimensions, with the time, the rounded time and the
+/// amount they account for.
+type Entry = (Vec<String>, Duration, Option<Duration>, Option<Money>);
+
+fn sum(entries: &[Entry]) -> Duration {
+    entries
+        .iter()
+        .fold(Duration::zero(), |total, (_, duration, _, _)| {
+            total + *duration
+        })
+}
+
+/// Adds up rounded durations, `None` when not rounded.
+fn sum_rounded(durations: impl Iterator<Item = Option<Duration>>) -> Option<Duration> {
+    durations
+        .collect::<Option<Vec<Duration>>>()
+        .filter(|durations| !durations.is_empty())
+        .map(|durations| {
+            durations
+                .into_iter()
+                .fold(Duration::zero(), |total, duration| total + duration)
+        })
+}
+
+fn group(entries: Vec<Entry>) -> Vec<ReportGroup> {
+    let mut groups: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+    for (mut keys, duration, rounded, amount) in entries.into_iter() {
+        if keys.is_empty() {
+            continue;
+        }
+        let key = keys.remove(0);
+        groups
+            .entry(key)
+            .or_default()
+            .push((keys, duration, rounded, amount));
+    }
+    groups
+        .into_iter()
+        .map(|(key, entries)| ReportGroup {
+            key,
+            duration: sum(&entries),
+            rounded: sum_rounded(entries.iter().map(|(_, _, rounded, _)| *rounded)),
+            amounts: Money::totals(
+                entries
+                    .iter()
+                    .filter_map(|(_, _, _, amount)| amount.as_ref()),
+            ),
+            groups: group(entries),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Rate;
+    use crate::rounding::RoundingMode;
+    use chrono::FixedOffset;
+    use rust_decimal::Decimal;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    fn create_track(name: &str, project: &str, start: &str, end: Option<&str>) -> Track {
+        Track::create(
+            String::from(name),
+            String::from(name),
+            at(start),
+            end.map(at),
+            String::from(project),
+            String::from("Workspace"),
+        )
+    }
+
+    fn create_tracks() -> Vec<Track> {
+        vec![
+            create_track(
+                "a",
+                "Project1",
+                "2022-01-03T09:00:00Z",
+                Some("2022-01-03T10:00:00Z"),
+            ),
+            create_track(
+                "b",
+                "Project2",
+                "2022-01-03T23:00:00Z",
+                Some("2022-01-04T01:00:00Z"),
+            ),
+            create_track("c", "Project1", "2022-01-04T09:00:00Z", None),
+        ]
+    }
+
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2022-01-04T10:30:00+00:00").unwrap()
+    }
+
+    fn summary(groups: &[ReportGroup]) -> Vec<(String, i64)> {
+        groups
+            .iter()
+            .map(|group| (group.key.clone(), group.duration.num_minutes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_report_by_project_and_day() {
+        let report = Report::build(
+            &create_tracks(),
+            &[GroupBy::Project, GroupBy::Day],
+            None,
+            None,
+            now(),
+            None,
+            None,
+        );
+        assert_eq!(report.total, Duration::minutes(270));
+        assert_eq!(
+            summary(&report.groups),
+            vec![
+                (String::from("Project1"), 150),
+                (String::from("Project2"), 120)
+            ]
+        );
+        assert_eq!(
+            summary(&report.groups[0].groups),
+            vec![
+                (String::from("2022-01-03"), 60),
+                (String::from("2022-01-04"), 90)
+            ]
+        );
+        assert_eq!(
+            summary(&report.groups[1].groups),
+            vec![
+                (String::from("2022-01-03"), 60),
+                (String::from("2022-01-04"), 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serialize_report() {
+        let report = Report::build(
+            &create_tracks()[..1],
+            &[GroupBy::Project],
+            None,
+            None,
+            now(),
+            None,
+            None,
+        );
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            "{\"since\":null,\"until\":null,\"group_by\":[\"project\"],\"total\":3600,\
+             \"groups\":[{\"key\":\"Project1\",\"duration\":3600,\"groups\":[]}]}"
+        );
+    }
+
+    #[test]
+    fn test_report_by_tag() {
+        let mut tracks = create_tracks();
+        tracks[0].set_tags(vec![String::from("billable"), String::from("review")]);
+        tracks[1].set_tags(vec![String::from("review")]);
+        let report = Report::build(&tracks, &[GroupBy::Tag], None, None, now(), None, None);
+        assert_eq!(report.total, Duration::minutes(270));
+        assert_eq!(
+            summary(&report.groups),
+            vec![
+                (String::from(UNTAGGED), 90),
+                (String::from("billable"), 60),
+                (String::from("review"), 180)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_amounts() {
+        let mut tracks = create_tracks();
+        tracks[0].rate = Some(Rate::new(Decimal::from(90), "EUR"));
+        tracks[1].rate = Some(Rate::new(Decimal::new(1005, 1), "usd"));
+        tracks[2].rate = Some(Rate::new(Decimal::from(90), "EUR"));
+        tracks[2].billable = false;
+        let report = Report::build(
+            &tracks,
+            &[GroupBy::Project],
+            None,
+            None,
+            now(),
+            Some(&Rates::default()),
+            None,
+        );
+        let money = |amount: i64, currency: &str| Money {
+            amount: Decimal::from(amount),
+            currency: String::from(currency),
+        };
+        assert_eq!(report.amounts, vec![money(90, "EUR"), money(201, "USD")]);
+        assert_eq!(report.groups[0].amounts, vec![money(90, "EUR")]);
+        assert_eq!(report.groups[1].amounts, vec![money(201, "USD")]);
+    }
+
+    #[test]
+    fn test_report_rounding() {
+        let mut tracks = create_tracks();
+        tracks[0].end = Some(at("2022-01-03T09:07:00Z"));
+        tracks[0].rate = Some(Rate::new(Decimal::from(60), "EUR"));
+        let rounding = Rounding {
+            increment: Duration::minutes(15),
+            mode: RoundingMode::Up,
+            ..Rounding::default()
+        };
+        let report = Report::build(
+            &tracks,
+            &[GroupBy::Project],
+            None,
+            None,
+            now(),
+            Some(&Rates::default()),
+            Some(&rounding),
+        );
+        assert_eq!(report.total, Duration::minutes(217));
+        assert_eq!(report.rounded, Some(Duration::minutes(225)));
+        assert_eq!(report.groups[0].duration, Duration::minutes(97));
+        assert_eq!(report.groups[0].rounded, Some(Duration::minutes(105)));
+        assert_eq!(report.amounts[0].amount, Decimal::from(15));
+        assert_eq!(tracks[0].duration(), Duration::minutes(7));
+    }
+
+    #[test]
+    fn test_report_rounds_tracks_across_midnight_once() {
+        let tracks = vec![Track::create(
+            String::from("a1"),
+            String::from("MyTrack"),
+            at("2022-01-03T23:50:00Z"),
+            Some(at("2022-01-04T00:20:00Z")),
+            String::from("Project1"),
+            String::from("Workspace"),
+        )];
+        let rounding = Rounding {
+            increment: Duration::minutes(15),
+            mode: RoundingMode::Up,
+            minimum: Duration::minutes(30),
+            ..Rounding::default()
+        };
+        let report = Report::build(
+            &tracks,
+            &[GroupBy::Day],
+            None,
+            None,
+            now(),
+            None,
+            Some(&rounding),
+        );
+        assert_eq!(report.total, Duration::minutes(30));
+        assert_eq!(report.rounded, Some(Duration::minutes(30)));
+        assert_eq!(
+            report
+                .groups
+                .iter()
+                .map(|group| (group.key.clone(), group.rounded))
+                .collect::<Vec<_>>(),
+            vec![
+                (String::from("2022-01-03"), Some(Duration::minutes(10))),
+                (String::from("2022-01-04"), Some(Duration::minutes(20))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_clips_period() {
+        let report = Report::build(
+            &create_tracks(),
+            &[GroupBy::Week],
+            Some(at("2022-01-03T09:30:00Z")),
+            Some(at("2022-01-04T00:00:00Z")),
+            now(),
+            None,
+            None,
+        );
+        assert_eq!(report.total, Duration::minutes(90));
+        assert_eq!(
+            summary(&report.groups),
+            vec![(String::from("2022-W01"), 90)]
+        );
+    }
+
+    #[test]
+    fn test_report_days_in_timezone() {
+        let now = DateTime::parse_from_rfc3339("2022-01-04T12:30:00+02:00").unwrap();
+        let report = Report::build(
+            &create_tracks(),
+            &[GroupBy::Day],
+            None,
+            None,
+            now,
+            None,
+            None,
+        );
+        assert_eq!(
+            summary(&report.groups),
+            vec![
+                (String::from("2022-01-03"), 60),
+                (String::from("2022-01-04"), 210)
+            ]
+        );
+    }
+}
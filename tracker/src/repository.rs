@@ -1,7 +1,173 @@
-use crate::model::Track;
+use crate::error::Result;
+use crate::invoice::Invoice;
+use crate::model::{Project, Track, Workspace};
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Order {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Filters for `TrackRepository::find_by`. A time range matches every track
+/// overlapping it, running tracks are considered to last until now.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub project: Option<String>,
+    pub workspace: Option<String>,
+    /// Only tracks of projects billed to this client, projects without a
+    /// client being billed to their workspace.
+    pub client: Option<String>,
+    /// Only tracks already invoiced, or only the ones not invoiced yet.
+    pub invoiced: Option<bool>,
+    pub name: Option<String>,
+    /// Only tracks whose name or notes contain this text.
+    pub grep: Option<String>,
+    /// Only tracks having every one of these tags.
+    pub tags: Vec<String>,
+    pub id_prefix: Option<String>,
+    pub running_only: bool,
+    pub deleted: bool,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub order: Order,
+}
+
+impl TrackQuery {
+    pub fn new() -> TrackQuery {
+        TrackQuery::default()
+    }
+
+    pub fn since(mut self, since: DateTime<Utc>) -> TrackQuery {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: DateTime<Utc>) -> TrackQuery {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn project(mut self, project: String) -> TrackQuery {
+        self.project = Some(project);
+        self
+    }
+
+    pub fn workspace(mut self, workspace: String) -> TrackQuery {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    pub fn client(mut self, client: String) -> TrackQuery {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn invoiced(mut self, invoiced: bool) -> TrackQuery {
+        self.invoiced = Some(invoiced);
+        self
+    }
+
+    pub fn name(mut self, name: String) -> TrackQuery {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn grep(mut self, text: String) -> TrackQuery {
+        self.grep = Some(text);
+        self
+    }
+
+    pub fn tag(mut self, tag: String) -> TrackQuery {
+        let tag = tag.trim().to_lowercase();
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+        self
+    }
+
+    pub fn id_prefix(mut self, prefix: String) -> TrackQuery {
+        self.id_prefix = Some(prefix);
+        self
+    }
+
+    /// Searches the trash instead of the live tracks.
+    pub fn deleted(mut self) -> TrackQuery {
+        self.deleted = true;
+        self
+    }
+
+    pub fn running_only(mut self) -> TrackQuery {
+        self.running_only = true;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> TrackQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> TrackQuery {
+        self.offset = offset;
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> TrackQuery {
+        self.order = order;
+        self
+    }
+}
 
 pub trait TrackRepository {
-    fn save(&self, track: &Track) -> Result<(), String>;
-    fn find(&self, id: String) -> Result<Track, String>;
-    fn find_all(&self) -> Result<Vec<Track>, String>;
+    fn save(&self, track: &Track) -> Result<()>;
+    fn find(&self, id: String) -> Result<Track>;
+    fn find_all(&self) -> Result<Vec<Track>>;
+    fn find_by(&self, query: &TrackQuery) -> Result<Vec<Track>>;
+    fn find_running(&self) -> Result<Option<Track>>;
+    fn update(&self, track: &Track) -> Result<()>;
+    /// Moves the track to the trash, from where it can be restored.
+    fn delete(&self, id: String) -> Result<()>;
+    fn restore(&self, id: String) -> Result<()>;
+    /// Starts a transaction, ended by `commit` or `rollback`, covering the
+    /// other repositories of the same database too. Transactions nest.
+    fn begin(&self) -> Result<()>;
+    fn commit(&self) -> Result<()>;
+    fn rollback(&self) -> Result<()>;
+}
+
+pub trait WorkspaceRepository {
+    /// Inserts the workspace or updates the one with the same id.
+    fn save(&self, workspace: &Workspace) -> Result<()>;
+    /// Finds a workspace by name, ignoring case.
+    fn find_by_name(&self, name: &str) -> Result<Option<Workspace>>;
+    /// Every workspace, archived ones too, sorted by name.
+    fn find_all(&self) -> Result<Vec<Workspace>>;
+}
+
+/// Preferences kept in the database, such as the context in use.
+pub trait SettingsRepository {
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    /// Stores the value of `key`, removing it when `None`.
+    fn set(&self, key: &str, value: Option<&str>) -> Result<()>;
+}
+
+pub trait ProjectRepository {
+    /// Inserts the project or updates the one with the same id. Its workspace
+    /// must exist.
+    fn save(&self, project: &Project) -> Result<()>;
+    /// Finds a project by workspace and name, ignoring case.
+    fn find_by_name(&self, workspace: &str, name: &str) -> Result<Option<Project>>;
+    /// Every project, archived ones too, sorted by workspace and name.
+    fn find_all(&self) -> Result<Vec<Project>>;
+}
+
+pub trait InvoiceRepository {
+    /// Stores the invoice and marks its tracks as invoiced, failing when its
+    /// number is taken or one of its tracks is already invoiced.
+    fn save(&self, invoice: &Invoice) -> Result<()>;
+    /// Numbers of the stored invoices, in the order they were issued.
+    fn numbers(&self) -> Result<Vec<String>>;
 }
@@ -1,149 +1,862 @@
-use crate::model::Track;
-use crate::repository::TrackRepository;
-use chrono::{DateTime, Utc};
+use crate::error::{Error, Result};
+use crate::invoice::Invoice;
+use crate::model::{Pause, Project, Rate, Track, Workspace};
+use crate::repository::{
+    InvoiceRepository, Order, ProjectRepository, SettingsRepository, TrackQuery, TrackRepository,
+    WorkspaceRepository,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
 use sqlite::Value;
-use std::sync::Arc;
-
-pub const SCHEME: &str = "
-    CREATE TABLE IF NOT EXISTS tracks (
-        id TEXT,
-        name TEXT,
-        start TEXT,
-        end TEXT,
-        project TEXT,
-        workspace TEXT
-    );
-";
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::str::FromStr;
+
+const COLUMNS: &str = "id, name, start, end, project, workspace, notes, rate, currency, billable,
+    invoice";
+/// Tracks whose pauses and tags are loaded with a single query.
+const LOAD_BATCH: usize = 500;
 
 pub struct RepositorySQLite {
-    connection: Arc<sqlite::Connection>,
+    connection: Rc<sqlite::Connection>,
 }
 
 impl RepositorySQLite {
-    pub fn create(connection: Arc<sqlite::Connection>) -> RepositorySQLite {
+    pub fn create(connection: Rc<sqlite::Connection>) -> RepositorySQLite {
         RepositorySQLite { connection }
     }
 
-    fn save_in_sqlite(&self, track: &Track) -> Result<(), sqlite::Error> {
-        let sql = match self.find_in_sqlite(track.id.clone()) {
-            Ok(_) => "UPDATE tracks SET name = :name, start = :start, end = :end, project = :project, workspace = :workspace WHERE id = :id",
-            Err(_) => "INSERT INTO tracks VALUES(:id, :name, :start, :end, :project, :workspace)"
-        };
-        let statement = self
+    /// Id of the project of the track, which must exist along with its
+    /// workspace: projects are only added through the project service.
+    fn project_id(&self, track: &Track) -> Result<String> {
+        let mut cursor = self
             .connection
-            .prepare(sql)
-            .unwrap();
+            .prepare(
+                "SELECT projects.id FROM projects
+                JOIN workspaces ON workspaces.id = projects.workspace_id
+                WHERE workspaces.name = :workspace AND projects.name = :name",
+            )?
+            .into_cursor();
+        cursor.bind_by_name(vec![
+            (":name", Value::String(track.project.clone())),
+            (":workspace", Value::String(track.workspace.clone())),
+        ])?;
+        if let Some(row) = cursor.next()? {
+            return Ok(String::from(read_string(row, 0)?));
+        }
+        let mut cursor = self
+            .connection
+            .prepare("SELECT id FROM workspaces WHERE name = :name")?
+            .into_cursor();
+        cursor.bind_by_name(vec![(":name", Value::String(track.workspace.clone()))])?;
+        match cursor.next()? {
+            Some(_) => Err(Error::UnknownProject(
+                track.project.clone(),
+                track.workspace.clone(),
+            )),
+            None => Err(Error::UnknownWorkspace(track.workspace.clone())),
+        }
+    }
+
+    fn save_in_sqlite(&self, track: &Track) -> Result<()> {
+        let project_id = self.project_id(track)?;
+        let statement = self.connection.prepare(
+            "INSERT INTO tracks (id, name, start, end, project_id, notes, rate, currency, billable)
+            VALUES (:id, :name, :start, :end, :project_id, :notes, :rate, :currency, :billable)
+            ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                start = excluded.start,
+                end = excluded.end,
+                project_id = excluded.project_id,
+                notes = excluded.notes,
+                rate = excluded.rate,
+                currency = excluded.currency,
+                billable = excluded.billable",
+        )?;
+        let (rate, currency) = rate_values(&track.rate);
         let mut cursor = statement.into_cursor();
         cursor.bind_by_name(vec![
             (":id", Value::String(track.id.to_string())),
             (":name", Value::String(track.name.to_string())),
-            (":start", Value::String(track.start.to_string())),
+            (":start", Value::Integer(track.start.timestamp_millis())),
             (
                 ":end",
-                Value::String(match track.end {
-                    Some(end) => end.to_string(),
-                    _ => String::from(""),
-                }),
+                match track.end {
+                    Some(end) => Value::Integer(end.timestamp_millis()),
+                    _ => Value::Null,
+                },
             ),
-            (":project", Value::String(track.project.to_string())),
-            (":workspace", Value::String(track.workspace.to_string())),
+            (":project_id", Value::String(project_id)),
+            (":notes", Value::String(track.notes.to_string())),
+            (":rate", rate),
+            (":currency", currency),
+            (":billable", Value::Integer(track.billable as i64)),
         ])?;
         cursor.next()?;
+        self.save_pauses(track)?;
+        self.save_tags(track)
+    }
+
+    fn save_pauses(&self, track: &Track) -> Result<()> {
+        let mut cursor = self
+            .connection
+            .prepare("DELETE FROM pauses WHERE track_id = :track_id")?
+            .into_cursor();
+        cursor.bind_by_name(vec![(":track_id", Value::String(track.id.clone()))])?;
+        cursor.next()?;
+        for pause in track.pauses.iter() {
+            let mut cursor = self
+                .connection
+                .prepare(
+                    "INSERT INTO pauses (track_id, start, end) VALUES (:track_id, :start, :end)",
+                )?
+                .into_cursor();
+            cursor.bind_by_name(vec![
+                (":track_id", Value::String(track.id.clone())),
+                (":start", Value::Integer(pause.start.timestamp_millis())),
+                (
+                    ":end",
+                    match pause.end {
+                        Some(end) => Value::Integer(end.timestamp_millis()),
+                        _ => Value::Null,
+                    },
+                ),
+            ])?;
+            cursor.next()?;
+        }
         Ok(())
     }
 
-    fn find_in_sqlite(&self, id: String) -> Result<Track, ()> {
-        let statement = self
+    fn save_tags(&self, track: &Track) -> Result<()> {
+        let mut cursor = self
             .connection
-            .prepare("SELECT * FROM tracks WHERE id = :id")
-            .unwrap();
-        let mut cursor = statement.into_cursor();
-        cursor
-            .bind_by_name(vec![(":id", Value::String(id))])
-            .unwrap();
-        if let Some(row) = cursor.next().unwrap() {
-            self.convert_row_to_entity(&row)
+            .prepare("DELETE FROM track_tags WHERE track_id = :track_id")?
+            .into_cursor();
+        cursor.bind_by_name(vec![(":track_id", Value::String(track.id.clone()))])?;
+        cursor.next()?;
+        for tag in track.tags.iter() {
+            let mut cursor = self
+                .connection
+                .prepare("INSERT OR IGNORE INTO tags (name) VALUES (:name)")?
+                .into_cursor();
+            cursor.bind_by_name(vec![(":name", Value::String(tag.clone()))])?;
+            cursor.next()?;
+            let mut cursor = self
+                .connection
+                .prepare(
+                    "INSERT INTO track_tags (track_id, tag_id)
+                    SELECT :track_id, id FROM tags WHERE name = :name",
+                )?
+                .into_cursor();
+            cursor.bind_by_name(vec![
+                (":track_id", Value::String(track.id.clone())),
+                (":name", Value::String(tag.clone())),
+            ])?;
+            cursor.next()?;
+        }
+        Ok(())
+    }
+
+    /// Fills the tags of the tracks, querying them in batches.
+    fn load_tags(&self, tracks: &mut [Track]) -> Result<()> {
+        for chunk in tracks.chunks_mut(LOAD_BATCH) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut cursor = self
+                .connection
+                .prepare(format!(
+                    "SELECT track_id, name FROM track_tags JOIN tags ON tags.id = track_tags.tag_id
+                    WHERE track_id IN ({}) ORDER BY name",
+                    placeholders
+                ))?
+                .into_cursor();
+            let ids: Vec<Value> = chunk
+                .iter()
+                .map(|track| Value::String(track.id.clone()))
+                .collect();
+            cursor.bind(&ids)?;
+            let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+            while let Some(row) = cursor.next()? {
+                tags.entry(String::from(read_string(row, 0)?))
+                    .or_default()
+                    .push(String::from(read_string(row, 1)?));
+            }
+            for track in chunk.iter_mut() {
+                track.tags = tags.remove(&track.id).unwrap_or_default();
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the pauses of the tracks, querying them in batches.
+    fn load_pauses(&self, tracks: &mut [Track]) -> Result<()> {
+        for chunk in tracks.chunks_mut(LOAD_BATCH) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut cursor = self
+                .connection
+                .prepare(format!(
+                    "SELECT track_id, start, end FROM pauses WHERE track_id IN ({}) ORDER BY start",
+                    placeholders
+                ))?
+                .into_cursor();
+            let ids: Vec<Value> = chunk
+                .iter()
+                .map(|track| Value::String(track.id.clone()))
+                .collect();
+            cursor.bind(&ids)?;
+            let mut pauses: HashMap<String, Vec<Pause>> = HashMap::new();
+            while let Some(row) = cursor.next()? {
+                let pause = Pause {
+                    start: read_timestamp(row, 1)?,
+                    end: match row.get(2) {
+                        Some(Value::Null) => None,
+                        _ => Some(read_timestamp(row, 2)?),
+                    },
+                };
+                pauses
+                    .entry(String::from(read_string(row, 0)?))
+                    .or_default()
+                    .push(pause);
+            }
+            for track in chunk.iter_mut() {
+                track.pauses = pauses.remove(&track.id).unwrap_or_default();
+            }
+        }
+        Ok(())
+    }
+
+    fn find_in_sqlite(&self, id: String) -> Result<Track> {
+        let mut tracks = self.select_tracks(
+            "SELECT {} FROM tracks_named WHERE id = :id AND deleted_at IS NULL",
+            vec![(":id", Value::String(id.clone()))],
+        )?;
+        tracks.pop().ok_or(Error::NotFound(id))
+    }
+
+    fn find_all_in_sqlite(&self) -> Result<Vec<Track>> {
+        self.select_tracks(
+            "SELECT {} FROM tracks_named WHERE deleted_at IS NULL ORDER BY start ASC, rowid ASC",
+            vec![],
+        )
+    }
+
+    fn find_by_in_sqlite(&self, query: &TrackQuery) -> Result<Vec<Track>> {
+        let tag_names: Vec<String> = (0..query.tags.len())
+            .map(|index| format!(":tag{}", index))
+            .collect();
+        let tag_condition = format!(
+            "id IN (SELECT track_id FROM track_tags JOIN tags ON tags.id = track_tags.tag_id
+                WHERE tags.name IN ({}) GROUP BY track_id HAVING COUNT(*) = {})",
+            tag_names.join(", "),
+            query.tags.len()
+        );
+        let mut conditions = vec![if query.deleted {
+            "deleted_at IS NOT NULL"
         } else {
-            Err(())
+            "deleted_at IS NULL"
+        }];
+        let mut values = vec![];
+        if let Some(prefix) = &query.id_prefix {
+            conditions.push("id LIKE :id_prefix ESCAPE '\\'");
+            values.push((
+                ":id_prefix",
+                Value::String(format!("{}%", escape_like(prefix))),
+            ));
+        }
+        if let Some(since) = query.since {
+            conditions.push("(end IS NULL OR end > :since)");
+            values.push((":since", Value::Integer(since.timestamp_millis())));
+        }
+        if let Some(until) = query.until {
+            conditions.push("start < :until");
+            values.push((":until", Value::Integer(until.timestamp_millis())));
+        }
+        if let Some(project) = &query.project {
+            conditions.push("project = :project");
+            values.push((":project", Value::String(project.clone())));
+        }
+        if let Some(workspace) = &query.workspace {
+            conditions.push("workspace = :workspace");
+            values.push((":workspace", Value::String(workspace.clone())));
+        }
+        if let Some(name) = &query.name {
+            conditions.push("name LIKE :name ESCAPE '\\'");
+            values.push((":name", Value::String(format!("%{}%", escape_like(name)))));
+        }
+        if let Some(text) = &query.grep {
+            conditions.push("(name LIKE :grep ESCAPE '\\' OR notes LIKE :grep ESCAPE '\\')");
+            values.push((":grep", Value::String(format!("%{}%", escape_like(text)))));
+        }
+        if let Some(client) = &query.client {
+            conditions.push("client = :client COLLATE NOCASE");
+            values.push((":client", Value::String(client.clone())));
+        }
+        if let Some(invoiced) = query.invoiced {
+            conditions.push(if invoiced {
+                "invoice_id IS NOT NULL"
+            } else {
+                "invoice_id IS NULL"
+            });
+        }
+        if query.running_only {
+            conditions.push("end IS NULL");
         }
+        if !query.tags.is_empty() {
+            conditions.push(&tag_condition);
+            for (name, tag) in tag_names.iter().zip(query.tags.iter()) {
+                values.push((name, Value::String(tag.clone())));
+            }
+        }
+        let mut sql = String::from("SELECT {} FROM tracks_named WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+        sql.push_str(match query.order {
+            Order::Ascending => " ORDER BY start ASC, rowid ASC",
+            Order::Descending => " ORDER BY start DESC, rowid DESC",
+        });
+        sql.push_str(" LIMIT :limit OFFSET :offset");
+        values.push((
+            ":limit",
+            Value::Integer(query.limit.map_or(-1, |limit| limit as i64)),
+        ));
+        values.push((":offset", Value::Integer(query.offset as i64)));
+        self.select_tracks(&sql, values)
     }
 
-    fn find_all_in_sqlite(&self) -> Result<Vec<Track>, ()> {
-        let statement = self
-            .connection
-            .prepare("SELECT * FROM tracks ORDER BY end ASC")
-            .unwrap();
+    fn find_running_in_sqlite(&self) -> Result<Option<Track>> {
+        let mut tracks = self.select_tracks(
+            "SELECT {} FROM tracks_named WHERE end IS NULL AND deleted_at IS NULL ORDER BY start DESC LIMIT 1",
+            vec![],
+        )?;
+        Ok(tracks.pop())
+    }
+
+    fn update_in_sqlite(&self, track: &Track) -> Result<()> {
+        let project_id = self.project_id(track)?;
+        let (rate, currency) = rate_values(&track.rate);
+        self.change_one(
+            "UPDATE tracks SET name = :name, start = :start, end = :end,
+                project_id = :project_id, notes = :notes, rate = :rate, currency = :currency,
+                billable = :billable
+            WHERE id = :id AND deleted_at IS NULL",
+            &track.id,
+            vec![
+                (":name", Value::String(track.name.to_string())),
+                (":start", Value::Integer(track.start.timestamp_millis())),
+                (
+                    ":end",
+                    match track.end {
+                        Some(end) => Value::Integer(end.timestamp_millis()),
+                        _ => Value::Null,
+                    },
+                ),
+                (":project_id", Value::String(project_id)),
+                (":notes", Value::String(track.notes.to_string())),
+                (":rate", rate),
+                (":currency", currency),
+                (":billable", Value::Integer(track.billable as i64)),
+            ],
+        )?;
+        self.save_pauses(track)?;
+        self.save_tags(track)
+    }
+
+    fn delete_in_sqlite(&self, id: &str) -> Result<()> {
+        self.change_one(
+            "UPDATE tracks SET deleted_at = :now WHERE id = :id AND deleted_at IS NULL",
+            id,
+            vec![(":now", Value::Integer(Utc::now().timestamp_millis()))],
+        )
+    }
+
+    fn restore_in_sqlite(&self, id: &str) -> Result<()> {
+        self.change_one(
+            "UPDATE tracks SET deleted_at = NULL WHERE id = :id AND deleted_at IS NOT NULL",
+            id,
+            vec![],
+        )
+    }
+
+    /// Runs a statement expected to change the track `id`, which is reported
+    /// as not found when no row matched.
+    fn change_one(&self, sql: &str, id: &str, mut values: Vec<(&str, Value)>) -> Result<()> {
+        values.push((":id", Value::String(String::from(id))));
+        let mut cursor = self.connection.prepare(sql)?.into_cursor();
+        cursor.bind_by_name(values)?;
+        cursor.next()?;
+        if self.connection.change_count() == 0 {
+            return Err(Error::NotFound(String::from(id)));
+        }
+        Ok(())
+    }
+
+    /// Runs a query whose `{}` placeholder is replaced by the track columns.
+    fn select_tracks(&self, sql: &str, values: Vec<(&str, Value)>) -> Result<Vec<Track>> {
+        let statement = self.connection.prepare(sql.replace("{}", COLUMNS))?;
         let mut cursor = statement.into_cursor();
+        cursor.bind_by_name(values)?;
         let mut tasks = vec![];
-        while let Some(row) = cursor.next().unwrap() {
-            tasks.push(self.convert_row_to_entity(&row)?);
+        while let Some(row) = cursor.next()? {
+            tasks.push(self.convert_row_to_entity(row)?);
         }
+        self.load_pauses(&mut tasks)?;
+        self.load_tags(&mut tasks)?;
         Ok(tasks)
     }
 
-    fn convert_row_to_entity(&self, row: &[Value]) -> Result<Track, ()> {
-        let start = row[2]
-            .as_string()
-            .unwrap()
-            .parse::<DateTime<Utc>>()
-            .unwrap();
-        let mut end: Option<DateTime<Utc>> = None;
-        let end_row = row[3].as_string().unwrap();
-        if end_row.len() > 0 {
-            let end_parsed = end_row.parse::<DateTime<Utc>>().unwrap();
-            end = Some(end_parsed);
-        }
-        let track = Track::create(
-            String::from(row[0].as_string().unwrap()),
-            String::from(row[1].as_string().unwrap()),
+    fn convert_row_to_entity(&self, row: &[Value]) -> Result<Track> {
+        let start = read_timestamp(row, 2)?;
+        let end = match row.get(3) {
+            Some(Value::Null) => None,
+            _ => Some(read_timestamp(row, 3)?),
+        };
+        let mut track = Track::create(
+            String::from(read_string(row, 0)?),
+            String::from(read_string(row, 1)?),
             start,
             end,
-            String::from(row[4].as_string().unwrap()),
-            String::from(row[5].as_string().unwrap()),
+            String::from(read_string(row, 4)?),
+            String::from(read_string(row, 5)?),
         );
+        track.notes = String::from(read_string(row, 6)?);
+        track.rate = read_rate(row, 7)?;
+        track.billable = row.get(9).and_then(|value| value.as_integer()) != Some(0);
+        track.invoice = read_optional_string(row, 10)?;
         Ok(track)
     }
 }
 
+/// Runs `operation` atomically, rolling back every change when it fails.
+fn in_transaction<T>(
+    connection: &sqlite::Connection,
+    operation: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    connection.execute("SAVEPOINT repository")?;
+    match operation() {
+        Ok(value) => {
+            connection.execute("RELEASE repository")?;
+            Ok(value)
+        }
+        Err(error) => {
+            connection.execute("ROLLBACK TO repository; RELEASE repository")?;
+            Err(error)
+        }
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn read_string(row: &[Value], index: usize) -> Result<&str> {
+    row.get(index)
+        .and_then(|value| value.as_string())
+        .ok_or_else(|| Error::Storage(format!("unexpected value in column {}", index)))
+}
+
+fn read_timestamp(row: &[Value], index: usize) -> Result<DateTime<Utc>> {
+    let millis = match row.get(index) {
+        Some(Value::Integer(millis)) => *millis,
+        Some(Value::String(value)) => return Err(Error::InvalidTimestamp(value.clone())),
+        value => return Err(Error::InvalidTimestamp(format!("{:?}", value))),
+    };
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .ok_or_else(|| Error::InvalidTimestamp(millis.to_string()))
+}
+
 impl TrackRepository for RepositorySQLite {
-    fn save(&self, track: &Track) -> Result<(), String> {
-        match self.save_in_sqlite(track) {
-            Err(_) => Err(String::from(
-                "An error happen when tried prepare to save the track",
-            )),
-            _ => Ok(()),
+    fn save(&self, track: &Track) -> Result<()> {
+        in_transaction(&self.connection, || self.save_in_sqlite(track))
+    }
+
+    fn find(&self, id: String) -> Result<Track> {
+        self.find_in_sqlite(id)
+    }
+
+    fn find_all(&self) -> Result<Vec<Track>> {
+        self.find_all_in_sqlite()
+    }
+
+    fn find_by(&self, query: &TrackQuery) -> Result<Vec<Track>> {
+        self.find_by_in_sqlite(query)
+    }
+
+    fn find_running(&self) -> Result<Option<Track>> {
+        self.find_running_in_sqlite()
+    }
+
+    fn update(&self, track: &Track) -> Result<()> {
+        in_transaction(&self.connection, || self.update_in_sqlite(track))
+    }
+
+    fn delete(&self, id: String) -> Result<()> {
+        self.delete_in_sqlite(&id)
+    }
+
+    fn restore(&self, id: String) -> Result<()> {
+        self.restore_in_sqlite(&id)
+    }
+
+    fn begin(&self) -> Result<()> {
+        Ok(self.connection.execute("SAVEPOINT service")?)
+    }
+
+    fn commit(&self) -> Result<()> {
+        Ok(self.connection.execute("RELEASE service")?)
+    }
+
+    fn rollback(&self) -> Result<()> {
+        Ok(self
+            .connection
+            .execute("ROLLBACK TO service; RELEASE service")?)
+    }
+}
+
+fn read_optional_string(row: &[Value], index: usize) -> Result<Option<String>> {
+    match row.get(index) {
+        Some(Value::Null) => Ok(None),
+        _ => read_string(row, index).map(|value| Some(String::from(value))),
+    }
+}
+
+/// Reads a rate stored as its amount text followed by its currency.
+fn read_rate(row: &[Value], index: usize) -> Result<Option<Rate>> {
+    match (
+        read_optional_string(row, index)?,
+        read_optional_string(row, index + 1)?,
+    ) {
+        (Some(amount), Some(currency)) => Decimal::from_str(&amount)
+            .map(|amount| Some(Rate::new(amount, &currency)))
+            .map_err(|_| Error::Storage(format!("invalid rate {} in column {}", amount, index))),
+        _ => Ok(None),
+    }
+}
+
+fn rate_values(rate: &Option<Rate>) -> (Value, Value) {
+    match rate {
+        Some(rate) => (
+            Value::String(rate.amount.to_string()),
+            Value::String(rate.currency.clone()),
+        ),
+        None => (Value::Null, Value::Null),
+    }
+}
+
+fn optional_string(value: &Option<String>) -> Value {
+    match value {
+        Some(value) => Value::String(value.clone()),
+        None => Value::Null,
+    }
+}
+
+pub struct WorkspaceRepositorySQLite {
+    connection: Rc<sqlite::Connection>,
+}
+
+impl WorkspaceRepositorySQLite {
+    pub fn create(connection: Rc<sqlite::Connection>) -> WorkspaceRepositorySQLite {
+        WorkspaceRepositorySQLite { connection }
+    }
+
+    fn select_workspaces(&self, sql: &str, values: Vec<(&str, Value)>) -> Result<Vec<Workspace>> {
+        let mut cursor = self.connection.prepare(sql)?.into_cursor();
+        cursor.bind_by_name(values)?;
+        let mut workspaces = vec![];
+        while let Some(row) = cursor.next()? {
+            workspaces.push(Workspace {
+                id: String::from(read_string(row, 0)?),
+                name: String::from(read_string(row, 1)?),
+                color: read_optional_string(row, 2)?,
+                archived: row[3].as_integer() == Some(1),
+                rate: read_rate(row, 4)?,
+            });
         }
+        Ok(workspaces)
+    }
+}
+
+impl WorkspaceRepository for WorkspaceRepositorySQLite {
+    fn save(&self, workspace: &Workspace) -> Result<()> {
+        let mut cursor = self
+            .connection
+            .prepare(
+                "INSERT INTO workspaces (id, name, color, archived, rate, currency)
+                VALUES (:id, :name, :color, :archived, :rate, :currency)
+                ON CONFLICT (id) DO UPDATE SET
+                    name = excluded.name,
+                    color = excluded.color,
+                    archived = excluded.archived,
+                    rate = excluded.rate,
+                    currency = excluded.currency",
+            )?
+            .into_cursor();
+        let (rate, currency) = rate_values(&workspace.rate);
+        cursor.bind_by_name(vec![
+            (":id", Value::String(workspace.id.clone())),
+            (":name", Value::String(workspace.name.clone())),
+            (":color", optional_string(&workspace.color)),
+            (":archived", Value::Integer(workspace.archived as i64)),
+            (":rate", rate),
+            (":currency", currency),
+        ])?;
+        cursor.next()?;
+        Ok(())
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Workspace>> {
+        let mut workspaces = self.select_workspaces(
+            "SELECT id, name, color, archived, rate, currency FROM workspaces WHERE name = :name",
+            vec![(":name", Value::String(String::from(name)))],
+        )?;
+        Ok(workspaces.pop())
+    }
+
+    fn find_all(&self) -> Result<Vec<Workspace>> {
+        self.select_workspaces(
+            "SELECT id, name, color, archived, rate, currency FROM workspaces ORDER BY name",
+            vec![],
+        )
     }
+}
+
+pub struct ProjectRepositorySQLite {
+    connection: Rc<sqlite::Connection>,
+}
+
+const PROJECT_COLUMNS: &str = "projects.id, projects.name, workspaces.name, projects.color,
+    projects.client, projects.archived, projects.rate, projects.currency";
 
-    fn find(&self, id: String) -> Result<Track, String> {
-        match self.find_in_sqlite(id) {
-            Ok(track) => Ok(track),
-            // Todo: Not found error??
-            Err(_) => Err(String::from("An error happen when tried find the track"))
+impl ProjectRepositorySQLite {
+    pub fn create(connection: Rc<sqlite::Connection>) -> ProjectRepositorySQLite {
+        ProjectRepositorySQLite { connection }
+    }
+
+    /// Runs a query whose `{}` placeholder is replaced by the project columns,
+    /// with `workspaces` joined.
+    fn select_projects(&self, sql: &str, values: Vec<(&str, Value)>) -> Result<Vec<Project>> {
+        let mut cursor = self
+            .connection
+            .prepare(sql.replace("{}", PROJECT_COLUMNS))?
+            .into_cursor();
+        cursor.bind_by_name(values)?;
+        let mut projects = vec![];
+        while let Some(row) = cursor.next()? {
+            projects.push(Project {
+                id: String::from(read_string(row, 0)?),
+                name: String::from(read_string(row, 1)?),
+                workspace: String::from(read_string(row, 2)?),
+                color: read_optional_string(row, 3)?,
+                client: read_optional_string(row, 4)?,
+                archived: row[5].as_integer() == Some(1),
+                rate: read_rate(row, 6)?,
+            });
         }
+        Ok(projects)
     }
+}
 
-    fn find_all(&self) -> Result<Vec<Track>, String> {
-        match self.find_all_in_sqlite() {
-            Ok(tracks) => Ok(tracks),
-            Err(_) => Err(String::from("An error happen when tried find all the tracks"))
+impl ProjectRepository for ProjectRepositorySQLite {
+    fn save(&self, project: &Project) -> Result<()> {
+        let mut cursor = self
+            .connection
+            .prepare(
+                "INSERT INTO projects (id, workspace_id, name, color, client, archived, rate, currency)
+                SELECT :id, id, :name, :color, :client, :archived, :rate, :currency
+                FROM workspaces WHERE name = :workspace
+                ON CONFLICT (id) DO UPDATE SET
+                    workspace_id = excluded.workspace_id,
+                    name = excluded.name,
+                    color = excluded.color,
+                    client = excluded.client,
+                    archived = excluded.archived,
+                    rate = excluded.rate,
+                    currency = excluded.currency",
+            )?
+            .into_cursor();
+        let (rate, currency) = rate_values(&project.rate);
+        cursor.bind_by_name(vec![
+            (":id", Value::String(project.id.clone())),
+            (":name", Value::String(project.name.clone())),
+            (":workspace", Value::String(project.workspace.clone())),
+            (":color", optional_string(&project.color)),
+            (":client", optional_string(&project.client)),
+            (":archived", Value::Integer(project.archived as i64)),
+            (":rate", rate),
+            (":currency", currency),
+        ])?;
+        cursor.next()?;
+        if self.connection.change_count() == 0 {
+            return Err(Error::UnknownWorkspace(project.workspace.clone()));
         }
+        Ok(())
+    }
+
+    fn find_by_name(&self, workspace: &str, name: &str) -> Result<Option<Project>> {
+        let mut projects = self.select_projects(
+            "SELECT {} FROM projects JOIN workspaces ON workspaces.id = projects.workspace_id
+            WHERE workspaces.name = :workspace AND projects.name = :name",
+            vec![
+                (":workspace", Value::String(String::from(workspace))),
+                (":name", Value::String(String::from(name))),
+            ],
+        )?;
+        Ok(projects.pop())
+    }
+
+    fn find_all(&self) -> Result<Vec<Project>> {
+        self.select_projects(
+            "SELECT {} FROM projects JOIN workspaces ON workspaces.id = projects.workspace_id
+            ORDER BY workspaces.name, projects.name",
+            vec![],
+        )
+    }
+}
+
+pub struct SettingsRepositorySQLite {
+    connection: Rc<sqlite::Connection>,
+}
+
+impl SettingsRepositorySQLite {
+    pub fn create(connection: Rc<sqlite::Connection>) -> SettingsRepositorySQLite {
+        SettingsRepositorySQLite { connection }
+    }
+}
+
+impl SettingsRepository for SettingsRepositorySQLite {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let mut cursor = self
+            .connection
+            .prepare("SELECT value FROM settings WHERE key = :key")?
+            .into_cursor();
+        cursor.bind_by_name(vec![(":key", Value::String(String::from(key)))])?;
+        match cursor.next()? {
+            Some(row) => Ok(Some(String::from(read_string(row, 0)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn set(&self, key: &str, value: Option<&str>) -> Result<()> {
+        let mut values = vec![(":key", Value::String(String::from(key)))];
+        let sql = match value {
+            Some(value) => {
+                values.push((":value", Value::String(String::from(value))));
+                "INSERT INTO settings (key, value) VALUES (:key, :value)
+                ON CONFLICT (key) DO UPDATE SET value = excluded.value"
+            }
+            None => "DELETE FROM settings WHERE key = :key",
+        };
+        let mut cursor = self.connection.prepare(sql)?.into_cursor();
+        cursor.bind_by_name(values)?;
+        cursor.next()?;
+        Ok(())
+    }
+}
+
+pub struct InvoiceRepositorySQLite {
+    connection: Rc<sqlite::Connection>,
+}
+
+impl InvoiceRepositorySQLite {
+    pub fn create(connection: Rc<sqlite::Connection>) -> InvoiceRepositorySQLite {
+        InvoiceRepositorySQLite { connection }
+    }
+
+    fn save_in_sqlite(&self, invoice: &Invoice) -> Result<()> {
+        if self
+            .numbers()?
+            .iter()
+            .any(|number| number.eq_ignore_ascii_case(&invoice.number))
+        {
+            return Err(Error::AlreadyExists(format!("invoice {}", invoice.number)));
+        }
+        let mut cursor = self
+            .connection
+            .prepare(
+                "INSERT INTO invoices (id, number, client, issued_at)
+                VALUES (:id, :number, :client, :issued_at)",
+            )?
+            .into_cursor();
+        cursor.bind_by_name(vec![
+            (":id", Value::String(invoice.id.clone())),
+            (":number", Value::String(invoice.number.clone())),
+            (":client", Value::String(invoice.client.clone())),
+            (
+                ":issued_at",
+                Value::Integer(invoice.issued.timestamp_millis()),
+            ),
+        ])?;
+        cursor.next()?;
+        for id in invoice.tracks.iter() {
+            let mut cursor = self
+                .connection
+                .prepare(
+                    "UPDATE tracks SET invoice_id = :invoice_id
+                    WHERE id = :id AND invoice_id IS NULL AND deleted_at IS NULL",
+                )?
+                .into_cursor();
+            cursor.bind_by_name(vec![
+                (":invoice_id", Value::String(invoice.id.clone())),
+                (":id", Value::String(id.clone())),
+            ])?;
+            cursor.next()?;
+            if self.connection.change_count() == 0 {
+                return Err(Error::AlreadyExists(format!("invoice of track {}", id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl InvoiceRepository for InvoiceRepositorySQLite {
+    fn save(&self, invoice: &Invoice) -> Result<()> {
+        in_transaction(&self.connection, || self.save_in_sqlite(invoice))
+    }
+
+    fn numbers(&self) -> Result<Vec<String>> {
+        let mut cursor = self
+            .connection
+            .prepare("SELECT number FROM invoices ORDER BY issued_at, rowid")?
+            .into_cursor();
+        let mut numbers = vec![];
+        while let Some(row) = cursor.next()? {
+            numbers.push(String::from(read_string(row, 0)?));
+        }
+        Ok(numbers)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::invoice::LineItems;
+    use crate::migrations;
     use chrono::{DateTime, Utc};
+    use uuid::Uuid;
 
-    fn create_connection() -> sqlite::Connection {
+    fn open_database() -> sqlite::Connection {
         let connection = sqlite::open(":memory:").unwrap();
-        connection.execute(SCHEME).unwrap();
+        connection.execute("PRAGMA foreign_keys = ON").unwrap();
+        migrations::migrate(&connection).unwrap();
+        connection
+    }
+
+    /// A database with the projects the tracks of the tests are filed under.
+    fn create_connection() -> sqlite::Connection {
+        let connection = open_database();
+        connection
+            .execute(
+                "INSERT INTO workspaces (id, name) VALUES ('w1', 'Workspace'), ('w2', 'Other');
+                INSERT INTO projects (id, workspace_id, name)
+                VALUES ('p1', 'w1', 'Project1'), ('p2', 'w1', 'Project2'), ('p3', 'w2', 'Project1');",
+            )
+            .unwrap();
         connection
     }
 
     fn create_repository(connection: sqlite::Connection) -> RepositorySQLite {
-        RepositorySQLite::create(Arc::new(connection).clone())
+        RepositorySQLite::create(Rc::new(connection))
     }
 
     #[test]
@@ -157,27 +870,33 @@ mod tests {
             String::from("Project1"),
             String::from("Workspace"),
         );
-        match repository.save(&track) {
-            Ok(_) => {
-                assert!(true, "Task saved");
-            }
-            Err(_) => {
-                assert!(false, "Task didn't saved");
-            }
-        };
+        if let Err(error) = repository.save(&track) {
+            panic!("Task didn't saved: {}", error);
+        }
     }
 
     #[test]
     fn test_find_task_notfound() {
         let repository = create_repository(create_connection());
-        match repository.find(String::from("not-found-id")) {
-            Ok(_) => {
-                assert!(false, "It wasn't expected fond an task");
-            }
-            Err(_) => {
-                assert!(true, "Task not found");
-            }
-        };
+        assert_eq!(
+            repository.find(String::from("not-found-id")).unwrap_err(),
+            Error::NotFound(String::from("not-found-id"))
+        );
+    }
+
+    #[test]
+    fn test_find_task_invalid_timestamp() {
+        let connection = create_connection();
+        connection
+            .execute(
+                "INSERT INTO tracks (id, name, start, end, project_id) VALUES ('a1', 'MyTrack', 'yesterday', NULL, 'p1');",
+            )
+            .unwrap();
+        let repository = create_repository(connection);
+        assert_eq!(
+            repository.find(String::from("a1")).unwrap_err(),
+            Error::InvalidTimestamp(String::from("yesterday"))
+        );
     }
 
     #[test]
@@ -195,15 +914,24 @@ mod tests {
 
         match repository.find(String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8")) {
             Ok(track) => {
-                assert_eq!(track.id, String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"));
+                assert_eq!(
+                    track.id,
+                    String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8")
+                );
                 assert_eq!(track.name, String::from("MyTrack"));
                 assert_eq!(track.project, String::from("Project1"));
                 assert_eq!(track.workspace, String::from("Workspace"));
-                assert_eq!(track.start, "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap());
-                assert_eq!(track.end, Some("2022-01-01T01:01:00Z".parse::<DateTime<Utc>>().unwrap()));
+                assert_eq!(
+                    track.start,
+                    "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap()
+                );
+                assert_eq!(
+                    track.end,
+                    Some("2022-01-01T01:01:00Z".parse::<DateTime<Utc>>().unwrap())
+                );
             }
             Err(_) => {
-                assert!(false, "It was expected fond an task");
+                panic!("It was expected fond an task");
             }
         };
     }
@@ -234,23 +962,428 @@ mod tests {
             Ok(tracks) => {
                 assert_eq!(tracks.len(), 2);
                 let track_one = &tracks[0];
-                assert_eq!(track_one.id, String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"));
+                assert_eq!(
+                    track_one.id,
+                    String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8")
+                );
                 assert_eq!(track_one.name, String::from("MyTrack"));
                 assert_eq!(track_one.project, String::from("Project1"));
                 assert_eq!(track_one.workspace, String::from("Workspace"));
-                assert_eq!(track_one.start, "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap());
-                assert_eq!(track_one.end, Some("2022-01-01T01:01:00Z".parse::<DateTime<Utc>>().unwrap()));
+                assert_eq!(
+                    track_one.start,
+                    "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap()
+                );
+                assert_eq!(
+                    track_one.end,
+                    Some("2022-01-01T01:01:00Z".parse::<DateTime<Utc>>().unwrap())
+                );
                 let track_two = &tracks[1];
-                assert_eq!(track_two.id, String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d1"));
+                assert_eq!(
+                    track_two.id,
+                    String::from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d1")
+                );
                 assert_eq!(track_two.name, String::from("MyTrack2"));
                 assert_eq!(track_two.project, String::from("Project2"));
                 assert_eq!(track_two.workspace, String::from("Workspace"));
-                assert_eq!(track_two.start, "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap());
-                assert_eq!(track_two.end, Some("2022-01-01T01:01:00Z".parse::<DateTime<Utc>>().unwrap()));
+                assert_eq!(
+                    track_two.start,
+                    "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap()
+                );
+                assert_eq!(
+                    track_two.end,
+                    Some("2022-01-01T01:01:00Z".parse::<DateTime<Utc>>().unwrap())
+                );
             }
             Err(_) => {
-                assert!(false, "It was expected fond an task");
+                panic!("It was expected fond an task");
             }
         };
     }
+
+    fn save_tracks(repository: &RepositorySQLite) {
+        let tracks = [
+            (
+                "t1",
+                "Review PR",
+                "01:00",
+                Some("02:00"),
+                "Project1",
+                "Workspace",
+            ),
+            (
+                "t2",
+                "Write docs",
+                "02:00",
+                Some("03:00"),
+                "Project2",
+                "Workspace",
+            ),
+            (
+                "t3",
+                "Review 100%",
+                "03:00",
+                Some("04:00"),
+                "Project1",
+                "Other",
+            ),
+            ("t4", "Deploy", "04:00", None, "Project1", "Workspace"),
+        ];
+        for (id, name, start, end, project, workspace) in tracks {
+            let at = |time: &str| {
+                format!("2022-01-01T{}:00Z", time)
+                    .parse::<DateTime<Utc>>()
+                    .unwrap()
+            };
+            repository
+                .save(&Track::create(
+                    String::from(id),
+                    String::from(name),
+                    at(start),
+                    end.map(at),
+                    String::from(project),
+                    String::from(workspace),
+                ))
+                .unwrap();
+        }
+    }
+
+    fn find_ids(repository: &RepositorySQLite, query: TrackQuery) -> Vec<String> {
+        repository
+            .find_by(&query)
+            .unwrap()
+            .into_iter()
+            .map(|track| track.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_find_by() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new()),
+            vec!["t1", "t2", "t3", "t4"]
+        );
+        assert_eq!(
+            find_ids(
+                &repository,
+                TrackQuery::new()
+                    .project(String::from("Project1"))
+                    .workspace(String::from("Workspace"))
+            ),
+            vec!["t1", "t4"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().name(String::from("review"))),
+            vec!["t1", "t3"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().name(String::from("0%"))),
+            vec!["t3"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().running_only()),
+            vec!["t4"]
+        );
+    }
+
+    #[test]
+    fn test_find_by_range() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        let query = TrackQuery::new()
+            .since("2022-01-01T02:30:00Z".parse::<DateTime<Utc>>().unwrap())
+            .until("2022-01-01T04:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(find_ids(&repository, query), vec!["t2", "t3"]);
+        let query =
+            TrackQuery::new().since("2022-01-01T05:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(find_ids(&repository, query), vec!["t4"]);
+    }
+
+    #[test]
+    fn test_find_by_pagination() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        let query = TrackQuery::new()
+            .order(Order::Descending)
+            .limit(2)
+            .offset(1);
+        assert_eq!(find_ids(&repository, query), vec!["t3", "t2"]);
+    }
+
+    #[test]
+    fn test_update_task() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        let mut track = repository.find(String::from("t1")).unwrap();
+        track.name = String::from("Renamed");
+        repository.update(&track).unwrap();
+        assert_eq!(repository.find(String::from("t1")).unwrap(), track);
+        track.id = String::from("missing");
+        assert_eq!(
+            repository.update(&track),
+            Err(Error::NotFound(String::from("missing")))
+        );
+    }
+
+    #[test]
+    fn test_delete_and_restore_task() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        repository.delete(String::from("t4")).unwrap();
+        assert_eq!(
+            repository.find(String::from("t4")),
+            Err(Error::NotFound(String::from("t4")))
+        );
+        assert_eq!(repository.find_running().unwrap(), None);
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().deleted()),
+            vec!["t4"]
+        );
+        assert_eq!(
+            repository.delete(String::from("t4")),
+            Err(Error::NotFound(String::from("t4")))
+        );
+        repository.restore(String::from("t4")).unwrap();
+        assert_eq!(repository.find_running().unwrap().unwrap().id, "t4");
+        assert!(find_ids(&repository, TrackQuery::new().deleted()).is_empty());
+    }
+
+    #[test]
+    fn test_find_by_id_prefix() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().id_prefix(String::from("t2"))),
+            vec!["t2"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().id_prefix(String::from("t"))).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_save_pauses() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        let mut track = repository.find_running().unwrap().unwrap();
+        track
+            .pause_at("2022-01-01T05:00:00Z".parse::<DateTime<Utc>>().unwrap())
+            .unwrap();
+        track
+            .resume_at("2022-01-01T05:30:00Z".parse::<DateTime<Utc>>().unwrap())
+            .unwrap();
+        track
+            .pause_at("2022-01-01T06:00:00Z".parse::<DateTime<Utc>>().unwrap())
+            .unwrap();
+        repository.save(&track).unwrap();
+        assert_eq!(repository.find(String::from("t4")).unwrap(), track);
+        track
+            .resume_at("2022-01-01T06:15:00Z".parse::<DateTime<Utc>>().unwrap())
+            .unwrap();
+        repository.update(&track).unwrap();
+        let tracks = repository.find_all().unwrap();
+        assert_eq!(tracks[3], track);
+        assert!(tracks[..3].iter().all(|track| track.pauses.is_empty()));
+    }
+
+    #[test]
+    fn test_save_tags() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        let mut track = repository.find(String::from("t1")).unwrap();
+        track.set_tags(vec![String::from("review"), String::from("billable")]);
+        repository.update(&track).unwrap();
+        let mut other = repository.find(String::from("t3")).unwrap();
+        other.set_tags(vec![String::from("review")]);
+        repository.save(&other).unwrap();
+        assert_eq!(repository.find(String::from("t1")).unwrap(), track);
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().tag(String::from("Review"))),
+            vec!["t1", "t3"]
+        );
+        assert_eq!(
+            find_ids(
+                &repository,
+                TrackQuery::new()
+                    .tag(String::from("review"))
+                    .tag(String::from("billable"))
+                    .tag(String::from("review"))
+            ),
+            vec!["t1"]
+        );
+        track.set_tags(vec![]);
+        repository.update(&track).unwrap();
+        assert!(repository.find(String::from("t1")).unwrap().tags.is_empty());
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().tag(String::from("billable"))),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_save_rates() {
+        let connection = Rc::new(create_connection());
+        let repository = RepositorySQLite::create(connection.clone());
+        save_tracks(&repository);
+        let mut track = repository.find(String::from("t1")).unwrap();
+        assert!(track.billable);
+        track.rate = Some(Rate::new(Decimal::new(9550, 2), "EUR"));
+        track.billable = false;
+        repository.update(&track).unwrap();
+        assert_eq!(repository.find(String::from("t1")).unwrap(), track);
+        let workspaces = WorkspaceRepositorySQLite::create(connection);
+        let mut workspace = workspaces.find_by_name(&track.workspace).unwrap().unwrap();
+        workspace.rate = Some(Rate::new(Decimal::from(80), "USD"));
+        workspaces.save(&workspace).unwrap();
+        assert_eq!(
+            workspaces.find_by_name(&track.workspace).unwrap(),
+            Some(workspace)
+        );
+    }
+
+    fn create_invoice(number: &str, tracks: &[&str]) -> Invoice {
+        let day = chrono::NaiveDate::from_ymd(2022, 1, 1);
+        Invoice {
+            id: Uuid::new_v4().hyphenated().to_string(),
+            number: String::from(number),
+            client: String::from("Workspace"),
+            issued: Utc::now(),
+            date: day,
+            since: day,
+            until: day,
+            items: LineItems::Tracks,
+            rounding: None,
+            lines: vec![],
+            subtotals: vec![],
+            taxes: vec![],
+            totals: vec![],
+            tracks: tracks.iter().map(|id| String::from(*id)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_save_invoices() {
+        let connection = Rc::new(create_connection());
+        let repository = RepositorySQLite::create(connection.clone());
+        save_tracks(&repository);
+        let client = |client: &str| TrackQuery::new().client(String::from(client));
+        assert_eq!(
+            find_ids(&repository, client("workspace")),
+            vec!["t1", "t2", "t4"]
+        );
+        assert_eq!(find_ids(&repository, client("Other")), vec!["t3"]);
+        let invoices = InvoiceRepositorySQLite::create(connection);
+        invoices
+            .save(&create_invoice("2022-0001", &["t1"]))
+            .unwrap();
+        assert_eq!(
+            invoices.save(&create_invoice("2022-0002", &["t2", "t1"])),
+            Err(Error::AlreadyExists(String::from("invoice of track t1")))
+        );
+        assert_eq!(
+            invoices.save(&create_invoice("2022-0001", &["t2"])),
+            Err(Error::AlreadyExists(String::from("invoice 2022-0001")))
+        );
+        assert_eq!(invoices.numbers().unwrap(), vec!["2022-0001"]);
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().invoiced(true)),
+            vec!["t1"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().invoiced(false)),
+            vec!["t2", "t3", "t4"]
+        );
+        assert_eq!(
+            repository.find(String::from("t1")).unwrap().invoice,
+            Some(String::from("2022-0001"))
+        );
+    }
+
+    #[test]
+    fn test_save_projects() {
+        let connection = Rc::new(open_database());
+        let workspaces = WorkspaceRepositorySQLite::create(connection.clone());
+        let projects = ProjectRepositorySQLite::create(connection.clone());
+        let mut workspace = Workspace::new(String::from("Acme"));
+        workspace.color = Some(String::from("#ff0000"));
+        workspaces.save(&workspace).unwrap();
+        let mut project = Project::new(String::from("Website"), String::from("ACME"));
+        project.client = Some(String::from("Acme Inc"));
+        projects.save(&project).unwrap();
+        project.workspace = String::from("Acme");
+        assert_eq!(
+            workspaces.find_by_name("acme").unwrap(),
+            Some(workspace.clone())
+        );
+        assert_eq!(
+            projects.find_by_name("acme", "WEBSITE").unwrap(),
+            Some(project.clone())
+        );
+        assert_eq!(
+            projects
+                .save(&Project::new(String::from("Mobile"), String::from("Other")))
+                .unwrap_err(),
+            Error::UnknownWorkspace(String::from("Other"))
+        );
+        let repository = RepositorySQLite::create(connection);
+        let mut track = Track::create(
+            String::from("t1"),
+            String::from("Review"),
+            "2022-01-01T01:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            None,
+            String::from("Mobile"),
+            String::from("Other"),
+        );
+        assert_eq!(
+            repository.save(&track),
+            Err(Error::UnknownWorkspace(String::from("Other")))
+        );
+        track.workspace = String::from("Acme");
+        assert_eq!(
+            repository.save(&track),
+            Err(Error::UnknownProject(
+                String::from("Mobile"),
+                String::from("Acme")
+            ))
+        );
+        track.project = String::from("Website");
+        repository.save(&track).unwrap();
+        assert_eq!(projects.find_all().unwrap(), vec![project.clone()]);
+        workspace.name = String::from("Acme Corp");
+        workspaces.save(&workspace).unwrap();
+        let mut moved = repository.find(String::from("t1")).unwrap();
+        moved.project = String::from("website");
+        moved.workspace = String::from("acme corp");
+        repository.update(&moved).unwrap();
+        let moved = repository.find(String::from("t1")).unwrap();
+        assert_eq!(
+            (moved.project, moved.workspace),
+            (String::from("Website"), String::from("Acme Corp"))
+        );
+        assert_eq!(workspaces.find_all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_grep_notes() {
+        let repository = create_repository(create_connection());
+        save_tracks(&repository);
+        let mut track = repository.find(String::from("t2")).unwrap();
+        track.notes = String::from("Fixed the flaky test\n100% green");
+        repository.update(&track).unwrap();
+        assert_eq!(repository.find(String::from("t2")).unwrap(), track);
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().grep(String::from("FLAKY"))),
+            vec!["t2"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().grep(String::from("0% g"))),
+            vec!["t2"]
+        );
+        assert_eq!(
+            find_ids(&repository, TrackQuery::new().grep(String::from("_"))),
+            Vec::<String>::new()
+        );
+    }
 }
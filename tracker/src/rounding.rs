@@ -0,0 +1,210 @@
+use crate::model::Track;
+use crate::report::seconds;
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    Up,
+    Down,
+    #[default]
+    Nearest,
+}
+
+/// What is rounded: the time of each track, or the time of each project in
+/// each day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingScope {
+    #[default]
+    Entry,
+    Day,
+}
+
+/// How tracked time is billed, e.g. in 15 minute increments rounded up with
+/// at least 30 minutes. Only reports and invoices round, stored tracks keep
+/// their timestamps. A zero increment keeps the durations as they are but
+/// the minimum. Serialized with the durations in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rounding {
+    #[serde(serialize_with = "seconds")]
+    pub increment: Duration,
+    pub mode: RoundingMode,
+    pub per: RoundingScope,
+    #[serde(serialize_with = "seconds")]
+    pub minimum: Duration,
+}
+
+impl Default for Rounding {
+    fn default() -> Rounding {
+        Rounding {
+            increment: Duration::zero(),
+            mode: RoundingMode::default(),
+            per: RoundingScope::default(),
+            minimum: Duration::zero(),
+        }
+    }
+}
+
+impl Rounding {
+    /// Whether it changes any duration.
+    pub fn is_active(&self) -> bool {
+        self.increment > Duration::zero() || self.minimum > Duration::zero()
+    }
+
+    /// Rounds a duration to the increment, then raises it to the minimum.
+    /// Nothing tracked stays nothing.
+    pub fn apply(&self, duration: Duration) -> Duration {
+        if duration <= Duration::zero() {
+            return Duration::zero();
+        }
+        let increment = self.increment.num_milliseconds();
+        let rounded = if increment > 0 {
+            let millis = duration.num_milliseconds();
+            let steps = match self.mode {
+                RoundingMode::Up => (millis + increment - 1) / increment,
+                RoundingMode::Down => millis / increment,
+                RoundingMode::Nearest => (millis + increment / 2) / increment,
+            };
+            Duration::milliseconds(steps * increment)
+        } else {
+            duration
+        };
+        rounded.max(self.minimum)
+    }
+
+    /// Rounds the time of tracks, each given with the day it counts in. Per
+    /// entry the time of each track is rounded once, even when split over
+    /// several days. Per day the time of each project in each day is rounded
+    /// as a whole. Either way the rounded time is shared back among the
+    /// entries in proportion to their time.
+    pub fn round(&self, entries: &[(&Track, NaiveDate, Duration)]) -> Vec<Duration> {
+        let mut groups: HashMap<_, Vec<usize>> = HashMap::new();
+        for (index, (track, day, _)) in entries.iter().enumerate() {
+            let key = match self.per {
+                RoundingScope::Entry => (None, track.id.clone(), String::new(), track.billable),
+                RoundingScope::Day => (
+                    Some(*day),
+                    track.workspace.to_lowercase(),
+                    track.project.to_lowercase(),
+                    track.billable,
+                ),
+            };
+            groups.entry(key).or_default().push(index);
+        }
+        let mut rounded = vec![Duration::zero(); entries.len()];
+        for indexes in groups.into_values() {
+            let raw: i64 = indexes
+                .iter()
+                .map(|index| entries[*index].2.num_milliseconds())
+                .sum();
+            let total = self.apply(Duration::milliseconds(raw)).num_milliseconds();
+            let mut left = total;
+            for (position, index) in indexes.iter().enumerate() {
+                let share = if position + 1 == indexes.len() {
+                    left
+                } else if raw > 0 {
+                    (total as i128 * entries[*index].2.num_milliseconds() as i128 / raw as i128)
+                        as i64
+                } else {
+                    0
+                };
+                left -= share;
+                rounded[*index] = Duration::milliseconds(share);
+            }
+        }
+        rounded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn rounding(increment: i64, mode: RoundingMode, minimum: i64) -> Rounding {
+        Rounding {
+            increment: Duration::minutes(increment),
+            mode,
+            per: RoundingScope::Entry,
+            minimum: Duration::minutes(minimum),
+        }
+    }
+
+    #[test]
+    fn test_apply_modes() {
+        let minutes = |rounding: &Rounding, minutes: i64| {
+            rounding
+                .apply(Duration::seconds(minutes * 60 + 30))
+                .num_minutes()
+        };
+        let up = rounding(15, RoundingMode::Up, 0);
+        assert_eq!(minutes(&up, 1), 15);
+        assert_eq!(minutes(&up, 15), 30);
+        let down = rounding(15, RoundingMode::Down, 0);
+        assert_eq!(minutes(&down, 14), 0);
+        assert_eq!(minutes(&down, 29), 15);
+        let nearest = rounding(6, RoundingMode::Nearest, 0);
+        assert_eq!(minutes(&nearest, 2), 0);
+        assert_eq!(minutes(&nearest, 3), 6);
+        assert_eq!(nearest.apply(Duration::minutes(3)), Duration::minutes(6));
+    }
+
+    #[test]
+    fn test_apply_minimum() {
+        let rounding = rounding(0, RoundingMode::Down, 30);
+        assert_eq!(rounding.apply(Duration::minutes(5)), Duration::minutes(30));
+        assert_eq!(
+            rounding.apply(Duration::seconds(2_000)),
+            Duration::seconds(2_000)
+        );
+        assert_eq!(rounding.apply(Duration::zero()), Duration::zero());
+        assert!(rounding.is_active());
+        assert!(!Rounding::default().is_active());
+    }
+
+    #[test]
+    fn test_round_per_day() {
+        let track = |id: &str, project: &str| {
+            Track::create(
+                String::from(id),
+                String::from(id),
+                "2022-01-01T09:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+                None,
+                String::from(project),
+                String::from("Acme"),
+            )
+        };
+        let (a1, a2, b1) = (
+            track("a1", "Website"),
+            track("a2", "website"),
+            track("b1", "Support"),
+        );
+        let day = NaiveDate::from_ymd(2022, 1, 1);
+        let entries = vec![
+            (&a1, day, Duration::minutes(10)),
+            (&a2, day, Duration::minutes(10)),
+            (&b1, day, Duration::minutes(10)),
+            (&a1, day.succ(), Duration::minutes(1)),
+        ];
+        let mut rounding = rounding(15, RoundingMode::Up, 0);
+        let minutes = |rounding: &Rounding| -> Vec<i64> {
+            rounding
+                .round(&entries)
+                .iter()
+                .map(Duration::num_minutes)
+                .collect()
+        };
+        assert_eq!(minutes(&rounding), vec![13, 15, 15, 1]);
+        let rounded = rounding.round(&entries);
+        assert_eq!(rounded[0] + rounded[3], Duration::minutes(15));
+        rounding.per = RoundingScope::Day;
+        assert_eq!(minutes(&rounding), vec![15, 15, 15, 15]);
+        rounding.mode = RoundingMode::Down;
+        assert_eq!(minutes(&rounding), vec![7, 7, 0, 0]);
+        let rounded = rounding.round(&entries);
+        assert_eq!(rounded[0] + rounded[1], Duration::minutes(15));
+    }
+}
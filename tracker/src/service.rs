@@ -1,69 +1,1047 @@
-use crate::model::Track;
-use crate::repository::TrackRepository;
+use crate::billing::Rates;
+use crate::error::{Error, Result};
+use crate::export::Exporter;
+use crate::invoice::{Invoice, InvoiceRequest};
+use crate::model::{Project, Rate, Track, Workspace};
+use crate::report::{GroupBy, Report};
+use crate::repository::{
+    InvoiceRepository, Order, ProjectRepository, SettingsRepository, TrackQuery, TrackRepository,
+    WorkspaceRepository,
+};
+use crate::rounding::Rounding;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+
+/// Fields to change on an existing track, `None` keeps the current value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackChanges {
+    pub name: Option<String>,
+    pub project: Option<String>,
+    pub workspace: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub tags: Option<Vec<String>>,
+    pub notes: Option<String>,
+    /// `Some(None)` removes the rate of the track.
+    pub rate: Option<Option<Rate>>,
+    pub billable: Option<bool>,
+}
+
+/// The rate and billable flag of a new track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Billing {
+    pub rate: Option<Rate>,
+    pub billable: bool,
+}
+
+impl Default for Billing {
+    fn default() -> Billing {
+        Billing {
+            rate: None,
+            billable: true,
+        }
+    }
+}
+
+/// What to do when a manually added track overlaps existing ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlap {
+    Reject,
+    /// Shortens, splits or deletes the existing tracks to make room.
+    Trim,
+    Allow,
+}
+
+/// When a manually added track ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackEnd {
+    At(DateTime<Utc>),
+    /// This long after the start.
+    After(Duration),
+}
+
+/// How many recent tracks are searched when continuing by a rough name.
+const FUZZY_HISTORY: usize = 500;
+
+fn fuzzy_matches(name: &str, pattern: &str) -> bool {
+    let mut name = name.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|character| !character.is_whitespace())
+        .all(|character| name.any(|candidate| candidate == character))
+}
+
+/// Outcome of `TrackService::import`. Duplicates are tracks already stored,
+/// in the trash too, or repeated in the input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    pub imported: Vec<Track>,
+    pub duplicates: Vec<Track>,
+    /// Workspace and project names of the tracks left out because the
+    /// project does not exist, sorted.
+    pub unknown_projects: Vec<(String, String)>,
+}
+
+/// The workspace, and maybe the project, commands default to when none is
+/// given. Empty when nothing is in use.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Context {
+    pub workspace: Option<String>,
+    pub project: Option<String>,
+}
+
+impl Context {
+    /// Completes the project and workspace of a new track. The project in use
+    /// only applies to tracks of its own workspace.
+    pub fn resolve(
+        &self,
+        project: Option<String>,
+        workspace: Option<String>,
+    ) -> Result<(String, String)> {
+        let workspace = match workspace {
+            Some(workspace) => workspace,
+            None => self
+                .workspace
+                .clone()
+                .ok_or_else(|| Error::NoContext(String::from("workspace")))?,
+        };
+        let project = match project {
+            Some(project) => project,
+            None => self
+                .project
+                .clone()
+                .filter(|_| self.in_workspace(&workspace))
+                .ok_or_else(|| Error::NoContext(String::from("project")))?,
+        };
+        Ok((project, workspace))
+    }
+
+    /// Limits the query to the context, unless it already names a workspace.
+    /// A query naming only a project stays in the workspace in use.
+    pub fn filter(&self, mut query: TrackQuery) -> TrackQuery {
+        if query.workspace.is_none() {
+            if query.project.is_none() {
+                query.project = self.project.clone();
+            }
+            query.workspace = self.workspace.clone();
+        }
+        query
+    }
+
+    fn in_workspace(&self, workspace: &str) -> bool {
+        self.workspace
+            .as_ref()
+            .is_some_and(|current| current.to_lowercase() == workspace.to_lowercase())
+    }
+}
+
+const CONTEXT_WORKSPACE: &str = "context.workspace";
+const CONTEXT_PROJECT: &str = "context.project";
 
 pub struct TrackService {
     repository: Box<dyn TrackRepository>,
-    tracks: Vec<Track>,
-    current_track_index: i64,
+    projects: Option<ProjectService>,
 }
 
 impl TrackService {
     pub fn create(repository: Box<dyn TrackRepository>) -> TrackService {
-        let tracks = match repository.find_all() {
-            Ok(tracks) => tracks,
-            Err(_) => Vec::new(),
-        };
-        let current_track_index = (tracks.len() as i64) - 1;
         TrackService {
             repository,
-            tracks,
-            current_track_index,
+            projects: None,
         }
     }
 
-    pub fn stop_current_track(&mut self) -> Result<(), ()> {
-        if let Some(last_track) = self.tracks.get_mut(self.current_track_index as usize) {
-            if last_track.is_tracking() {
-                last_track.stop_track();
-                self.repository.save(last_track).unwrap();
+    /// Only accepts new tracks, and tracks moved, into active projects of
+    /// `projects`. Without it the repository still refuses unknown projects,
+    /// archived ones are accepted.
+    pub fn with_projects(mut self, projects: ProjectService) -> TrackService {
+        self.projects = Some(projects);
+        self
+    }
+
+    /// Runs `operation` in one transaction, undoing all its writes when it
+    /// fails.
+    fn in_transaction<T>(&self, operation: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.repository.begin()?;
+        match operation() {
+            Ok(value) => {
+                self.repository.commit()?;
+                Ok(value)
+            }
+            Err(error) => {
+                self.repository.rollback()?;
+                Err(error)
             }
         }
+    }
+
+    /// Files the track under the exact names of its project, failing when the
+    /// project is unknown or archived.
+    fn check_project(&self, track: &mut Track) -> Result<()> {
+        if let Some(projects) = &self.projects {
+            let project = projects.active_project(&track.workspace, &track.project)?;
+            track.project = project.name;
+            track.workspace = project.workspace;
+        }
         Ok(())
     }
 
+    pub fn stop_current_track(&self) -> Result<Track> {
+        self.stop_current_track_at(Utc::now())
+    }
+
+    /// Stops the running track at a past moment, which must be after its start
+    /// and not before its last pause began.
+    pub fn stop_current_track_at(&self, at: DateTime<Utc>) -> Result<Track> {
+        match self.repository.find_running()? {
+            Some(mut track) => {
+                if at <= track.start {
+                    return Err(Error::InvalidInterval(track.start, at));
+                }
+                if let Some(pause) = track.pauses.last().filter(|pause| at < pause.start) {
+                    return Err(Error::InvalidInterval(pause.start, at));
+                }
+                let now = Utc::now();
+                if at > now {
+                    return Err(Error::InvalidInterval(at, now));
+                }
+                track.stop_track_at(at);
+                track.clip_pauses();
+                self.repository.save(&track)?;
+                Ok(track)
+            }
+            None => Err(Error::NoActiveTrack),
+        }
+    }
+
     pub fn start_new_track(
-        &mut self,
+        &self,
         name: String,
         project: String,
         workspace: String,
-    ) -> Result<&Track, ()> {
-        self.stop_current_track().unwrap();
-        let new_track = Track::start_new_track(name, project, workspace);
-        self.repository.save(&new_track).unwrap();
-        self.tracks.push(new_track);
-        self.current_track_index = self.current_track_index + 1;
-        Ok(self.tracks.get(self.current_track_index as usize).unwrap())
+        tags: Vec<String>,
+        billing: Billing,
+    ) -> Result<Track> {
+        self.start_new_track_at(name, project, workspace, tags, billing, Utc::now())
+    }
+
+    /// Starts a track at `at`, stopping the running one at the same moment.
+    pub fn start_new_track_at(
+        &self,
+        name: String,
+        project: String,
+        workspace: String,
+        tags: Vec<String>,
+        billing: Billing,
+        at: DateTime<Utc>,
+    ) -> Result<Track> {
+        let mut new_track = Track::new_track(name, project, workspace, at, None);
+        new_track.set_tags(tags);
+        new_track.rate = billing.rate;
+        new_track.billable = billing.billable;
+        self.start_track(new_track, at)
+    }
+
+    /// Stops the running track and saves the new one in one transaction.
+    fn start_track(&self, mut new_track: Track, at: DateTime<Utc>) -> Result<Track> {
+        self.check_project(&mut new_track)?;
+        self.in_transaction(|| {
+            match self.stop_current_track_at(at) {
+                Ok(_) | Err(Error::NoActiveTrack) => {}
+                Err(error) => return Err(error),
+            }
+            self.repository.save(&new_track)
+        })?;
+        Ok(new_track)
+    }
+
+    /// Starts a new track with the name, project, workspace, tags and billing of
+    /// an earlier one. `reference` is an id prefix or a rough name; without it the most
+    /// recently stopped track is continued.
+    pub fn continue_track_at(&self, reference: Option<&str>, at: DateTime<Utc>) -> Result<Track> {
+        let previous = match reference {
+            Some(reference) => self.find_by_reference(reference)?,
+            None => self
+                .repository
+                .find_by(&TrackQuery::new().order(Order::Descending).limit(2))?
+                .into_iter()
+                .find(|track| !track.is_tracking())
+                .ok_or_else(|| Error::NotFound(String::from("last stopped track")))?,
+        };
+        let mut new_track = Track::new_track(
+            previous.name,
+            previous.project,
+            previous.workspace,
+            at,
+            None,
+        );
+        new_track.tags = previous.tags;
+        new_track.rate = previous.rate;
+        new_track.billable = previous.billable;
+        self.start_track(new_track, at)
+    }
+
+    /// Finds the latest track named exactly like the reference ignoring case,
+    /// else a track by id prefix, else the latest whose name contains the
+    /// reference, else the latest whose name has its letters in order.
+    fn find_by_reference(&self, reference: &str) -> Result<Track> {
+        let latest = TrackQuery::new().order(Order::Descending);
+        let containing = self.repository.find_by(
+            &latest
+                .clone()
+                .name(String::from(reference))
+                .limit(FUZZY_HISTORY),
+        )?;
+        let reference_name = reference.to_lowercase();
+        if let Some(track) = containing
+            .iter()
+            .find(|track| track.name.to_lowercase() == reference_name)
+        {
+            return Ok(track.clone());
+        }
+        if let Ok(track) = self.find_by_prefix(reference) {
+            return Ok(track);
+        }
+        if let Some(track) = containing.into_iter().next() {
+            return Ok(track);
+        }
+        self.repository
+            .find_by(&latest.limit(FUZZY_HISTORY))?
+            .into_iter()
+            .find(|track| fuzzy_matches(&track.name, reference))
+            .ok_or_else(|| Error::NotFound(String::from(reference)))
+    }
+
+    /// Appends a note to the running track, stamped with the time of `at`.
+    pub fn append_note<Tz: TimeZone>(&self, note: &str, at: DateTime<Tz>) -> Result<Track> {
+        let mut track = self
+            .repository
+            .find_running()?
+            .ok_or(Error::NoActiveTrack)?;
+        track.append_note(note, at);
+        self.repository.update(&track)?;
+        Ok(track)
+    }
+
+    pub fn pause_current_track_at(&self, at: DateTime<Utc>) -> Result<Track> {
+        let mut track = self
+            .repository
+            .find_running()?
+            .ok_or(Error::NoActiveTrack)?;
+        track.pause_at(at)?;
+        self.repository.update(&track)?;
+        Ok(track)
+    }
+
+    pub fn resume_current_track_at(&self, at: DateTime<Utc>) -> Result<Track> {
+        let mut track = self
+            .repository
+            .find_running()?
+            .ok_or(Error::NoActiveTrack)?;
+        track.resume_at(at)?;
+        self.repository.update(&track)?;
+        Ok(track)
+    }
+
+    /// Records a track that already happened, from `start` until its `end`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_track(
+        &self,
+        name: String,
+        project: String,
+        workspace: String,
+        tags: Vec<String>,
+        billing: Billing,
+        start: DateTime<Utc>,
+        end: TrackEnd,
+        overlap: Overlap,
+    ) -> Result<Track> {
+        let end = match end {
+            TrackEnd::At(end) => end,
+            TrackEnd::After(duration) => start
+                .checked_add_signed(duration)
+                .ok_or_else(|| Error::InvalidDuration(duration.to_string()))?,
+        };
+        if end <= start {
+            return Err(Error::InvalidInterval(start, end));
+        }
+        let mut track = Track::new_track(name, project, workspace, start, Some(end));
+        track.set_tags(tags);
+        track.rate = billing.rate;
+        track.billable = billing.billable;
+        self.check_project(&mut track)?;
+        self.in_transaction(|| {
+            let neighbours = self
+                .repository
+                .find_by(&TrackQuery::new().since(start).until(end))?;
+            if !neighbours.is_empty() {
+                match overlap {
+                    Overlap::Reject => return Err(Error::Overlap(neighbours)),
+                    Overlap::Trim => {
+                        for neighbour in neighbours {
+                            self.trim_track(neighbour, start, end)?;
+                        }
+                    }
+                    Overlap::Allow => {}
+                }
+            }
+            self.repository.save(&track)
+        })?;
+        Ok(track)
+    }
+
+    /// Makes room for `start..end` in the track. A track around it is split
+    /// in two, the running part keeping the id of a running track.
+    fn trim_track(&self, mut track: Track, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<()> {
+        let keeps_head = track.start < start;
+        let keeps_tail = track.end.is_none_or(|track_end| track_end > end);
+        match (keeps_head, keeps_tail) {
+            (true, true) => {
+                let running = track.end.is_none();
+                let (kept, moved) = if running {
+                    ((end, track.end), (track.start, Some(start)))
+                } else {
+                    ((track.start, Some(start)), (end, track.end))
+                };
+                let mut part = Track::new_track(
+                    track.name.clone(),
+                    track.project.clone(),
+                    track.workspace.clone(),
+                    moved.0,
+                    moved.1,
+                );
+                part.tags = track.tags.clone();
+                part.pauses = track.pauses.clone();
+                part.rate = track.rate.clone();
+                part.billable = track.billable;
+                part.clip_pauses();
+                track.start = kept.0;
+                track.end = kept.1;
+                track.clip_pauses();
+                self.repository.update(&track)?;
+                self.repository.save(&part)
+            }
+            (true, false) => {
+                track.end = Some(start);
+                track.clip_pauses();
+                self.repository.update(&track)
+            }
+            (false, true) => {
+                track.start = end;
+                track.clip_pauses();
+                self.repository.update(&track)
+            }
+            (false, false) => self.repository.delete(track.id),
+        }
+    }
+
+    /// The running track, if any.
+    pub fn current(&self) -> Result<Option<Track>> {
+        self.repository.find_running()
+    }
+
+    pub fn list(&self) -> Result<Vec<Track>> {
+        self.repository.find_all()
+    }
+
+    pub fn list_by(&self, query: &TrackQuery) -> Result<Vec<Track>> {
+        self.repository.find_by(query)
+    }
+
+    /// Aggregates the time of the tracks matching `query`, clipped to its
+    /// `since` and `until` bounds, and with `amounts` what they earned.
+    /// `rounding` adds the rounded time, which amounts are computed on.
+    /// Limit and offset are ignored.
+    pub fn report<Tz: TimeZone>(
+        &self,
+        query: &TrackQuery,
+        group_by: &[GroupBy],
+        now: DateTime<Tz>,
+        amounts: bool,
+        rounding: Option<&Rounding>,
+    ) -> Result<Report> {
+        let mut query = query.clone();
+        query.limit = None;
+        query.offset = 0;
+        let tracks = self.repository.find_by(&query)?;
+        let rates = if amounts { Some(self.rates()?) } else { None };
+        Ok(Report::build(
+            &tracks,
+            group_by,
+            query.since,
+            query.until,
+            now,
+            rates.as_ref(),
+            rounding,
+        ))
+    }
+
+    /// The rates of the workspaces and projects, none without projects.
+    fn rates(&self) -> Result<Rates> {
+        match &self.projects {
+            Some(projects) => projects.rates(),
+            None => Ok(Rates::default()),
+        }
+    }
+
+    /// The client as spelled in the projects, or in the workspaces for
+    /// projects without a client.
+    fn client_name(&self, client: &str) -> Result<String> {
+        let projects = match &self.projects {
+            Some(projects) => projects.projects(None, true)?,
+            None => vec![],
+        };
+        Ok(projects
+            .into_iter()
+            .map(|project| project.client.unwrap_or(project.workspace))
+            .find(|name| name.to_lowercase() == client.to_lowercase())
+            .unwrap_or_else(|| String::from(client)))
+    }
+
+    /// Writes the tracks matching `query` with the exporter and returns how
+    /// many were exported.
+    pub fn export(
+        &self,
+        query: &TrackQuery,
+        exporter: &dyn Exporter,
+        now: DateTime<Utc>,
+        writer: &mut dyn Write,
+    ) -> Result<usize> {
+        let tracks = self.repository.find_by(query)?;
+        exporter.export(&tracks, now, writer)?;
+        Ok(tracks.len())
+    }
+
+    /// Saves the tracks whose content is not stored yet, or only reports what
+    /// would be saved with `dry_run`. Overlaps with stored tracks are kept.
+    /// Either all the tracks are saved or none.
+    /// Imports the tracks in one transaction. Tracks of unknown workspaces or
+    /// projects are all listed in the summary of a dry run, and fail the import
+    /// otherwise.
+    pub fn import(&self, tracks: Vec<Track>, dry_run: bool) -> Result<ImportSummary> {
+        if dry_run {
+            return self.import_tracks(tracks, true);
+        }
+        self.in_transaction(|| {
+            let summary = self.import_tracks(tracks, false)?;
+            if summary.unknown_projects.is_empty() {
+                return Ok(summary);
+            }
+            let names: Vec<String> = summary
+                .unknown_projects
+                .iter()
+                .map(|(workspace, project)| format!("{}/{}", workspace, project))
+                .collect();
+            Err(Error::Import(format!(
+                "no such workspace or project for {}, add them first or pass --workspace and --project",
+                names.join(", ")
+            )))
+        })
+    }
+
+    fn import_tracks(&self, tracks: Vec<Track>, dry_run: bool) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+        let since = tracks.iter().map(|track| track.start).min();
+        let until = tracks.iter().filter_map(|track| track.end).max();
+        let mut known = HashSet::new();
+        let mut unknown_projects = BTreeSet::new();
+        if let (Some(since), Some(until)) = (since, until) {
+            let query = TrackQuery::new().since(since).until(until);
+            for stored in [
+                self.repository.find_by(&query)?,
+                self.repository.find_by(&query.deleted())?,
+            ] {
+                known.extend(stored.iter().map(Track::content_hash));
+            }
+        }
+        for track in tracks.into_iter() {
+            if !known.insert(track.content_hash()) {
+                summary.duplicates.push(track);
+                continue;
+            }
+            let mut track = if self.id_in_use(&track.id)? {
+                let mut copy = Track::new_track(
+                    track.name,
+                    track.project,
+                    track.workspace,
+                    track.start,
+                    track.end,
+                );
+                copy.tags = track.tags;
+                copy.notes = track.notes;
+                copy.pauses = track.pauses;
+                copy.rate = track.rate;
+                copy.billable = track.billable;
+                copy
+            } else {
+                track
+            };
+            let saved = self.check_project(&mut track).and_then(|_| {
+                if dry_run {
+                    Ok(())
+                } else {
+                    self.repository.save(&track)
+                }
+            });
+            match saved {
+                Ok(()) => summary.imported.push(track),
+                Err(Error::UnknownWorkspace(_)) | Err(Error::UnknownProject(_, _)) => {
+                    unknown_projects.insert((track.workspace, track.project));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        summary.unknown_projects = unknown_projects.into_iter().collect();
+        Ok(summary)
+    }
+
+    fn id_in_use(&self, id: &str) -> Result<bool> {
+        let query = TrackQuery::new().id_prefix(String::from(id)).limit(1);
+        Ok(!self.repository.find_by(&query)?.is_empty()
+            || !self.repository.find_by(&query.deleted())?.is_empty())
+    }
+
+    /// Finds the track whose id starts with `prefix`, failing when the prefix
+    /// matches more than one track.
+    pub fn find_by_prefix(&self, prefix: &str) -> Result<Track> {
+        self.find_unique(TrackQuery::new(), prefix)
+    }
+
+    pub fn edit_track(&self, prefix: &str, changes: TrackChanges) -> Result<Track> {
+        let mut track = self.find_by_prefix(prefix)?;
+        let moved = changes.project.is_some() || changes.workspace.is_some();
+        if let Some(name) = changes.name {
+            track.name = name;
+        }
+        if let Some(project) = changes.project {
+            track.project = project;
+        }
+        if let Some(workspace) = changes.workspace {
+            track.workspace = workspace;
+        }
+        if let Some(start) = changes.start {
+            track.start = start;
+        }
+        if let Some(end) = changes.end {
+            track.end = Some(end);
+        }
+        if let Some(tags) = changes.tags {
+            track.set_tags(tags);
+        }
+        if let Some(notes) = changes.notes {
+            track.notes = notes;
+        }
+        if let Some(rate) = changes.rate {
+            track.rate = rate;
+        }
+        if let Some(billable) = changes.billable {
+            track.billable = billable;
+        }
+        if moved {
+            self.check_project(&mut track)?;
+        }
+        if let Some(end) = track.end {
+            if end <= track.start {
+                return Err(Error::InvalidInterval(track.start, end));
+            }
+        }
+        track.clip_pauses();
+        self.repository.update(&track)?;
+        Ok(track)
+    }
+
+    pub fn delete_track(&self, prefix: &str) -> Result<Track> {
+        let track = self.find_by_prefix(prefix)?;
+        self.repository.delete(track.id.clone())?;
+        Ok(track)
+    }
+
+    pub fn restore_track(&self, prefix: &str) -> Result<Track> {
+        let track = self.find_unique(TrackQuery::new().deleted(), prefix)?;
+        self.repository.restore(track.id.clone())?;
+        Ok(track)
+    }
+
+    pub fn trash(&self) -> Result<Vec<Track>> {
+        self.repository.find_by(&TrackQuery::new().deleted())
     }
 
-    pub fn list(&self) -> Vec<Track> {
-        self.tracks.to_vec()
+    fn find_unique(&self, query: TrackQuery, prefix: &str) -> Result<Track> {
+        let mut tracks = self
+            .repository
+            .find_by(&query.id_prefix(String::from(prefix)).limit(2))?;
+        match tracks.len() {
+            0 => Err(Error::NotFound(String::from(prefix))),
+            1 => Ok(tracks.remove(0)),
+            _ => Err(Error::AmbiguousId(String::from(prefix))),
+        }
+    }
+}
+
+/// Issues invoices for the tracks of a track service, keeping them in its
+/// invoice repository.
+pub struct InvoiceService {
+    tracks: TrackService,
+    invoices: Box<dyn InvoiceRepository>,
+}
+
+impl InvoiceService {
+    pub fn create(tracks: TrackService, invoices: Box<dyn InvoiceRepository>) -> InvoiceService {
+        InvoiceService { tracks, invoices }
+    }
+
+    /// Bills the tracks of the client in the period of `request` and marks
+    /// them as invoiced, or only builds the invoice with `dry_run`. Without a
+    /// number the next one of the year is taken, e.g. `2022-0003`.
+    pub fn invoice<Tz: TimeZone>(
+        &self,
+        request: &InvoiceRequest,
+        now: DateTime<Tz>,
+        dry_run: bool,
+    ) -> Result<Invoice> {
+        if dry_run {
+            return self.build_invoice(request, now);
+        }
+        self.tracks.in_transaction(|| {
+            let invoice = self.build_invoice(request, now)?;
+            self.invoices.save(&invoice)?;
+            Ok(invoice)
+        })
+    }
+
+    /// The invoice of `request`, numbered but not stored.
+    fn build_invoice<Tz: TimeZone>(
+        &self,
+        request: &InvoiceRequest,
+        now: DateTime<Tz>,
+    ) -> Result<Invoice> {
+        let numbers = self.invoices.numbers()?;
+        let number = match &request.number {
+            Some(number) if numbers.iter().any(|used| used.eq_ignore_ascii_case(number)) => {
+                return Err(Error::AlreadyExists(format!("invoice {}", number)))
+            }
+            Some(number) => number.clone(),
+            None => next_number(&numbers, now.year()),
+        };
+        let query = TrackQuery::new()
+            .client(request.client.clone())
+            .since(request.since)
+            .until(request.until)
+            .invoiced(false);
+        let tracks = self.tracks.repository.find_by(&query)?;
+        let request = InvoiceRequest {
+            client: self.tracks.client_name(&request.client)?,
+            ..request.clone()
+        };
+        Invoice::build(number, &request, &tracks, &self.tracks.rates()?, now)
+    }
+}
+
+/// The number following the invoices of `year`, numbered `YEAR-NNNN`.
+fn next_number(numbers: &[String], year: i32) -> String {
+    let prefix = format!("{}-", year);
+    let last = numbers
+        .iter()
+        .filter_map(|number| number.strip_prefix(&prefix)?.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+    format!("{}{:04}", prefix, last + 1)
+}
+
+/// Manages the workspaces and the projects tracks are filed under. Names are
+/// compared ignoring case.
+pub struct ProjectService {
+    workspaces: Box<dyn WorkspaceRepository>,
+    projects: Box<dyn ProjectRepository>,
+    settings: Box<dyn SettingsRepository>,
+}
+
+impl ProjectService {
+    pub fn create(
+        workspaces: Box<dyn WorkspaceRepository>,
+        projects: Box<dyn ProjectRepository>,
+        settings: Box<dyn SettingsRepository>,
+    ) -> ProjectService {
+        ProjectService {
+            workspaces,
+            projects,
+            settings,
+        }
+    }
+
+    /// Puts a workspace, and optionally one of its projects, in use. They are
+    /// remembered by id so renaming them keeps the context.
+    pub fn use_context(&self, workspace: &str, project: Option<&str>) -> Result<Context> {
+        let workspace = self.find_workspace(workspace)?;
+        if workspace.archived {
+            return Err(Error::Archived(format!("workspace {}", workspace.name)));
+        }
+        let project = project
+            .map(|project| self.active_project(&workspace.name, project))
+            .transpose()?;
+        self.settings.set(CONTEXT_WORKSPACE, Some(&workspace.id))?;
+        self.settings.set(
+            CONTEXT_PROJECT,
+            project.as_ref().map(|project| project.id.as_str()),
+        )?;
+        Ok(Context {
+            workspace: Some(workspace.name),
+            project: project.map(|project| project.name),
+        })
+    }
+
+    pub fn clear_context(&self) -> Result<()> {
+        self.settings.set(CONTEXT_WORKSPACE, None)?;
+        self.settings.set(CONTEXT_PROJECT, None)
+    }
+
+    /// The context in use, with the current names of its workspace and project.
+    pub fn context(&self) -> Result<Context> {
+        let workspace = match self.settings.get(CONTEXT_WORKSPACE)? {
+            Some(id) => self
+                .workspaces
+                .find_all()?
+                .into_iter()
+                .find(|workspace| workspace.id == id),
+            None => None,
+        };
+        let project = match self.settings.get(CONTEXT_PROJECT)? {
+            Some(id) => self
+                .projects
+                .find_all()?
+                .into_iter()
+                .find(|project| project.id == id),
+            None => None,
+        };
+        Ok(Context {
+            workspace: workspace.map(|workspace| workspace.name),
+            project: project.map(|project| project.name),
+        })
+    }
+
+    pub fn add_workspace(&self, name: String, color: Option<String>) -> Result<Workspace> {
+        if self.workspaces.find_by_name(&name)?.is_some() {
+            return Err(Error::AlreadyExists(format!("workspace {}", name)));
+        }
+        let mut workspace = Workspace::new(name);
+        workspace.color = color;
+        self.workspaces.save(&workspace)?;
+        Ok(workspace)
+    }
+
+    pub fn find_workspace(&self, name: &str) -> Result<Workspace> {
+        self.workspaces
+            .find_by_name(name)?
+            .ok_or_else(|| Error::UnknownWorkspace(String::from(name)))
+    }
+
+    /// Renames a workspace, its projects and tracks follow.
+    pub fn rename_workspace(&self, name: &str, new_name: String) -> Result<Workspace> {
+        let mut workspace = self.find_workspace(name)?;
+        if let Some(existing) = self.workspaces.find_by_name(&new_name)? {
+            if existing.id != workspace.id {
+                return Err(Error::AlreadyExists(format!("workspace {}", new_name)));
+            }
+        }
+        workspace.name = new_name;
+        self.workspaces.save(&workspace)?;
+        Ok(workspace)
+    }
+
+    pub fn set_workspace_rate(&self, name: &str, rate: Option<Rate>) -> Result<Workspace> {
+        let mut workspace = self.find_workspace(name)?;
+        workspace.rate = rate;
+        self.workspaces.save(&workspace)?;
+        Ok(workspace)
+    }
+
+    pub fn archive_workspace(&self, name: &str, archived: bool) -> Result<Workspace> {
+        let mut workspace = self.find_workspace(name)?;
+        workspace.archived = archived;
+        self.workspaces.save(&workspace)?;
+        Ok(workspace)
+    }
+
+    /// Workspaces sorted by name, archived ones only when asked for.
+    pub fn workspaces(&self, archived: bool) -> Result<Vec<Workspace>> {
+        Ok(self
+            .workspaces
+            .find_all()?
+            .into_iter()
+            .filter(|workspace| archived || !workspace.archived)
+            .collect())
+    }
+
+    pub fn add_project(
+        &self,
+        name: String,
+        workspace: &str,
+        color: Option<String>,
+        client: Option<String>,
+    ) -> Result<Project> {
+        let workspace = self.find_workspace(workspace)?;
+        if self
+            .projects
+            .find_by_name(&workspace.name, &name)?
+            .is_some()
+        {
+            return Err(Error::AlreadyExists(format!(
+                "project {} in workspace {}",
+                name, workspace.name
+            )));
+        }
+        let mut project = Project::new(name, workspace.name);
+        project.color = color;
+        project.client = client;
+        self.projects.save(&project)?;
+        Ok(project)
+    }
+
+    pub fn find_project(&self, workspace: &str, name: &str) -> Result<Project> {
+        self.projects
+            .find_by_name(workspace, name)?
+            .ok_or_else(|| Error::UnknownProject(String::from(name), String::from(workspace)))
+    }
+
+    /// Renames a project, its tracks follow.
+    pub fn rename_project(&self, workspace: &str, name: &str, new_name: String) -> Result<Project> {
+        let mut project = self.find_project(workspace, name)?;
+        if let Some(existing) = self.projects.find_by_name(workspace, &new_name)? {
+            if existing.id != project.id {
+                return Err(Error::AlreadyExists(format!(
+                    "project {} in workspace {}",
+                    new_name, project.workspace
+                )));
+            }
+        }
+        project.name = new_name;
+        self.projects.save(&project)?;
+        Ok(project)
+    }
+
+    pub fn set_project_rate(
+        &self,
+        workspace: &str,
+        name: &str,
+        rate: Option<Rate>,
+    ) -> Result<Project> {
+        let mut project = self.find_project(workspace, name)?;
+        project.rate = rate;
+        self.projects.save(&project)?;
+        Ok(project)
+    }
+
+    pub fn archive_project(&self, workspace: &str, name: &str, archived: bool) -> Result<Project> {
+        let mut project = self.find_project(workspace, name)?;
+        project.archived = archived;
+        self.projects.save(&project)?;
+        Ok(project)
+    }
+
+    /// Projects sorted by workspace and name, optionally of a single
+    /// workspace. Archived projects, or projects of archived workspaces, only
+    /// come when asked for.
+    pub fn projects(&self, workspace: Option<&str>, archived: bool) -> Result<Vec<Project>> {
+        let workspace = workspace
+            .map(|workspace| self.find_workspace(workspace))
+            .transpose()?;
+        let archived_workspaces: HashSet<String> = self
+            .workspaces
+            .find_all()?
+            .into_iter()
+            .filter(|workspace| workspace.archived)
+            .map(|workspace| workspace.name)
+            .collect();
+        Ok(self
+            .projects
+            .find_all()?
+            .into_iter()
+            .filter(|project| {
+                workspace
+                    .as_ref()
+                    .is_none_or(|workspace| workspace.name == project.workspace)
+            })
+            .filter(|project| {
+                archived || !(project.archived || archived_workspaces.contains(&project.workspace))
+            })
+            .collect())
+    }
+
+    /// The rates of all workspaces and projects, archived ones included.
+    pub fn rates(&self) -> Result<Rates> {
+        Ok(Rates::new(
+            &self.workspaces.find_all()?,
+            &self.projects.find_all()?,
+        ))
+    }
+
+    /// The project new tracks can be filed under, failing when it or its
+    /// workspace are unknown or archived.
+    pub fn active_project(&self, workspace: &str, name: &str) -> Result<Project> {
+        let found = self.find_workspace(workspace)?;
+        if found.archived {
+            return Err(Error::Archived(format!("workspace {}", found.name)));
+        }
+        let project = self.find_project(workspace, name)?;
+        if project.archived {
+            return Err(Error::Archived(format!("project {}", project.name)));
+        }
+        Ok(project)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::repository_sqlite::{RepositorySQLite, SCHEME};
-    use std::sync::Arc;
+    use crate::export::JsonExporter;
+    use crate::invoice::LineItems;
+    use crate::migrations;
+    use crate::repository_sqlite::{
+        InvoiceRepositorySQLite, ProjectRepositorySQLite, RepositorySQLite,
+        SettingsRepositorySQLite, WorkspaceRepositorySQLite,
+    };
+    use chrono::Duration;
+    use rust_decimal::Decimal;
+    use std::rc::Rc;
 
-    fn create_connection() -> sqlite::Connection {
+    fn open_database() -> sqlite::Connection {
         let connection = sqlite::open(":memory:").unwrap();
-        connection.execute(SCHEME).unwrap();
+        connection.execute("PRAGMA foreign_keys = ON").unwrap();
+        migrations::migrate(&connection).unwrap();
+        connection
+    }
+
+    /// A database with the projects the tracks of the tests are filed under.
+    fn create_connection() -> sqlite::Connection {
+        let connection = open_database();
+        connection
+            .execute(
+                "INSERT INTO workspaces (id, name)
+                VALUES ('w1', 'Workspace'), ('w2', 'Other'), ('w3', 'Workspace2');
+                INSERT INTO projects (id, workspace_id, name)
+                VALUES ('p1', 'w1', 'Project1'), ('p2', 'w1', 'Project2'), ('p3', 'w1', 'Project3'),
+                    ('p4', 'w2', 'Project1'), ('p5', 'w3', 'Project2');",
+            )
+            .unwrap();
         connection
     }
 
     fn create_repository(connection: sqlite::Connection) -> RepositorySQLite {
-        RepositorySQLite::create(Arc::new(connection).clone())
+        RepositorySQLite::create(Rc::new(connection))
+    }
+
+    fn create_track(id: &str, start: &str, end: Option<&str>) -> Track {
+        Track::create(
+            String::from(id),
+            String::from("MyTrack"),
+            start.parse::<DateTime<Utc>>().unwrap(),
+            end.map(|end| end.parse::<DateTime<Utc>>().unwrap()),
+            String::from("Project1"),
+            String::from("Workspace"),
+        )
     }
 
     #[test]
@@ -76,55 +1054,144 @@ mod tests {
                 String::from("Workspace"),
             ))
             .unwrap();
-        let mut service = TrackService::create(repository);
-        service.stop_current_track().unwrap();
-        let track = service.tracks.get(0).unwrap();
-        assert_eq!(track.is_tracking(), false);
+        let service = TrackService::create(repository);
+        let track = service.stop_current_track().unwrap();
+        assert!(!track.is_tracking());
+        let tracks = service.list().unwrap();
+        assert!(!tracks[0].is_tracking());
+    }
+
+    #[test]
+    fn test_stop_without_active_track() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        assert_eq!(service.stop_current_track(), Err(Error::NoActiveTrack));
+    }
+
+    #[test]
+    fn test_current() {
+        let service = create_service();
+        assert_eq!(service.current().unwrap(), None);
+        let track = service
+            .start_new_track_at(
+                String::from("MyTrack"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("05:00"),
+            )
+            .unwrap();
+        assert_eq!(service.current().unwrap(), Some(track));
+        service.stop_current_track_at(at("06:00")).unwrap();
+        assert_eq!(service.current().unwrap(), None);
+    }
+
+    #[test]
+    fn test_stop_running_track_started_before_others() {
+        let repository = Box::new(create_repository(create_connection()));
+        repository
+            .save(&create_track("running", "2022-01-01T01:00:00Z", None))
+            .unwrap();
+        repository
+            .save(&create_track(
+                "stopped",
+                "2022-01-01T02:00:00Z",
+                Some("2022-01-01T03:00:00Z"),
+            ))
+            .unwrap();
+        let service = TrackService::create(repository);
+        let track = service.stop_current_track().unwrap();
+        assert_eq!(track.id, String::from("running"));
+        assert_eq!(service.stop_current_track(), Err(Error::NoActiveTrack));
+    }
+
+    #[test]
+    fn test_new_tracks_keep_their_billing() {
+        let service = TrackService::create(Box::new(create_repository(create_connection())));
+        let billing = Billing {
+            rate: Some(Rate::new(Decimal::from(90), "EUR")),
+            billable: false,
+        };
+        let started = service
+            .start_new_track_at(
+                String::from("Started"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                billing.clone(),
+                at("01:00"),
+            )
+            .unwrap();
+        let added = service
+            .add_track(
+                String::from("Added"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                billing.clone(),
+                at("00:00"),
+                TrackEnd::At(at("00:30")),
+                Overlap::Reject,
+            )
+            .unwrap();
+        for track in [started, added] {
+            let stored = service.find_by_prefix(&track.id).unwrap();
+            assert_eq!(
+                (stored.rate, stored.billable),
+                (billing.rate.clone(), false)
+            );
+        }
     }
 
     #[test]
     fn test_start_new_track() {
         let repository = Box::new(create_repository(create_connection()));
-        let mut service = TrackService::create(repository);
-        service
+        let service = TrackService::create(repository);
+        let first = service
             .start_new_track(
                 String::from("MyTrack"),
                 String::from("Project1"),
                 String::from("Workspace"),
+                vec![],
+                Billing::default(),
             )
             .unwrap();
-        let track = service.tracks.get(0).unwrap();
-        assert_eq!(track.is_tracking(), true);
-        assert_eq!(service.stop_current_track(), Ok(()));
-        let track = service.tracks.get(0).unwrap();
-        assert_eq!(track.is_tracking(), false);
-        service
+        assert!(first.is_tracking());
+        assert_eq!(service.stop_current_track().unwrap().id, first.id);
+        let tracks = service.list().unwrap();
+        assert!(!tracks[0].is_tracking());
+        let second = service
             .start_new_track(
                 String::from("MyTrack2"),
                 String::from("Project2"),
                 String::from("Workspace2"),
+                vec![],
+                Billing::default(),
             )
             .unwrap();
-        let old_track = service.tracks.get(0).unwrap();
-        assert_eq!(old_track.is_tracking(), false);
-        let track = service.tracks.get(1).unwrap();
-        assert_eq!(track.is_tracking(), true);
+        let tracks = service.list().unwrap();
+        assert!(!tracks[0].is_tracking());
+        assert_eq!(tracks[1].id, second.id);
+        assert!(tracks[1].is_tracking());
         service.stop_current_track().unwrap();
-        let track = service.tracks.get(1).unwrap();
-        assert_eq!(track.is_tracking(), false);
+        let tracks = service.list().unwrap();
+        assert!(!tracks[1].is_tracking());
     }
 
     #[test]
     fn test_list() {
         let repository = Box::new(create_repository(create_connection()));
-        let mut service = TrackService::create(repository);
-        let list = service.list();
+        let service = TrackService::create(repository);
+        let list = service.list().unwrap();
         assert_eq!(list.len(), 0);
         service
             .start_new_track(
                 String::from("MyTrack"),
                 String::from("Project1"),
                 String::from("Workspace"),
+                vec![],
+                Billing::default(),
             )
             .unwrap();
         service
@@ -132,9 +1199,968 @@ mod tests {
                 String::from("MyTrack2"),
                 String::from("Project1"),
                 String::from("Workspace"),
+                vec![],
+                Billing::default(),
             )
             .unwrap();
-        let list = service.list();
+        let list = service.list().unwrap();
         assert_eq!(list.len(), 2);
     }
+
+    #[test]
+    fn test_list_by() {
+        let repository = Box::new(create_repository(create_connection()));
+        for (id, start) in [
+            ("a", "2022-01-01T01:00:00Z"),
+            ("b", "2022-01-01T02:00:00Z"),
+            ("c", "2022-01-01T03:00:00Z"),
+        ] {
+            repository
+                .save(&create_track(id, start, Some("2022-01-01T04:00:00Z")))
+                .unwrap();
+        }
+        let service = TrackService::create(repository);
+        let page = service
+            .list_by(&TrackQuery::new().limit(2).offset(1))
+            .unwrap();
+        let ids: Vec<&str> = page.iter().map(|track| track.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c"]);
+    }
+
+    fn create_service() -> TrackService {
+        let repository = Box::new(create_repository(create_connection()));
+        for (id, start) in [
+            ("a1", "2022-01-01T01:00:00Z"),
+            ("a2", "2022-01-01T02:00:00Z"),
+            ("b1", "2022-01-01T03:00:00Z"),
+        ] {
+            repository
+                .save(&create_track(id, start, Some("2022-01-01T04:00:00Z")))
+                .unwrap();
+        }
+        TrackService::create(repository)
+    }
+
+    #[test]
+    fn test_report() {
+        let service = create_service();
+        let query = TrackQuery::new()
+            .since(at("02:30"))
+            .until(at("03:30"))
+            .limit(1);
+        let report = service
+            .report(&query, &[GroupBy::Project], at("05:00"), false, None)
+            .unwrap();
+        assert_eq!(report.total, Duration::minutes(150));
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].key, "Project1");
+    }
+
+    #[test]
+    fn test_export() {
+        let service = create_service();
+        let mut output = vec![];
+        let count = service
+            .export(
+                &TrackQuery::new().id_prefix(String::from("a")),
+                &JsonExporter,
+                at("05:00"),
+                &mut output,
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+        let tracks: Vec<Track> = serde_json::from_slice(&output).unwrap();
+        assert_eq!(tracks, service.list().unwrap()[..2]);
+    }
+
+    #[test]
+    fn test_import() {
+        let service = create_service();
+        let stored = service.find_by_prefix("a1").unwrap();
+        let mut renamed = create_track("a2", "2022-01-01T05:00:00Z", Some("2022-01-01T06:00:00Z"));
+        renamed.name = String::from("Other");
+        let new = create_track("c1", "2022-01-01T07:00:00Z", Some("2022-01-01T08:00:00Z"));
+        let tracks = vec![stored.clone(), renamed.clone(), new.clone(), new.clone()];
+        let summary = service.import(tracks.clone(), true).unwrap();
+        assert_eq!(summary.imported.len(), 2);
+        assert_eq!(summary.duplicates, vec![stored, new.clone()]);
+        assert_eq!(service.list().unwrap().len(), 3);
+        let summary = service.import(tracks, false).unwrap();
+        assert_ne!(summary.imported[0].id, "a2");
+        assert_eq!(summary.imported[0].name, "Other");
+        assert_eq!(summary.imported[1], new);
+        assert_eq!(service.list().unwrap().len(), 5);
+        let mut unknown = create_track("d2", "2022-01-01T10:00:00Z", Some("2022-01-01T11:00:00Z"));
+        unknown.project = String::from("Projcet1");
+        let tracks = vec![
+            create_track("d1", "2022-01-01T09:00:00Z", Some("2022-01-01T10:00:00Z")),
+            unknown,
+        ];
+        assert_eq!(
+            service.import(tracks, false),
+            Err(Error::Import(String::from(
+                "no such workspace or project for Workspace/Projcet1, add them first or pass --workspace and --project"
+            )))
+        );
+        assert_eq!(service.list().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_find_by_prefix() {
+        let service = create_service();
+        assert_eq!(service.find_by_prefix("b").unwrap().id, "b1");
+        assert_eq!(
+            service.find_by_prefix("a"),
+            Err(Error::AmbiguousId(String::from("a")))
+        );
+        assert_eq!(
+            service.find_by_prefix("c"),
+            Err(Error::NotFound(String::from("c")))
+        );
+    }
+
+    #[test]
+    fn test_edit_track() {
+        let service = create_service();
+        let changes = TrackChanges {
+            name: Some(String::from("Renamed")),
+            project: Some(String::from("Project2")),
+            ..TrackChanges::default()
+        };
+        let track = service.edit_track("b", changes).unwrap();
+        assert_eq!(track.name, "Renamed");
+        assert_eq!(track.project, "Project2");
+        assert_eq!(track.workspace, "Workspace");
+        assert_eq!(service.find_by_prefix("b1").unwrap(), track);
+    }
+
+    #[test]
+    fn test_edit_track_rejects_end_before_start() {
+        let service = create_service();
+        let changes = TrackChanges {
+            start: Some("2022-01-01T05:00:00Z".parse::<DateTime<Utc>>().unwrap()),
+            ..TrackChanges::default()
+        };
+        assert_eq!(
+            service.edit_track("b1", changes),
+            Err(Error::InvalidInterval(
+                "2022-01-01T05:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+                "2022-01-01T04:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_delete_and_restore_track() {
+        let service = create_service();
+        service.delete_track("b").unwrap();
+        assert_eq!(service.list().unwrap().len(), 2);
+        assert_eq!(service.trash().unwrap()[0].id, "b1");
+        let track = service.restore_track("b").unwrap();
+        assert_eq!(track.id, "b1");
+        assert_eq!(service.list().unwrap().len(), 3);
+        assert!(service.trash().unwrap().is_empty());
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2022-01-01T{}:00Z", time)
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+    }
+
+    fn add(service: &TrackService, start: &str, end: &str, overlap: Overlap) -> Result<Track> {
+        service.add_track(
+            String::from("Added"),
+            String::from("Project1"),
+            String::from("Workspace"),
+            vec![],
+            Billing::default(),
+            at(start),
+            TrackEnd::At(at(end)),
+            overlap,
+        )
+    }
+
+    fn intervals(service: &TrackService) -> Vec<(String, DateTime<Utc>, Option<DateTime<Utc>>)> {
+        service
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|track| (track.name, track.start, track.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_add_track() {
+        let service = create_service();
+        let track = add(&service, "04:00", "04:30", Overlap::Reject).unwrap();
+        assert_eq!(track.start, at("04:00"));
+        assert_eq!(track.end, Some(at("04:30")));
+        assert_eq!(service.find_by_prefix(&track.id).unwrap(), track);
+        assert_eq!(
+            add(&service, "05:00", "05:00", Overlap::Reject),
+            Err(Error::InvalidInterval(at("05:00"), at("05:00")))
+        );
+        let after = |duration: Duration| {
+            service.add_track(
+                String::from("After"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("06:00"),
+                TrackEnd::After(duration),
+                Overlap::Reject,
+            )
+        };
+        assert_eq!(after(Duration::minutes(45)).unwrap().end, Some(at("06:45")));
+        assert!(matches!(
+            after(Duration::weeks(99_999_999)),
+            Err(Error::InvalidDuration(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_track_rejects_overlap() {
+        let service = create_service();
+        match add(&service, "03:30", "04:30", Overlap::Reject) {
+            Err(Error::Overlap(tracks)) => {
+                let ids: Vec<&str> = tracks.iter().map(|track| track.id.as_str()).collect();
+                assert_eq!(ids, vec!["a1", "a2", "b1"]);
+            }
+            result => panic!("It was expected an overlap, got {:?}", result),
+        }
+        assert_eq!(service.list().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_add_track_allows_overlap() {
+        let service = create_service();
+        add(&service, "03:30", "04:30", Overlap::Allow).unwrap();
+        assert_eq!(service.list().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_add_track_trims_overlap() {
+        let repository = Box::new(create_repository(create_connection()));
+        repository
+            .save(&create_track(
+                "head",
+                "2022-01-01T01:00:00Z",
+                Some("2022-01-01T02:30:00Z"),
+            ))
+            .unwrap();
+        repository
+            .save(&create_track(
+                "inside",
+                "2022-01-01T02:40:00Z",
+                Some("2022-01-01T02:50:00Z"),
+            ))
+            .unwrap();
+        repository
+            .save(&create_track(
+                "tail",
+                "2022-01-01T02:55:00Z",
+                Some("2022-01-01T04:00:00Z"),
+            ))
+            .unwrap();
+        repository
+            .save(&create_track("around", "2022-01-01T05:00:00Z", None))
+            .unwrap();
+        let service = TrackService::create(repository);
+        add(&service, "02:00", "03:00", Overlap::Trim).unwrap();
+        add(&service, "06:00", "07:00", Overlap::Trim).unwrap();
+        assert_eq!(
+            intervals(&service),
+            vec![
+                (String::from("MyTrack"), at("01:00"), Some(at("02:00"))),
+                (String::from("Added"), at("02:00"), Some(at("03:00"))),
+                (String::from("MyTrack"), at("03:00"), Some(at("04:00"))),
+                (String::from("MyTrack"), at("05:00"), Some(at("06:00"))),
+                (String::from("Added"), at("06:00"), Some(at("07:00"))),
+                (String::from("MyTrack"), at("07:00"), None),
+            ]
+        );
+        assert_eq!(service.trash().unwrap()[0].id, "inside");
+        assert_eq!(service.current().unwrap().unwrap().id, "around");
+        assert_eq!(
+            service.find_by_prefix("head").unwrap().end,
+            Some(at("02:00"))
+        );
+    }
+
+    #[test]
+    fn test_start_and_stop_track_at() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        let first = service
+            .start_new_track_at(
+                String::from("MyTrack"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("01:00"),
+            )
+            .unwrap();
+        assert_eq!(first.start, at("01:00"));
+        let second = service
+            .start_new_track_at(
+                String::from("MyTrack2"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("02:00"),
+            )
+            .unwrap();
+        assert_eq!(
+            service.find_by_prefix(&first.id).unwrap().end,
+            Some(at("02:00"))
+        );
+        assert_eq!(
+            service.stop_current_track_at(at("01:30")),
+            Err(Error::InvalidInterval(at("02:00"), at("01:30")))
+        );
+        let stopped = service.stop_current_track_at(at("02:45")).unwrap();
+        assert_eq!(stopped.id, second.id);
+        assert_eq!(stopped.end, Some(at("02:45")));
+    }
+
+    #[test]
+    fn test_pause_and_resume_current_track() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        assert_eq!(
+            service.pause_current_track_at(at("01:00")),
+            Err(Error::NoActiveTrack)
+        );
+        service
+            .start_new_track_at(
+                String::from("MyTrack"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("01:00"),
+            )
+            .unwrap();
+        assert!(service
+            .pause_current_track_at(at("02:00"))
+            .unwrap()
+            .is_paused());
+        let track = service.resume_current_track_at(at("02:30")).unwrap();
+        assert!(!track.is_paused());
+        let stopped = service.stop_current_track_at(at("04:00")).unwrap();
+        assert_eq!(
+            stopped.duration(),
+            Duration::hours(2) + Duration::minutes(30)
+        );
+        assert_eq!(service.list().unwrap()[0], stopped);
+    }
+
+    #[test]
+    fn test_stop_rejects_times_before_pause_or_in_future() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        service
+            .start_new_track_at(
+                String::from("MyTrack"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("01:00"),
+            )
+            .unwrap();
+        service.pause_current_track_at(at("03:00")).unwrap();
+        assert_eq!(
+            service.stop_current_track_at(at("02:00")),
+            Err(Error::InvalidInterval(at("03:00"), at("02:00")))
+        );
+        let future = Utc::now() + Duration::hours(1);
+        match service.stop_current_track_at(future) {
+            Err(Error::InvalidInterval(start, _)) => assert_eq!(start, future),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(service.current().unwrap().unwrap().is_paused());
+        let stopped = service.stop_current_track_at(at("03:30")).unwrap();
+        assert_eq!(stopped.duration(), Duration::hours(2));
+        assert_eq!(stopped.pauses[0].end, Some(at("03:30")));
+    }
+
+    fn create_history() -> TrackService {
+        let repository = Box::new(create_repository(create_connection()));
+        for (id, name, project, start, end) in [
+            (
+                "a1",
+                "Review pull requests",
+                "Project1",
+                "01:00",
+                Some("02:00"),
+            ),
+            (
+                "b1",
+                "Write documentation",
+                "Project2",
+                "02:00",
+                Some("03:00"),
+            ),
+            ("c1", "Deploy", "Project3", "03:00", None),
+        ] {
+            repository
+                .save(&Track::create(
+                    String::from(id),
+                    String::from(name),
+                    at(start),
+                    end.map(at),
+                    String::from(project),
+                    String::from("Workspace"),
+                ))
+                .unwrap();
+        }
+        TrackService::create(repository)
+    }
+
+    #[test]
+    fn test_continue_last_stopped_track() {
+        let service = create_history();
+        let track = service.continue_track_at(None, at("04:00")).unwrap();
+        assert_eq!(track.name, "Write documentation");
+        assert_eq!(track.project, "Project2");
+        assert_eq!(track.workspace, "Workspace");
+        assert_eq!(track.start, at("04:00"));
+        assert!(track.is_tracking());
+        assert_eq!(service.find_by_prefix("c1").unwrap().end, Some(at("04:00")));
+    }
+
+    fn create_services() -> (TrackService, ProjectService) {
+        create_services_on(&Rc::new(open_database()))
+    }
+
+    fn create_services_on(connection: &Rc<sqlite::Connection>) -> (TrackService, ProjectService) {
+        let projects = || {
+            ProjectService::create(
+                Box::new(WorkspaceRepositorySQLite::create(connection.clone())),
+                Box::new(ProjectRepositorySQLite::create(connection.clone())),
+                Box::new(SettingsRepositorySQLite::create(connection.clone())),
+            )
+        };
+        let tracks = TrackService::create(Box::new(RepositorySQLite::create(connection.clone())))
+            .with_projects(projects());
+        (tracks, projects())
+    }
+
+    #[test]
+    fn test_manage_projects() {
+        let (_, projects) = create_services();
+        projects.add_workspace(String::from("Acme"), None).unwrap();
+        assert_eq!(
+            projects
+                .add_workspace(String::from("ACME"), None)
+                .unwrap_err(),
+            Error::AlreadyExists(String::from("workspace ACME"))
+        );
+        let project = projects
+            .add_project(
+                String::from("Website"),
+                "acme",
+                Some(String::from("blue")),
+                Some(String::from("Acme Inc")),
+            )
+            .unwrap();
+        assert_eq!(project.workspace, "Acme");
+        assert_eq!(projects.find_project("ACME", "website").unwrap(), project);
+        assert_eq!(
+            projects
+                .add_project(String::from("Website"), "Other", None, None)
+                .unwrap_err(),
+            Error::UnknownWorkspace(String::from("Other"))
+        );
+        projects
+            .add_project(String::from("Mobile"), "Acme", None, None)
+            .unwrap();
+        assert_eq!(
+            projects
+                .rename_project("Acme", "Mobile", String::from("website"))
+                .unwrap_err(),
+            Error::AlreadyExists(String::from("project website in workspace Acme"))
+        );
+        projects
+            .rename_project("Acme", "Mobile", String::from("App"))
+            .unwrap();
+        projects.archive_project("Acme", "Website", true).unwrap();
+        let names = |projects: Vec<Project>| -> Vec<String> {
+            projects.into_iter().map(|project| project.name).collect()
+        };
+        assert_eq!(names(projects.projects(None, false).unwrap()), vec!["App"]);
+        assert_eq!(
+            names(projects.projects(Some("Acme"), true).unwrap()),
+            vec!["App", "Website"]
+        );
+        projects.archive_workspace("Acme", true).unwrap();
+        assert!(projects.projects(None, false).unwrap().is_empty());
+        assert!(projects.workspaces(false).unwrap().is_empty());
+        let workspace = projects
+            .rename_workspace("Acme", String::from("Acme Corp"))
+            .unwrap();
+        assert_eq!(projects.workspaces(true).unwrap(), vec![workspace]);
+    }
+
+    #[test]
+    fn test_context() {
+        let (_, projects) = create_services();
+        assert_eq!(projects.context().unwrap(), Context::default());
+        projects.add_workspace(String::from("Acme"), None).unwrap();
+        projects
+            .add_project(String::from("Website"), "Acme", None, None)
+            .unwrap();
+        assert_eq!(
+            projects.use_context("acme", Some("Mobile")).unwrap_err(),
+            Error::UnknownProject(String::from("Mobile"), String::from("Acme"))
+        );
+        projects.use_context("acme", Some("website")).unwrap();
+        projects
+            .rename_project("Acme", "Website", String::from("Site"))
+            .unwrap();
+        let context = projects.context().unwrap();
+        assert_eq!(
+            context,
+            Context {
+                workspace: Some(String::from("Acme")),
+                project: Some(String::from("Site")),
+            }
+        );
+        assert_eq!(
+            context.resolve(None, None).unwrap(),
+            (String::from("Site"), String::from("Acme"))
+        );
+        assert_eq!(
+            context.resolve(Some(String::from("Mobile")), None).unwrap(),
+            (String::from("Mobile"), String::from("Acme"))
+        );
+        assert_eq!(
+            context
+                .resolve(None, Some(String::from("Other")))
+                .unwrap_err(),
+            Error::NoContext(String::from("project"))
+        );
+        let query = context.filter(TrackQuery::new().project(String::from("Mobile")));
+        assert_eq!(
+            (query.project, query.workspace),
+            (Some(String::from("Mobile")), Some(String::from("Acme")))
+        );
+        let query = context.filter(TrackQuery::new().workspace(String::from("Other")));
+        assert_eq!(
+            (query.project, query.workspace),
+            (None, Some(String::from("Other")))
+        );
+        projects.use_context("Acme", None).unwrap();
+        assert_eq!(projects.context().unwrap().project, None);
+        projects.clear_context().unwrap();
+        assert_eq!(
+            projects.context().unwrap().resolve(None, None).unwrap_err(),
+            Error::NoContext(String::from("workspace"))
+        );
+    }
+
+    #[test]
+    fn test_tracks_need_active_project() {
+        let (service, projects) = create_services();
+        projects
+            .add_workspace(String::from("Workspace"), None)
+            .unwrap();
+        projects
+            .add_project(String::from("Project1"), "Workspace", None, None)
+            .unwrap();
+        assert_eq!(
+            service
+                .start_new_track_at(
+                    String::from("Review"),
+                    String::from("Projcet1"),
+                    String::from("Workspace"),
+                    vec![],
+                    Billing::default(),
+                    at("01:00"),
+                )
+                .unwrap_err(),
+            Error::UnknownProject(String::from("Projcet1"), String::from("Workspace"))
+        );
+        let track = service
+            .start_new_track_at(
+                String::from("Review"),
+                String::from("project1"),
+                String::from("WORKSPACE"),
+                vec![],
+                Billing::default(),
+                at("01:00"),
+            )
+            .unwrap();
+        assert_eq!(track.project, "Project1");
+        assert_eq!(track.workspace, "Workspace");
+        projects
+            .rename_project("Workspace", "Project1", String::from("Renamed"))
+            .unwrap();
+        assert_eq!(service.current().unwrap().unwrap().project, "Renamed");
+        projects
+            .archive_project("Workspace", "Renamed", true)
+            .unwrap();
+        assert_eq!(
+            service
+                .add_track(
+                    String::from("Review"),
+                    String::from("Renamed"),
+                    String::from("Workspace"),
+                    vec![],
+                    Billing::default(),
+                    at("00:00"),
+                    TrackEnd::At(at("00:30")),
+                    Overlap::Reject,
+                )
+                .unwrap_err(),
+            Error::Archived(String::from("project Renamed"))
+        );
+        let imported = |project: &str| {
+            Track::new_track(
+                String::from("Imported"),
+                String::from(project),
+                String::from("workspace"),
+                at("02:00"),
+                Some(at("02:30")),
+            )
+        };
+        let mut elsewhere = imported("Project1");
+        elsewhere.workspace = String::from("Nowhere");
+        let summary = service
+            .import(
+                vec![imported("Projcet1"), elsewhere, imported("Projcet1")],
+                true,
+            )
+            .unwrap();
+        assert!(summary.imported.is_empty());
+        assert_eq!(
+            summary.unknown_projects,
+            vec![
+                (String::from("Nowhere"), String::from("Project1")),
+                (String::from("workspace"), String::from("Projcet1")),
+            ]
+        );
+        assert!(matches!(
+            service.import(vec![imported("Projcet1")], false),
+            Err(Error::Import(_))
+        ));
+        for dry_run in [true, false] {
+            assert_eq!(
+                service
+                    .import(vec![imported("Renamed")], dry_run)
+                    .unwrap_err(),
+                Error::Archived(String::from("project Renamed"))
+            );
+        }
+        let changes = TrackChanges {
+            name: Some(String::from("Reviewed")),
+            ..TrackChanges::default()
+        };
+        assert_eq!(
+            service.edit_track(&track.id, changes).unwrap().project,
+            "Renamed"
+        );
+        assert!(service.current().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_tags() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        let track = service
+            .start_new_track_at(
+                String::from("Review"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![String::from("Billable"), String::from("review")],
+                Billing::default(),
+                at("01:00"),
+            )
+            .unwrap();
+        assert_eq!(track.tags, vec!["billable", "review"]);
+        service.stop_current_track_at(at("02:00")).unwrap();
+        let continued = service.continue_track_at(None, at("03:00")).unwrap();
+        assert_eq!(continued.tags, track.tags);
+        let changes = TrackChanges {
+            tags: Some(vec![String::from("meeting")]),
+            ..TrackChanges::default()
+        };
+        let edited = service.edit_track(&track.id, changes).unwrap();
+        assert_eq!(edited.tags, vec!["meeting"]);
+        assert_eq!(service.find_by_prefix(&track.id).unwrap(), edited);
+    }
+
+    #[test]
+    fn test_append_note() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        assert_eq!(
+            service.append_note("too early", at("00:30")).unwrap_err(),
+            Error::NoActiveTrack
+        );
+        let track = service
+            .start_new_track_at(
+                String::from("Review"),
+                String::from("Project1"),
+                String::from("Workspace"),
+                vec![],
+                Billing::default(),
+                at("01:00"),
+            )
+            .unwrap();
+        service
+            .append_note("fixed the flaky test", at("01:15"))
+            .unwrap();
+        let noted = service.append_note("opened a PR", at("01:45")).unwrap();
+        assert_eq!(
+            noted.notes,
+            "[01:15] fixed the flaky test\n[01:45] opened a PR\n"
+        );
+        let changes = TrackChanges {
+            notes: Some(String::from("rewritten")),
+            ..TrackChanges::default()
+        };
+        let edited = service.edit_track(&track.id, changes).unwrap();
+        assert_eq!(
+            service.find_by_prefix(&track.id).unwrap().notes,
+            "rewritten"
+        );
+        assert_eq!(edited.notes, "rewritten");
+    }
+
+    #[test]
+    fn test_continue_track_by_reference() {
+        let service = create_history();
+        let track = service.continue_track_at(Some("a1"), at("04:00")).unwrap();
+        assert_eq!(track.name, "Review pull requests");
+        let track = service
+            .continue_track_at(Some("DOCUM"), at("05:00"))
+            .unwrap();
+        assert_eq!(track.name, "Write documentation");
+        let track = service
+            .continue_track_at(Some("rvw pr"), at("06:00"))
+            .unwrap();
+        assert_eq!(track.name, "Review pull requests");
+        assert_eq!(
+            service.continue_track_at(Some("xyz"), at("07:00")),
+            Err(Error::NotFound(String::from("xyz")))
+        );
+    }
+
+    #[test]
+    fn test_continue_prefers_exact_names() {
+        let repository = Box::new(create_repository(create_connection()));
+        for (id, name, start, end) in [
+            ("a1", "Retro", "01:00", "02:00"),
+            ("b1", "retro2", "02:00", "03:00"),
+            ("beef", "Review", "03:00", "04:00"),
+            ("c1", "Beef", "04:00", "05:00"),
+            ("d1", "beef stew", "05:00", "06:00"),
+        ] {
+            repository
+                .save(&Track::create(
+                    String::from(id),
+                    String::from(name),
+                    at(start),
+                    Some(at(end)),
+                    String::from("Project1"),
+                    String::from("Workspace"),
+                ))
+                .unwrap();
+        }
+        let service = TrackService::create(repository);
+        let track = service
+            .continue_track_at(Some("retro"), at("07:00"))
+            .unwrap();
+        assert_eq!(track.name, "Retro");
+        let track = service
+            .continue_track_at(Some("BEEF"), at("08:00"))
+            .unwrap();
+        assert_eq!(track.name, "Beef");
+        let track = service.continue_track_at(Some("b1"), at("09:00")).unwrap();
+        assert_eq!(track.name, "retro2");
+        let track = service
+            .continue_track_at(Some("stew"), at("10:00"))
+            .unwrap();
+        assert_eq!(track.name, "beef stew");
+    }
+
+    #[test]
+    fn test_continue_without_history() {
+        let repository = Box::new(create_repository(create_connection()));
+        let service = TrackService::create(repository);
+        assert!(matches!(
+            service.continue_track_at(None, at("01:00")),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_report_amounts() {
+        let (service, projects) = create_services();
+        projects
+            .add_workspace(String::from("Workspace"), None)
+            .unwrap();
+        for project in ["Project1", "Project2"] {
+            projects
+                .add_project(String::from(project), "Workspace", None, None)
+                .unwrap();
+        }
+        projects
+            .set_workspace_rate("workspace", Some(Rate::new(Decimal::from(60), "EUR")))
+            .unwrap();
+        projects
+            .set_project_rate(
+                "Workspace",
+                "project2",
+                Some(Rate::new(Decimal::from(90), "EUR")),
+            )
+            .unwrap();
+        for (name, project, start, end) in [
+            ("Review", "Project1", "01:00", "02:00"),
+            ("Write", "Project2", "02:00", "02:30"),
+            ("Meeting", "Project2", "03:00", "04:00"),
+        ] {
+            service
+                .add_track(
+                    String::from(name),
+                    String::from(project),
+                    String::from("Workspace"),
+                    vec![],
+                    Billing::default(),
+                    at(start),
+                    TrackEnd::At(at(end)),
+                    Overlap::Reject,
+                )
+                .unwrap();
+        }
+        let meeting = service
+            .list_by(&TrackQuery::new().name(String::from("Meeting")))
+            .unwrap()
+            .remove(0);
+        service
+            .edit_track(
+                &meeting.id,
+                TrackChanges {
+                    billable: Some(false),
+                    ..TrackChanges::default()
+                },
+            )
+            .unwrap();
+        let query = TrackQuery::new();
+        let report = service
+            .report(&query, &[GroupBy::Project], at("05:00"), true, None)
+            .unwrap();
+        let amounts: Vec<Decimal> = report.amounts.iter().map(|money| money.amount).collect();
+        assert_eq!(amounts, vec![Decimal::from(105)]);
+        assert_eq!(report.groups[1].amounts[0].amount, Decimal::from(45));
+        let report = service
+            .report(&query, &[GroupBy::Project], at("05:00"), false, None)
+            .unwrap();
+        assert!(report.amounts.is_empty());
+    }
+
+    #[test]
+    fn test_invoice_marks_tracks() {
+        let connection = Rc::new(open_database());
+        let (tracks, projects) = create_services_on(&connection);
+        let service = InvoiceService::create(
+            tracks,
+            Box::new(InvoiceRepositorySQLite::create(connection.clone())),
+        );
+        projects.add_workspace(String::from("Acme"), None).unwrap();
+        projects
+            .add_project(
+                String::from("Website"),
+                "Acme",
+                None,
+                Some(String::from("Acme Inc")),
+            )
+            .unwrap();
+        projects
+            .set_workspace_rate("Acme", Some(Rate::new(Decimal::from(60), "EUR")))
+            .unwrap();
+        for (start, end) in [("01:00", "02:00"), ("03:00", "03:30")] {
+            service
+                .tracks
+                .add_track(
+                    String::from("Review"),
+                    String::from("Website"),
+                    String::from("Acme"),
+                    vec![],
+                    Billing::default(),
+                    at(start),
+                    TrackEnd::At(at(end)),
+                    Overlap::Reject,
+                )
+                .unwrap();
+        }
+        let request = InvoiceRequest {
+            client: String::from("acme inc"),
+            since: at("00:00"),
+            until: at("23:00"),
+            items: LineItems::Tracks,
+            tax: None,
+            number: None,
+            rounding: None,
+        };
+        let now = "2022-02-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let invoice = service.invoice(&request, now, true).unwrap();
+        assert_eq!(invoice.number, "2022-0001");
+        assert_eq!(invoice.client, "Acme Inc");
+        assert_eq!(invoice.totals[0].to_string(), "90.00 EUR");
+        let invoice = service.invoice(&request, now, false).unwrap();
+        assert_eq!(invoice.number, "2022-0001");
+        let invoiced = service
+            .tracks
+            .list_by(&TrackQuery::new().invoiced(true))
+            .unwrap();
+        assert_eq!(invoiced.len(), 2);
+        assert_eq!(invoiced[0].invoice, Some(String::from("2022-0001")));
+        assert_eq!(
+            service.invoice(&request, now, false).unwrap_err(),
+            Error::NothingToInvoice(String::from("Acme Inc"))
+        );
+        let request = InvoiceRequest {
+            number: Some(String::from("2022-0001")),
+            ..request
+        };
+        assert_eq!(
+            service.invoice(&request, now, true).unwrap_err(),
+            Error::AlreadyExists(String::from("invoice 2022-0001"))
+        );
+    }
+
+    #[test]
+    fn test_failed_transaction_is_rolled_back() {
+        let service = create_service();
+        let result: Result<()> = service.in_transaction(|| {
+            service.repository.delete(String::from("a1"))?;
+            service.in_transaction(|| {
+                service
+                    .repository
+                    .save(&create_track("c1", "2022-01-01T05:00:00Z", None))
+            })?;
+            Err(Error::NoActiveTrack)
+        });
+        assert_eq!(result, Err(Error::NoActiveTrack));
+        assert_eq!(service.list().unwrap().len(), 3);
+        assert!(service.find_by_prefix("c1").is_err());
+    }
+
+    #[test]
+    fn test_next_invoice_number() {
+        let numbers = vec![
+            String::from("2021-0007"),
+            String::from("2022-0002"),
+            String::from("special"),
+        ];
+        assert_eq!(next_number(&numbers, 2022), "2022-0003");
+        assert_eq!(next_number(&numbers, 2023), "2023-0001");
+    }
 }
@@ -0,0 +1,234 @@
+use crate::error::{Error, Result};
+use serde_json::Value;
+
+/// Fills a template written in a subset of Mustache: `{{name}}` inserts an
+/// escaped value, `{{{name}}}` a raw one, `{{#name}}...{{/name}}` repeats its
+/// content for each item of a list or shows it once for other non-empty
+/// values, and `{{^name}}...{{/name}}` shows its content for empty ones.
+/// `{{.}}` is the current item. Names are looked up in the current item, then
+/// in the enclosing ones. Lines holding only a section tag are dropped.
+pub fn render(template: &str, context: &Value, escape: fn(&str) -> String) -> Result<String> {
+    let template = strip_standalone_tags(template);
+    let mut output = String::new();
+    render_part(&template, &mut vec![context], escape, &mut output)?;
+    Ok(output)
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Escapes the characters that would break a Markdown table or emphasis.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for character in text.chars() {
+        if "\\|*_`[]<>".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+pub fn escape_nothing(text: &str) -> String {
+    String::from(text)
+}
+
+fn strip_standalone_tags(template: &str) -> String {
+    template
+        .split_inclusive('\n')
+        .map(|line| {
+            let trimmed = line.trim();
+            let standalone = trimmed.len() > 5
+                && (trimmed.starts_with("{{#")
+                    || trimmed.starts_with("{{^")
+                    || trimmed.starts_with("{{/"))
+                && trimmed.ends_with("}}")
+                && trimmed[2..].find("{{").is_none();
+            if standalone {
+                trimmed
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+fn render_part<'a>(
+    template: &str,
+    stack: &mut Vec<&'a Value>,
+    escape: fn(&str) -> String,
+    output: &mut String,
+) -> Result<()> {
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        output.push_str(&rest[..open]);
+        rest = &rest[open..];
+        if let Some(tag) = rest.strip_prefix("{{{") {
+            let close = tag
+                .find("}}}")
+                .ok_or_else(|| unclosed(&rest[..rest.len().min(20)]))?;
+            output.push_str(&text(lookup(stack, tag[..close].trim())));
+            rest = &tag[close + 3..];
+            continue;
+        }
+        let close = rest
+            .find("}}")
+            .ok_or_else(|| unclosed(&rest[..rest.len().min(20)]))?;
+        let tag = rest[2..close].trim();
+        rest = &rest[close + 2..];
+        match tag.chars().next() {
+            Some(kind @ ('#' | '^')) => {
+                let name = tag[1..].trim();
+                let (inner, after) = section(rest, name)?;
+                rest = after;
+                let value = lookup(stack, name);
+                match (kind, value) {
+                    ('#', Value::Array(items)) => {
+                        for item in items.iter() {
+                            stack.push(item);
+                            render_part(inner, stack, escape, output)?;
+                            stack.pop();
+                        }
+                    }
+                    ('#', value) if !is_empty(value) => {
+                        stack.push(value);
+                        render_part(inner, stack, escape, output)?;
+                        stack.pop();
+                    }
+                    ('^', value) if is_empty(value) => render_part(inner, stack, escape, output)?,
+                    _ => {}
+                }
+            }
+            Some('/') => {
+                return Err(Error::Template(format!(
+                    "{{{{{}}}}} closes no section",
+                    tag
+                )))
+            }
+            Some('!') => {}
+            _ => output.push_str(&escape(&text(lookup(stack, tag)))),
+        }
+    }
+    output.push_str(rest);
+    Ok(())
+}
+
+/// Splits the content of the section `name` from what follows its closing
+/// tag, skipping nested sections of the same name.
+fn section<'t>(template: &'t str, name: &str) -> Result<(&'t str, &'t str)> {
+    let mut depth = 0;
+    let mut position = 0;
+    while let Some(open) = template[position..].find("{{") {
+        let start = position + open;
+        let close = template[start..]
+            .find("}}")
+            .map(|close| start + close)
+            .ok_or_else(|| unclosed(name))?;
+        let tag = template[start + 2..close].trim();
+        if tag.len() > 1 && tag[1..].trim() == name {
+            match tag.chars().next() {
+                Some('#' | '^') => depth += 1,
+                Some('/') if depth == 0 => return Ok((&template[..start], &template[close + 2..])),
+                Some('/') => depth -= 1,
+                _ => {}
+            }
+        }
+        position = close + 2;
+    }
+    Err(Error::Template(format!("section {} is not closed", name)))
+}
+
+fn unclosed(tag: &str) -> Error {
+    Error::Template(format!("tag {} is not closed", tag))
+}
+
+fn lookup<'a>(stack: &[&'a Value], name: &str) -> &'a Value {
+    if name == "." {
+        return stack.last().copied().unwrap_or(&Value::Null);
+    }
+    stack
+        .iter()
+        .rev()
+        .find_map(|value| value.get(name))
+        .unwrap_or(&Value::Null)
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(value) => !value,
+        Value::String(value) => value.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null | Value::Bool(false) => String::new(),
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_sections() {
+        let context = json!({
+            "client": "Acme & Co",
+            "lines": [{"name": "Review"}, {"name": "<Docs>"}],
+            "totals": ["90.00 EUR", "10.00 USD"],
+            "tax": null,
+        });
+        let template = "Client: {{client}} {{{client}}}\n\
+                        {{#lines}}\n\
+                        - {{name}} for {{client}}\n\
+                        {{/lines}}\n\
+                        {{#totals}}{{.}};{{/totals}}\n\
+                        {{#tax}}\n\
+                        Tax\n\
+                        {{/tax}}\n\
+                        {{^tax}}\n\
+                        No tax\n\
+                        {{/tax}}\n";
+        assert_eq!(
+            render(template, &context, escape_html).unwrap(),
+            "Client: Acme &amp; Co Acme & Co\n\
+             - Review for Acme &amp; Co\n\
+             - &lt;Docs&gt; for Acme &amp; Co\n\
+             90.00 EUR;10.00 USD;\n\
+             No tax\n"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let context = json!({"lines": []});
+        assert!(matches!(
+            render("{{#lines}} never closed", &context, escape_nothing),
+            Err(Error::Template(_))
+        ));
+        assert!(matches!(
+            render("{{/lines}}", &context, escape_nothing),
+            Err(Error::Template(_))
+        ));
+        assert!(matches!(
+            render("{{client", &context, escape_nothing),
+            Err(Error::Template(_))
+        ));
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(escape_markdown("a|b *c*"), "a\\|b \\*c\\*");
+    }
+}